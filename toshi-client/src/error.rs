@@ -19,4 +19,7 @@ pub enum ToshiClientError {
 
     #[error("IO Error: {0}")]
     UriError(#[from] InvalidUri),
+
+    #[error("No index was given and the client has no default index")]
+    NoDefaultIndex,
 }
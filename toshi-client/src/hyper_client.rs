@@ -19,6 +19,7 @@ where
 {
     host: String,
     client: Client<C, Body>,
+    default_index: Option<String>,
 }
 
 impl HyperToshi<HttpConnector> {
@@ -29,7 +30,7 @@ impl HyperToshi<HttpConnector> {
 }
 
 #[cfg(feature = "rust_tls")]
-#[cfg(not(feature = "hyper_tls"))]
+#[cfg(not(feature = "tls"))]
 impl HyperToshi<hyper_rustls::HttpsConnector<HttpConnector>> {
     pub fn with_tls<H: ToString>(host: H, connector: hyper_rustls::HttpsConnector<HttpConnector>) -> Self {
         let client = Client::builder().build(connector);
@@ -37,7 +38,7 @@ impl HyperToshi<hyper_rustls::HttpsConnector<HttpConnector>> {
     }
 }
 
-#[cfg(feature = "hyper_tls")]
+#[cfg(feature = "tls")]
 #[cfg(not(feature = "rust_tls"))]
 impl HyperToshi<hyper_tls::HttpsConnector<HttpConnector>> {
     pub fn with_tls<H: ToString>(host: H, connector: hyper_tls::HttpsConnector<HttpConnector>) -> Self {
@@ -54,9 +55,16 @@ where
         Self {
             host: host.to_string(),
             client,
+            default_index: None,
         }
     }
 
+    /// Use `index` for the `*_default` methods that don't take an index
+    pub fn with_default_index<I: ToString>(mut self, index: I) -> Self {
+        self.default_index = Some(index.to_string());
+        self
+    }
+
     #[inline]
     fn uri<I>(&self, index: I) -> String
     where
@@ -83,6 +91,10 @@ where
 {
     type Body = hyper::Body;
 
+    fn default_index(&self) -> Option<&str> {
+        self.default_index.as_deref()
+    }
+
     async fn index(&self) -> Result<Response<Body>> {
         let request = Request::get(&self.host).body(Body::empty())?;
         self.client.request(request).await.map_err(Into::into)
@@ -8,6 +8,7 @@ use async_trait::async_trait;
 use toshi_types::*;
 
 use crate::{AsyncClient, Result, SyncClient};
+use http::{Method, Request};
 use isahc::{HttpClient, Response};
 use std::fmt::Display;
 
@@ -15,6 +16,8 @@ use std::fmt::Display;
 pub struct ToshiClient {
     host: String,
     client: HttpClient,
+    proxy: Option<http::Uri>,
+    default_index: Option<String>,
 }
 
 impl ToshiClient {
@@ -27,9 +30,24 @@ impl ToshiClient {
         Self {
             host: host.to_string(),
             client,
+            proxy: None,
+            default_index: None,
         }
     }
 
+    /// Send every request through the HTTP proxy at `proxy`, the proxy is set on each request so
+    /// a client given to [`ToshiClient::with_client`] keeps the rest of its configuration
+    pub fn with_proxy(mut self, proxy: http::Uri) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Use `index` for the `*_default` methods that don't take an index
+    pub fn with_default_index<I: ToString>(mut self, index: I) -> Self {
+        self.default_index = Some(index.to_string());
+        self
+    }
+
     #[inline]
     fn uri<I>(&self, index: I) -> String
     where
@@ -37,19 +55,37 @@ impl ToshiClient {
     {
         format!("{}/{}", self.host, index.to_string())
     }
+
+    fn request<B>(&self, method: Method, uri: String, body: B) -> Result<Request<B>> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Some(proxy.clone()));
+        }
+        builder.body(body).map_err(Into::into)
+    }
 }
 
 #[async_trait]
 impl AsyncClient for ToshiClient {
     type Body = isahc::AsyncBody;
 
+    fn default_index(&self) -> Option<&str> {
+        self.default_index.as_deref()
+    }
+
     async fn index(&self) -> Result<Response<Self::Body>> {
-        self.client.get_async(&self.host).await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::GET, self.host.clone(), ())?)
+            .await
+            .map_err(Into::into)
     }
 
     async fn list(&self) -> Result<Response<Self::Body>> {
         let uri = self.uri("");
-        self.client.get_async(uri).await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::GET, uri, ())?)
+            .await
+            .map_err(Into::into)
     }
 
     async fn index_summary<I>(&self, index: I, include_sizes: bool) -> Result<Response<Self::Body>>
@@ -57,7 +93,10 @@ impl AsyncClient for ToshiClient {
         I: ToString + Send + Sync + Display,
     {
         let uri = self.uri(format!("{}/_summary?include_sizes={}", index, include_sizes));
-        self.client.get_async(uri).await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::GET, uri, ())?)
+            .await
+            .map_err(Into::into)
     }
 
     async fn create_index<I>(&self, name: I, schema: Schema) -> Result<Response<Self::Body>>
@@ -66,7 +105,10 @@ impl AsyncClient for ToshiClient {
     {
         let uri = self.uri(format!("{}/_create", name));
         let body = serde_json::to_vec(&SchemaBody(schema))?;
-        self.client.put_async(uri, body).await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::PUT, uri, body)?)
+            .await
+            .map_err(Into::into)
     }
 
     async fn add_document<I, D>(&self, index: I, document: D, options: Option<IndexOptions>) -> Result<Response<AsyncBody>>
@@ -76,7 +118,10 @@ impl AsyncClient for ToshiClient {
     {
         let uri = self.uri(index);
         let body = serde_json::to_vec(&AddDocument { options, document })?;
-        self.client.put_async(uri, body).await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::PUT, uri, body)?)
+            .await
+            .map_err(Into::into)
     }
 
     async fn search<I, D>(&self, index: I, search: Search) -> Result<SearchResults<D>>
@@ -86,7 +131,12 @@ impl AsyncClient for ToshiClient {
     {
         let uri = self.uri(index);
         let body = serde_json::to_vec(&search)?;
-        self.client.post_async(uri, body).await?.json().await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::POST, uri, body)?)
+            .await?
+            .json()
+            .await
+            .map_err(Into::into)
     }
 
     async fn all_docs<I, D>(&self, index: I) -> Result<SearchResults<D>>
@@ -95,15 +145,26 @@ impl AsyncClient for ToshiClient {
         D: DeserializeOwned + Clone + Send + Sync + Unpin,
     {
         let uri = self.uri(index);
-        self.client.get_async(uri).await?.json().await.map_err(Into::into)
+        self.client
+            .send_async(self.request(Method::GET, uri, ())?)
+            .await?
+            .json()
+            .await
+            .map_err(Into::into)
     }
 }
 
 impl SyncClient for ToshiClient {
     type Body = isahc::Body;
 
+    fn sync_default_index(&self) -> Option<&str> {
+        self.default_index.as_deref()
+    }
+
     fn sync_index(&self) -> Result<Response<Self::Body>> {
-        self.client.get(self.host.clone()).map_err(Into::into)
+        self.client
+            .send(self.request(Method::GET, self.host.clone(), ())?)
+            .map_err(Into::into)
     }
 
     fn sync_index_summary<I>(&self, index: I, include_sizes: bool) -> Result<Response<Self::Body>>
@@ -111,7 +172,7 @@ impl SyncClient for ToshiClient {
         I: ToString + Display,
     {
         let uri = self.uri(format!("{}/_summary?include_sizes={}", index, include_sizes));
-        self.client.get(uri).map_err(Into::into)
+        self.client.send(self.request(Method::GET, uri, ())?).map_err(Into::into)
     }
 
     fn sync_create_index<I>(&self, name: I, schema: Schema) -> Result<Response<Self::Body>>
@@ -120,7 +181,7 @@ impl SyncClient for ToshiClient {
     {
         let uri = self.uri(format!("{}/_create", name));
         let body = serde_json::to_vec(&SchemaBody(schema))?;
-        self.client.put(uri, body).map_err(Into::into)
+        self.client.send(self.request(Method::PUT, uri, body)?).map_err(Into::into)
     }
 
     fn sync_add_document<I, D>(&self, index: I, document: D, options: Option<IndexOptions>) -> Result<Response<Self::Body>>
@@ -130,7 +191,7 @@ impl SyncClient for ToshiClient {
     {
         let uri = self.uri(index);
         let body = serde_json::to_vec(&AddDocument { options, document })?;
-        self.client.put(uri, body).map_err(Into::into)
+        self.client.send(self.request(Method::PUT, uri, body)?).map_err(Into::into)
     }
 
     fn sync_search<I, D>(&self, index: I, search: Search) -> Result<SearchResults<D>>
@@ -140,7 +201,7 @@ impl SyncClient for ToshiClient {
     {
         let uri = self.uri(index);
         let body = serde_json::to_vec(&search)?;
-        self.client.post(uri, body)?.json().map_err(Into::into)
+        self.client.send(self.request(Method::POST, uri, body)?)?.json().map_err(Into::into)
     }
 
     fn sync_all_docs<I, D>(&self, index: I) -> Result<SearchResults<D>>
@@ -149,6 +210,79 @@ impl SyncClient for ToshiClient {
         D: DeserializeOwned + Clone,
     {
         let uri = self.uri(index);
-        self.client.get(uri)?.json().map_err(Into::into)
+        self.client.send(self.request(Method::GET, uri, ())?)?.json().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use serde_json::Value;
+
+    use crate::ToshiClientError;
+
+    use super::*;
+
+    /// Answers a single request with empty search results, returning the request line it got
+    fn stub_server(listener: TcpListener) -> std::thread::JoinHandle<String> {
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            reader.read_exact(&mut vec![0; content_length]).unwrap();
+            let body = r#"{"hits":0,"docs":[],"facets":[]}"#;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request_line
+        })
+    }
+
+    #[test]
+    fn test_default_index() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let host = format!("http://{}", listener.local_addr()?);
+        let server = stub_server(listener);
+
+        let client = ToshiClient::new(host).with_default_index("wiki");
+        let results: SearchResults<Value> = client.sync_search_default(Search::all_docs())?;
+        assert_eq!(results.hits, 0);
+        assert!(server.join().unwrap().starts_with("POST /wiki HTTP/1.1"));
+
+        let client = ToshiClient::new("http://localhost:8080");
+        let err = client.sync_search_default::<Value>(Search::all_docs()).unwrap_err();
+        assert!(matches!(err, ToshiClientError::NoDefaultIndex));
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let proxy: http::Uri = format!("http://{}", listener.local_addr()?).parse()?;
+        let proxy_server = stub_server(listener);
+
+        let client = ToshiClient::with_client("http://toshi.invalid:8080", HttpClient::new()?).with_proxy(proxy);
+        let results: SearchResults<Value> = client.sync_search("wiki", Search::all_docs())?;
+        assert_eq!(results.hits, 0);
+        assert!(proxy_server
+            .join()
+            .unwrap()
+            .starts_with("POST http://toshi.invalid:8080/wiki HTTP/1.1"));
+        Ok(())
     }
 }
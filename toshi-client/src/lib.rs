@@ -28,6 +28,9 @@ pub type Result<T> = std::result::Result<T, ToshiClientError>;
 pub trait AsyncClient {
     type Body;
 
+    /// The index used by the `*_default` methods, if the client was given one
+    fn default_index(&self) -> Option<&str>;
+
     async fn index(&self) -> Result<Response<Self::Body>>;
 
     async fn list(&self) -> Result<Response<Self::Body>>;
@@ -54,11 +57,41 @@ pub trait AsyncClient {
     where
         I: ToString + Send + Sync + Display,
         D: DeserializeOwned + Clone + Send + Sync + Unpin;
+
+    async fn add_document_default<D>(&self, document: D, options: Option<IndexOptions>) -> Result<Response<Self::Body>>
+    where
+        Self: Sync,
+        D: Serialize + Send + Sync + 'async_trait,
+    {
+        let index = self.default_index().ok_or(ToshiClientError::NoDefaultIndex)?.to_string();
+        self.add_document(index, document, options).await
+    }
+
+    async fn search_default<D>(&self, search: Search) -> Result<SearchResults<D>>
+    where
+        Self: Sync,
+        D: DeserializeOwned + Clone + Send + Sync + Unpin,
+    {
+        let index = self.default_index().ok_or(ToshiClientError::NoDefaultIndex)?.to_string();
+        self.search(index, search).await
+    }
+
+    async fn all_docs_default<D>(&self) -> Result<SearchResults<D>>
+    where
+        Self: Sync,
+        D: DeserializeOwned + Clone + Send + Sync + Unpin,
+    {
+        let index = self.default_index().ok_or(ToshiClientError::NoDefaultIndex)?.to_string();
+        self.all_docs(index).await
+    }
 }
 
 pub trait SyncClient {
     type Body;
 
+    /// The index used by the `sync_*_default` methods, if the client was given one
+    fn sync_default_index(&self) -> Option<&str>;
+
     fn sync_index(&self) -> Result<Response<Self::Body>>;
 
     fn sync_index_summary<I>(&self, index: I, include_sizes: bool) -> Result<Response<Self::Body>>
@@ -83,4 +116,28 @@ pub trait SyncClient {
     where
         I: ToString + Display,
         D: DeserializeOwned + Clone;
+
+    fn sync_add_document_default<D>(&self, document: D, options: Option<IndexOptions>) -> Result<Response<Self::Body>>
+    where
+        D: Serialize,
+    {
+        let index = self.sync_default_index().ok_or(ToshiClientError::NoDefaultIndex)?.to_string();
+        self.sync_add_document(index, document, options)
+    }
+
+    fn sync_search_default<D>(&self, search: Search) -> Result<SearchResults<D>>
+    where
+        D: DeserializeOwned + Clone,
+    {
+        let index = self.sync_default_index().ok_or(ToshiClientError::NoDefaultIndex)?.to_string();
+        self.sync_search(index, search)
+    }
+
+    fn sync_all_docs_default<D>(&self) -> Result<SearchResults<D>>
+    where
+        D: DeserializeOwned + Clone,
+    {
+        let index = self.sync_default_index().ok_or(ToshiClientError::NoDefaultIndex)?.to_string();
+        self.sync_all_docs(index)
+    }
 }
@@ -105,7 +105,7 @@ where
         self.handle.get_index()
     }
 
-    fn get_writer(&self) -> Arc<Mutex<IndexWriter>> {
+    fn get_writer(&self) -> ToshiResult<Arc<Mutex<IndexWriter>>> {
         self.handle.get_writer()
     }
 
@@ -121,6 +121,10 @@ where
         self.handle.set_opstamp(opstamp);
     }
 
+    fn num_docs(&self) -> u64 {
+        self.handle.num_docs()
+    }
+
     async fn commit(&self) -> std::result::Result<u64, toshi_types::Error> {
         self.commit().await
     }
@@ -137,4 +141,40 @@ where
     async fn delete_term(&self, term: DeleteDoc) -> ToshiResult<DocsAffected> {
         self.handle.delete_term(term).await
     }
+
+    async fn delete_by_query(&self, delete: DeleteQuery) -> ToshiResult<DocsAffected> {
+        self.handle.delete_by_query(delete).await
+    }
+
+    fn get_settings(&self) -> IndexSettings {
+        self.handle.get_settings()
+    }
+
+    async fn set_settings(&self, settings: IndexSettings) -> ToshiResult<()> {
+        self.handle.set_settings(settings).await
+    }
+
+    fn warmup(&self) -> ToshiResult<usize> {
+        self.handle.warmup()
+    }
+
+    async fn recreate_writer(&self) -> ToshiResult<()> {
+        self.handle.recreate_writer().await
+    }
+
+    async fn freeze(&self) -> ToshiResult<()> {
+        self.handle.freeze().await
+    }
+
+    async fn thaw(&self) -> ToshiResult<()> {
+        self.handle.thaw().await
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.handle.is_frozen()
+    }
+
+    fn term_limit(&self) -> Option<TermLimit> {
+        self.handle.term_limit()
+    }
 }
@@ -73,7 +73,7 @@ where
         info!(self.logger, "REQ = {:?}", &request);
         let DocumentRequest { index, document } = request.into_inner();
         let cat = Arc::clone(&self.catalog);
-        if let Ok(idx) = cat.get_index(&index) {
+        if let Ok(idx) = cat.get_index(&index).await {
             if let Ok(doc) = serde_json::from_slice::<AddDocument<serde_json::Value>>(&document) {
                 if idx.add_document(doc).await.is_ok() {
                     Ok(Response::new(ok_result()))
@@ -91,7 +91,7 @@ where
     async fn delete_document(&self, request: Request<DeleteRequest>) -> Result<Response<DeleteReply>, Status> {
         let DeleteRequest { index, terms } = request.into_inner();
         let cat = Arc::clone(&self.catalog);
-        if let Ok(idx) = cat.get_index(&index) {
+        if let Ok(idx) = cat.get_index(&index).await {
             if let Ok(delete_docs) = serde_json::from_slice::<DeleteDoc>(&terms) {
                 let DocsAffected { docs_affected } = idx.delete_term(delete_docs).await.unwrap();
                 Ok(Response::new(DeleteReply { index, docs_affected }))
@@ -107,11 +107,14 @@ where
         let inner = request.into_inner();
         let cat = Arc::clone(&self.catalog);
         {
-            if let Ok(index) = cat.get_index(&inner.index) {
-                let query = match query_or_all(&inner.query) {
+            if let Ok(index) = cat.get_index(&inner.index).await {
+                let mut query = match query_or_all(&inner.query) {
                     Ok(v) => v,
                     Err(e) => return error_response(Code::Internal, e.to_string()),
                 };
+                if inner.aggregations_only {
+                    query = query.aggregations_only();
+                }
                 info!(self.logger, "QUERY = {:?}", &query);
 
                 match index.search_index(query).await {
@@ -132,7 +135,7 @@ where
     async fn get_summary(&self, request: Request<SummaryRequest>) -> Result<Response<SummaryReply>, Status> {
         let SummaryRequest { index } = request.into_inner();
         let cat = Arc::clone(&self.catalog);
-        if let Ok(idx) = cat.get_index(&index) {
+        if let Ok(idx) = cat.get_index(&index).await {
             if let Ok(metas) = idx.get_index().load_metas() {
                 let meta_json = serde_json::to_vec(&metas).unwrap();
                 Ok(Response::new(SummaryReply { summary: meta_json }))
@@ -44,10 +44,8 @@ pub fn error_response<T>(code: Code, msg: String) -> Result<Response<T>, Status>
     Err(status)
 }
 
+/// Searches sent over RPC are normalized the same way as ones made over HTTP, see [`Search::normalize`]
 pub fn query_or_all(b: &[u8]) -> Result<Search, Error> {
     let deser: Search = serde_json::from_slice(b)?;
-    if deser.query.is_none() {
-        return Ok(Search::all_docs());
-    }
-    Ok(deser)
+    deser.normalize(Search::DEFAULT_MAX_LIMIT, Search::DEFAULT_MAX_OFFSET)
 }
@@ -23,7 +23,7 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
     let settings = settings();
     let logger = setup_logging_from_file("config/logging.toml")?;
     let _scope = slog_scope::set_global_logger(logger.clone());
-    let _guard = slog_stdlog::init_with_level(log::Level::from_str(&settings.log_level)?)?;
+    slog_stdlog::init_with_level(log::Level::from_str(&settings.log_level)?)?;
 
     let (tx, shutdown_signal) = oneshot::channel();
     if !Path::new(&settings.path).exists() {
@@ -60,7 +60,7 @@ async fn setup_toshi(settings: Settings, index_catalog: SharedCatalog, tx: onesh
 }
 
 async fn setup_catalog(settings: &Settings) -> Result<SharedCatalog, toshi_types::Error> {
-    let mut index_catalog = match IndexCatalog::new(settings.clone()) {
+    let index_catalog = match IndexCatalog::new(settings.clone()) {
         Ok(v) => v,
         Err(e) => {
             eprintln!("Error creating IndexCatalog from path {} - {:?}", settings.path, e);
@@ -68,7 +68,7 @@ async fn setup_catalog(settings: &Settings) -> Result<SharedCatalog, toshi_types
         }
     };
     index_catalog.refresh_catalog().await?;
-    info!("{} Indexes loaded...", index_catalog.get_collection().len());
+    info!("{} Indexes registered...", index_catalog.list_indexes().await.len());
     Ok(Arc::new(index_catalog))
 }
 
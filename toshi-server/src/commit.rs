@@ -1,18 +1,38 @@
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use log::trace;
 use tokio::time;
 
 use toshi_types::{Catalog, IndexHandle};
 
-#[allow(irrefutable_let_patterns)]
+/// The most time that passes between the watcher checking whether indexes are due for a commit
+const MAX_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Commit each index with pending changes every `commit_duration` seconds, or as often as its own
+/// `auto_commit_duration` setting asks for
 pub async fn watcher<C: Catalog>(cat: Arc<C>, commit_duration: f32, lock: Arc<AtomicBool>) -> Result<(), ()> {
-    while let _ = time::interval(Duration::from_secs_f32(commit_duration)).tick().await {
-        for e in cat.get_collection().iter() {
+    let mut last_commits: HashMap<String, Instant> = HashMap::new();
+    let mut interval = time::interval(MAX_CHECK_INTERVAL.min(Duration::from_secs_f32(commit_duration)));
+    loop {
+        interval.tick().await;
+        // Deleted and closed indexes would otherwise keep their entries forever
+        let collection = cat.get_collection();
+        last_commits.retain(|name, _| collection.contains_key(name));
+        for e in collection.iter() {
             let (k, v) = e.pair();
-            let writer = v.get_writer();
+            let duration = v.get_settings().auto_commit_duration.unwrap_or(commit_duration);
+            let last_commit = last_commits.entry(k.clone()).or_insert_with(Instant::now);
+            if last_commit.elapsed() < Duration::from_secs_f32(duration) {
+                continue;
+            }
+            *last_commit = Instant::now();
+            let writer = match v.get_writer() {
+                Ok(writer) => writer,
+                Err(_) => continue,
+            };
             let current_ops = v.get_opstamp();
             if current_ops == 0 {
                 trace!("No update to index={}, opstamp={}", k, current_ops);
@@ -24,7 +44,6 @@ pub async fn watcher<C: Catalog>(cat: Arc<C>, commit_duration: f32, lock: Arc<At
             }
         }
     }
-    Ok(())
 }
 
 #[cfg(test)]
@@ -42,10 +61,20 @@ pub mod tests {
     use tantivy::{doc, Index};
 
     pub fn create_test_index() -> Index {
+        test_index_with((STORED | INDEXED).into())
+    }
+
+    /// The same documents as [`create_test_index`] with `test_i64` and `test_u64` as fast fields, for
+    /// sorting, scoring and aggregating by them
+    pub fn create_fast_test_index() -> Index {
+        test_index_with((STORED | INDEXED | FAST).into())
+    }
+
+    fn test_index_with(numeric: NumericOptions) -> Index {
         let mut builder = SchemaBuilder::new();
         let test_text = builder.add_text_field("test_text", STORED | TEXT);
-        let test_int = builder.add_i64_field("test_i64", STORED | INDEXED);
-        let test_unsign = builder.add_u64_field("test_u64", STORED | INDEXED);
+        let test_int = builder.add_i64_field("test_i64", numeric.clone());
+        let test_unsign = builder.add_u64_field("test_u64", numeric);
         let test_unindexed = builder.add_text_field("test_unindex", STORED);
         let test_facet = builder.add_facet_field("test_facet", INDEXED | STORED);
 
@@ -68,6 +97,33 @@ pub mod tests {
         serde_json::from_slice::<T>(bytes.as_bytes()).unwrap_or_else(|e| panic!("Could not deserialize JSON: {:?}", e))
     }
 
+    /// Runs `probe` every 50ms until `done` accepts its result or a second has passed, returning the
+    /// last result so the caller can assert on it
+    pub async fn wait_until<T, F, Fut>(mut probe: F, done: impl Fn(&T) -> bool) -> T
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = T>,
+    {
+        let mut last = probe().await;
+        for _ in 0..20 {
+            if done(&last) {
+                break;
+            }
+            time::sleep(Duration::from_millis(50)).await;
+            last = probe().await;
+        }
+        last
+    }
+
+    /// Waits for the reader of `index` to see `hits` documents, returning the last count it saw
+    pub async fn wait_for_hits<C: Catalog>(catalog: &Arc<C>, index: &str, hits: usize) -> usize {
+        let probe = || async {
+            let docs: SearchResults = wait_json(all_docs(Arc::clone(catalog), index).await.unwrap()).await;
+            docs.hits
+        };
+        wait_until(probe, |&seen| seen == hits).await
+    }
+
     pub fn cmp_float(a: f32, b: f32) -> bool {
         let abs_a = a.abs();
         let abs_b = b.abs();
@@ -107,4 +163,39 @@ pub mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    pub async fn test_index_auto_commit_duration() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::index::{catalog_fixture, clear_catalog_fixture, IndexCatalog};
+        use toshi_types::IndexSettings;
+
+        let (path, settings, schema) = catalog_fixture("toshi_test_index_auto_commit_duration")?;
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+        for (name, duration) in [("hot", 0.1), ("cold", 60.0)] {
+            catalog.add_index(name, schema.clone()).await?;
+            let settings = IndexSettings {
+                auto_commit_duration: Some(duration),
+                ..Default::default()
+            };
+            catalog.get_index(name).await?.set_settings(settings).await?;
+        }
+        tokio::spawn(watcher(Arc::clone(&catalog), 60.0, Arc::new(AtomicBool::new(false))));
+
+        let body = r#"{"document": { "test_text": "Uncommitted" } }"#;
+        add_document(Arc::clone(&catalog), Body::from(body), "hot").await?;
+        add_document(Arc::clone(&catalog), Body::from(body), "cold").await?;
+        let hot = catalog.get_index("hot").await?;
+        assert_eq!(wait_until(|| async { hot.get_opstamp() }, |&opstamp| opstamp == 0).await, 0);
+        assert_eq!(catalog.get_index("cold").await?.get_opstamp(), 1);
+
+        let bad = IndexSettings {
+            auto_commit_duration: Some(0.0),
+            ..Default::default()
+        };
+        assert!(catalog.get_index("cold").await?.set_settings(bad).await.is_err());
+
+        drop(hot);
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
 }
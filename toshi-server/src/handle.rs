@@ -1,17 +1,25 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use log::*;
-use tantivy::collector::{FacetCollector, MultiCollector, TopDocs};
+use tantivy::collector::{Collector, Count, FacetCollector, MultiCollector, SegmentCollector, TopDocs};
 use tantivy::directory::MmapDirectory;
-use tantivy::merge_policy::MergePolicy;
-use tantivy::query::{AllQuery, QueryParser};
+use tantivy::fastfield::Column;
+use tantivy::merge_policy::{MergeCandidate, MergePolicy};
+use tantivy::query::{AllQuery, BooleanQuery, EnableScoring, Query as TantivyQuery, QueryParser};
 use tantivy::schema::*;
 use tantivy::space_usage::SearcherSpaceUsage;
-use tantivy::{Document, Index, IndexReader, IndexWriter, ReloadPolicy, Term};
+use tantivy::store::StoreReader;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::{
+    DocAddress, DocId, DocSet, Document, Index, IndexReader, IndexWriter, Postings, ReloadPolicy, Score, Searcher, SegmentMeta,
+    SegmentOrdinal, SegmentReader, SnippetGenerator, Term, TERMINATED,
+};
 use tokio::sync::*;
 
 use toshi_types::*;
@@ -20,17 +28,531 @@ use crate::settings::{Settings, DEFAULT_WRITER_MEMORY};
 use crate::{register_tokenizers, Result};
 use crate::{AddDocument, SearchResults};
 
+/// Compile a query from the DSL into the Tantivy query it will be executed as
+pub(crate) fn build_query(
+    index: &Index,
+    query: Query,
+    conjunction_by_default: bool,
+    settings: &IndexSettings,
+) -> Result<Box<dyn TantivyQuery>> {
+    let schema = index.schema();
+    query.validate_fields(&schema)?;
+    let gen_query = match query {
+        Query::Regex(regex) => regex.create_query(&schema)?,
+        Query::Phrase(phrase) => phrase.create_query(&schema)?,
+        Query::Fuzzy(fuzzy) => fuzzy.create_query(&schema)?,
+        Query::Exact(term) => term.create_query(&schema)?,
+        Query::Range(range) => range.create_query(&schema)?,
+        Query::Boolean { bool } => bool.create_query(&schema)?,
+        // The decay is applied by the top docs collector, see `FunctionScoreQuery::top_docs`
+        Query::FunctionScore { function_score } => build_query(index, function_score.into_query(), conjunction_by_default, settings)?,
+        Query::Raw { raw } => {
+            // Fields are grouped by whether their terms are AND'd, each group gets its own parser
+            // and a document matching the query in any group is a match.
+            let mut groups: BTreeMap<bool, Vec<Field>> = BTreeMap::new();
+            for (field, entry) in schema.fields() {
+                let conjunction = match settings.default_operators.get(entry.name()) {
+                    Some(DefaultOperator::And) => true,
+                    Some(DefaultOperator::Or) => false,
+                    None => conjunction_by_default,
+                };
+                groups.entry(conjunction).or_default().push(field);
+            }
+            let mut queries = Vec::with_capacity(groups.len());
+            for (conjunction, fields) in groups {
+                let mut query_parser = QueryParser::for_index(index, fields);
+                if conjunction {
+                    query_parser.set_conjunction_by_default();
+                }
+                queries.push(query_parser.parse_query(&raw).map_err(|e| Error::raw_query(&raw, e))?);
+            }
+            if queries.len() == 1 {
+                queries.remove(0)
+            } else {
+                Box::new(BooleanQuery::union(queries))
+            }
+        }
+        Query::All => Box::new(AllQuery),
+    };
+    Ok(gen_query)
+}
+
+/// The file an index's [`IndexSettings`] are persisted to inside the index's directory
+pub(crate) const INDEX_SETTINGS_FILE: &str = "toshi_settings.json";
+
+fn read_index_settings(path: &Path) -> Result<IndexSettings> {
+    if path.exists() {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    } else {
+        Ok(IndexSettings::default())
+    }
+}
+
+/// The id of a document is the first value of the id field, text is returned as is
+fn doc_id(doc: &FlatNamedDocument, id_field: &str) -> Option<String> {
+    doc.get_first(id_field).map(|value| match value {
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    })
+}
+
+/// The text of every term in a query, grouped by the field the term is searched for in
+fn query_term_texts(query: &dyn TantivyQuery) -> BTreeMap<Field, HashSet<String>> {
+    let mut terms: BTreeMap<Field, HashSet<String>> = BTreeMap::new();
+    query.query_terms(&mut |term, _| {
+        if let Some(text) = term.as_str() {
+            terms.entry(term.field()).or_default().insert(text.to_string());
+        }
+    });
+    terms
+}
+
+/// The document frequency and total term frequency of every term of the query, summed over the
+/// searcher's segments
+fn term_stats(searcher: &Searcher, query: &dyn TantivyQuery) -> Result<Vec<TermStats>> {
+    let mut terms = BTreeSet::new();
+    query.query_terms(&mut |term, _| {
+        terms.insert(term.clone());
+    });
+    let schema = searcher.schema();
+    let mut stats = Vec::with_capacity(terms.len());
+    for term in terms {
+        let (mut doc_freq, mut total_term_freq) = (0, 0);
+        for segment in searcher.segment_readers() {
+            let inverted_index = segment.inverted_index(term.field())?;
+            doc_freq += u64::from(inverted_index.doc_freq(&term)?);
+            if let Some(mut postings) = inverted_index.read_postings(&term, IndexRecordOption::WithFreqs)? {
+                while postings.doc() != TERMINATED {
+                    total_term_freq += u64::from(postings.term_freq());
+                    postings.advance();
+                }
+            }
+        }
+        stats.push(TermStats {
+            field: schema.get_field_name(term.field()).to_string(),
+            term: term_value(&term),
+            doc_freq,
+            total_term_freq,
+        });
+    }
+    Ok(stats)
+}
+
+fn term_value(term: &Term) -> String {
+    if let Some(text) = term.as_str() {
+        return text.to_string();
+    }
+    term.as_u64()
+        .map(|v| v.to_string())
+        .or_else(|| term.as_i64().map(|v| v.to_string()))
+        .or_else(|| term.as_f64().map(|v| v.to_string()))
+        .or_else(|| term.as_bool().map(|v| v.to_string()))
+        .unwrap_or_else(|| format!("{:?}", term))
+}
+
+/// A snippet generator for each field of `options` that's a stored text field, other fields are skipped
+fn snippet_generators(searcher: &Searcher, query: &dyn TantivyQuery, options: &HighlightOptions) -> Vec<(String, SnippetGenerator)> {
+    let schema = searcher.schema();
+    options
+        .fields
+        .iter()
+        .filter_map(|name| {
+            let field = schema.get_field(name)?;
+            let entry = schema.get_field_entry(field);
+            if !(entry.is_stored() && matches!(entry.field_type(), FieldType::Str(_))) {
+                return None;
+            }
+            let mut generator = SnippetGenerator::create(searcher, query, field).ok()?;
+            if let Some(max) = options.max_fragment_length {
+                generator.set_max_num_chars(max);
+            }
+            Some((name.clone(), generator))
+        })
+        .collect()
+}
+
+/// A fragment of each value of the highlighted fields that the query matched
+fn highlights(generators: &[(String, SnippetGenerator)], doc: &FlatNamedDocument) -> BTreeMap<String, Vec<Highlight>> {
+    generators
+        .iter()
+        .filter_map(|(name, generator)| {
+            let fragments: Vec<Highlight> = doc
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(|text| generator.snippet(text))
+                .filter(|snippet| !snippet.is_empty())
+                .map(|snippet| Highlight {
+                    fragment: snippet.fragment().into(),
+                    highlighted: snippet
+                        .highlighted()
+                        .iter()
+                        .map(|r| MatchOffset {
+                            start: r.start,
+                            end: r.end,
+                        })
+                        .collect(),
+                })
+                .collect();
+            (!fragments.is_empty()).then(|| (name.clone(), fragments))
+        })
+        .collect()
+}
+
+/// Tokenize the first value of each queried text field the same way it was indexed and return the
+/// offsets of the tokens that match a term of the query
+fn match_offsets(
+    index: &Index,
+    terms: &BTreeMap<Field, HashSet<String>>,
+    doc: &FlatNamedDocument,
+) -> Result<BTreeMap<String, Vec<MatchOffset>>> {
+    let schema = index.schema();
+    let mut offsets = BTreeMap::new();
+    for (field, texts) in terms {
+        let name = schema.get_field_name(*field);
+        let value = match doc.get_str(name) {
+            Some(v) => v,
+            None => continue,
+        };
+        let mut matches = Vec::new();
+        index.tokenizer_for_field(*field)?.token_stream(&value).process(&mut |token| {
+            if texts.contains(&token.text) {
+                matches.push(MatchOffset {
+                    start: token.offset_from,
+                    end: token.offset_to,
+                });
+            }
+        });
+        if !matches.is_empty() {
+            offsets.insert(name.to_string(), matches);
+        }
+    }
+    Ok(offsets)
+}
+
+/// One field's part of a document's sort key: whether the document sorts ahead of the other group
+/// of missing or present values, its value ranked in the field's direction, and the value itself
+type SortKey = (bool, u64, Option<f32>);
+
+/// A field documents are sorted by, its direction and its fast field column in a segment
+type SortColumn = (Option<Field>, SortOrder, Option<Arc<dyn Column<u64>>>);
+
+/// The fast field columns of a segment that documents are sorted by, `None` sorting by score
+struct SortColumns {
+    columns: Vec<SortColumn>,
+    store: Option<StoreReader>,
+    missing: SortMissing,
+}
+
+impl SortColumns {
+    fn open(segment: &SegmentReader, fields: &[(Option<Field>, SortOrder)], missing: SortMissing) -> Self {
+        let columns = fields
+            .iter()
+            .map(|(field, order)| (*field, *order, field.and_then(|f| segment.fast_fields().u64(f).ok())))
+            .collect();
+        Self {
+            columns,
+            store: segment.get_store_reader(1).ok(),
+            missing,
+        }
+    }
+
+    fn key(&self, doc: DocId, score: Score) -> Vec<SortKey> {
+        // Only read from the store once per document, however many of its values are 0
+        let mut stored: Option<Option<Document>> = None;
+        self.columns
+            .iter()
+            .map(|(field, order, column)| {
+                let (rank, value) = match field {
+                    Some(field) => {
+                        let value = column.as_ref().map(|c| c.get_val(doc)).unwrap_or_default();
+                        let is_missing = value == 0
+                            && stored
+                                .get_or_insert_with(|| self.store.as_ref().and_then(|s| s.get(doc).ok()))
+                                .as_ref()
+                                .map(|d| d.get_first(*field).is_none())
+                                .unwrap_or_default();
+                        if is_missing {
+                            return (self.missing == SortMissing::First, 0, None);
+                        }
+                        (value, value as f32)
+                    }
+                    None => {
+                        let score = if score.is_nan() { f32::NEG_INFINITY } else { score };
+                        (score_rank(score), score)
+                    }
+                };
+                let rank = match order {
+                    SortOrder::Desc => rank,
+                    SortOrder::Asc => u64::MAX - rank,
+                };
+                (self.missing == SortMissing::Last, rank, Some(value))
+            })
+            .collect()
+    }
+}
+
+/// Map a score onto a u64 with the same ordering, flipping the bits of negative scores so they
+/// sort below the positive ones
+fn score_rank(score: Score) -> u64 {
+    let bits = score.to_bits();
+    u64::from(if score.is_sign_negative() { !bits } else { bits | 1 << 31 })
+}
+
+/// Top docs ordered by u64 fast fields, each field only breaking the ties of the ones before it. A
+/// missing value reads as 0 from the fast field, so those documents are told apart by checking the
+/// stored document and then placed according to `missing`. Documents aren't scored, so none of
+/// the fields may be [`Search::SCORE`], see [`scored_sorted_top_docs`] for those.
+fn sorted_top_docs(
+    fields: Vec<(Option<Field>, SortOrder)>,
+    limit: usize,
+    missing: SortMissing,
+) -> impl Collector<Fruit = Vec<(Vec<SortKey>, DocAddress)>> {
+    TopDocs::with_limit(limit).custom_score(move |segment: &SegmentReader| {
+        let columns = SortColumns::open(segment, &fields, missing);
+        move |doc: DocId| columns.key(doc, 0.0)
+    })
+}
+
+/// The same ordering as [`sorted_top_docs`] with the query's score available to sort by where a
+/// field is `None`. This is the score of the query itself, BM25 parameters and score expressions
+/// given alongside the search aren't applied to it.
+fn scored_sorted_top_docs(
+    fields: Vec<(Option<Field>, SortOrder)>,
+    limit: usize,
+    missing: SortMissing,
+) -> impl Collector<Fruit = Vec<(Vec<SortKey>, DocAddress)>> {
+    TopDocs::with_limit(limit).tweak_score(move |segment: &SegmentReader| {
+        let columns = SortColumns::open(segment, &fields, missing);
+        move |doc: DocId, score: Score| columns.key(doc, score)
+    })
+}
+
+/// The first documents matched in index order, by segment and then doc id, which skips scoring
+struct DocOrder {
+    limit: usize,
+}
+
+struct DocOrderSegment {
+    segment: SegmentOrdinal,
+    limit: usize,
+    docs: Vec<DocAddress>,
+}
+
+impl Collector for DocOrder {
+    type Fruit = Vec<DocAddress>;
+    type Child = DocOrderSegment;
+
+    fn for_segment(&self, segment: SegmentOrdinal, _: &SegmentReader) -> tantivy::Result<Self::Child> {
+        Ok(DocOrderSegment {
+            segment,
+            limit: self.limit,
+            docs: Vec::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_docs: Vec<Vec<DocAddress>>) -> tantivy::Result<Self::Fruit> {
+        let mut docs: Vec<DocAddress> = segment_docs.into_iter().flatten().collect();
+        docs.sort_unstable();
+        docs.truncate(self.limit);
+        Ok(docs)
+    }
+}
+
+impl SegmentCollector for DocOrderSegment {
+    type Fruit = Vec<DocAddress>;
+
+    fn collect(&mut self, doc: DocId, _: Score) {
+        // Docs arrive in increasing order, so once the limit is reached the rest come later
+        if self.docs.len() < self.limit {
+            self.docs.push(DocAddress::new(self.segment, doc));
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.docs
+    }
+}
+
+/// The first `limit` documents matching `query` in index order, unlike a collector this stops
+/// advancing through the query's matches as soon as enough have been found
+fn first_matches(searcher: &Searcher, query: &dyn TantivyQuery, limit: usize) -> Result<Vec<DocAddress>> {
+    let weight = query.weight(EnableScoring::Disabled(searcher.schema()))?;
+    let mut docs = Vec::with_capacity(limit.min(searcher.num_docs() as usize));
+    for (ord, segment) in searcher.segment_readers().iter().enumerate() {
+        if docs.len() >= limit {
+            break;
+        }
+        let mut scorer = weight.scorer(segment, 1.0)?;
+        let mut doc = scorer.doc();
+        while doc != TERMINATED && docs.len() < limit {
+            if !segment.alive_bitset().is_some_and(|alive| alive.is_deleted(doc)) {
+                docs.push(DocAddress::new(ord as SegmentOrdinal, doc));
+            }
+            doc = scorer.advance();
+        }
+    }
+    Ok(docs)
+}
+
+/// Hold the text fields of a parsed document to `limit`, rejecting the document when it has a longer
+/// term or cutting each longer term short in the text itself, so the stored value is shortened too
+pub(crate) fn limit_terms(index: &Index, doc: Document, limit: TermLimit) -> Result<Document> {
+    let schema = index.schema();
+    doc.into_iter()
+        .map(|field_value| {
+            let field = field_value.field();
+            match (field_value.value(), schema.get_field_entry(field).field_type()) {
+                (Value::Str(text), FieldType::Str(options)) if options.get_indexing_options().is_some() => {
+                    let analyzer = index.tokenizer_for_field(field)?;
+                    let text = limit_text(&analyzer, text, limit).map_err(|term| {
+                        let msg = format!(
+                            "Field: {} has a term longer than {} bytes: {}...",
+                            schema.get_field_name(field),
+                            limit.max_length,
+                            term
+                        );
+                        Error::QueryError(msg)
+                    })?;
+                    Ok(FieldValue::new(field, Value::Str(text)))
+                }
+                _ => Ok(field_value),
+            }
+        })
+        .collect::<Result<Vec<FieldValue>>>()
+        .map(Document::from)
+}
+
+/// `text` with each of its terms longer than `limit` cut short, or the start of the first long term
+/// when they're rejected
+fn limit_text(analyzer: &TextAnalyzer, text: &str, limit: TermLimit) -> std::result::Result<String, String> {
+    let mut long_terms = Vec::new();
+    analyzer.token_stream(text).process(&mut |token| {
+        if token.text.len() > limit.max_length {
+            long_terms.push((token.offset_from, token.offset_to));
+        }
+    });
+    let mut limited = String::with_capacity(text.len());
+    let mut last = 0;
+    for (from, to) in long_terms {
+        let end = floor_char_boundary(text, (from + limit.max_length).min(to));
+        if !limit.truncate {
+            return Err(text[from..end].to_string());
+        }
+        // Tokenizers producing overlapping terms have already had this part of the text cut
+        if from < last {
+            continue;
+        }
+        limited.push_str(&text[last..end]);
+        last = to;
+    }
+    limited.push_str(&text[last..]);
+    Ok(limited)
+}
+
+/// The closest index at or before `index` that starts a character of `text`
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Load a stored document to return from `search`, text values longer than the index's
+/// `max_stored_field_bytes` are cut short and marked as truncated
+fn load_doc(searcher: &Searcher, doc: DocAddress, search: &Search, settings: &IndexSettings) -> Result<FlatNamedDocument> {
+    let mut d = searcher.doc(doc)?;
+    if let Some(max_bytes) = settings.max_stored_field_bytes {
+        d = d
+            .into_iter()
+            .map(|field_value| match field_value.value() {
+                Value::Str(text) if text.len() > max_bytes => {
+                    let end = floor_char_boundary(text, max_bytes);
+                    let text = format!("{}{}", &text[..end], IndexSettings::TRUNCATION_MARKER);
+                    FieldValue::new(field_value.field(), Value::Str(text))
+                }
+                _ => field_value,
+            })
+            .collect::<Vec<FieldValue>>()
+            .into();
+    }
+    Ok(FlatNamedDocument::from_named(
+        searcher.schema().to_named_doc(&d),
+        search.always_arrays,
+    ))
+}
+
+/// Top level fields of a document that Tantivy would otherwise silently drop when parsing it
+fn unknown_fields<'a>(schema: &Schema, doc: &'a serde_json::Value) -> Vec<&'a str> {
+    let mut unknown: Vec<&str> = doc
+        .as_object()
+        .map(|o| o.keys().map(String::as_str).filter(|k| schema.get_field(k).is_none()).collect())
+        .unwrap_or_default();
+    unknown.sort_unstable();
+    unknown
+}
+
+#[inline]
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Keep only the first, and so highest ranked, document for each distinct value of `field`,
+/// documents without a value for the field are all kept
+fn collapse_ranked(searcher: &Searcher, ranked: Vec<(Option<Score>, DocAddress)>, field: &str) -> Result<Vec<(Option<Score>, DocAddress)>> {
+    let schema = searcher.schema();
+    let field = schema.get_field(field).ok_or_else(|| Error::UnknownIndexField(field.into()))?;
+    let mut seen = HashSet::new();
+    let mut kept = Vec::new();
+    for (score, address) in ranked {
+        let doc = searcher.doc(address)?;
+        let first = match doc.get_first(field) {
+            Some(value) => seen.insert(serde_json::to_string(value)?),
+            None => true,
+        };
+        if first {
+            kept.push((score, address));
+        }
+    }
+    Ok(kept)
+}
+
 /// Index handle that operates on an Index local to the node, a remote index handle
 /// will eventually call to wherever the local index is stored, so at some level the relevant
 /// local handle will always get called through rpc
 #[derive(Clone)]
 pub struct LocalIndex {
     index: Index,
-    writer: Arc<Mutex<IndexWriter>>,
+    writer: Arc<std::sync::RwLock<WriterState>>,
+    /// Memory given to the writer, kept so a thawed index can build a new one
+    writer_memory: usize,
+    /// Held while the index is frozen or thawed so only one of them swaps the writer at a time
+    transition: Arc<Mutex<()>>,
     reader: IndexReader,
     current_opstamp: Arc<AtomicUsize>,
     deleted_docs: Arc<AtomicU64>,
     name: String,
+    settings: Arc<std::sync::RwLock<IndexSettings>>,
+    /// Where settings are persisted, indexes that don't live on disk only keep them in memory
+    settings_path: Option<PathBuf>,
+    /// Commit once this many operations are uncommitted, 0 leaves it to the auto commit watcher
+    max_pending_ops: usize,
+    /// Permits for searches allowed to run at once, searches aren't limited without it
+    search_permits: Option<Arc<Semaphore>>,
+    /// Most boolean clauses a query may have in total, 0 leaves it unlimited
+    max_query_clauses: usize,
+    /// Most levels a query may nest other queries, 0 leaves it unlimited
+    max_query_depth: usize,
+    /// Reject documents with fields missing from the schema unless the index's settings say otherwise
+    strict_mapping: bool,
+    /// Most bytes a term may have unless the index's settings say otherwise, 0 leaves it unlimited
+    max_term_length: usize,
+    /// Cut long terms short rather than rejecting their documents unless the index's settings say otherwise
+    truncate_long_terms: bool,
 }
 
 impl PartialEq for LocalIndex {
@@ -51,8 +573,11 @@ impl IndexHandle for LocalIndex {
         self.index.clone()
     }
 
-    fn get_writer(&self) -> Arc<Mutex<IndexWriter>> {
-        Arc::clone(&self.writer)
+    fn get_writer(&self) -> Result<Arc<Mutex<IndexWriter>>> {
+        match &*self.writer.read().unwrap() {
+            WriterState::Open(writer) => Ok(Arc::clone(writer)),
+            WriterState::Frozen(_) => Err(Error::IndexFrozen(self.name.clone())),
+        }
     }
 
     fn get_space(&self) -> SearcherSpaceUsage {
@@ -69,29 +594,131 @@ impl IndexHandle for LocalIndex {
         self.current_opstamp.store(opstamp, Ordering::SeqCst)
     }
 
+    fn num_docs(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
     async fn commit(&self) -> Result<u64> {
-        let mut lock = self.writer.lock().await;
+        let writer = self.get_writer()?;
+        let mut lock = writer.lock().await;
         Ok(lock.commit()?)
     }
 
     async fn search_index(&self, search: Search) -> Result<SearchResults> {
+        if let Some(query) = &search.query {
+            query.validate_complexity(self.max_query_clauses, self.max_query_depth)?;
+            query.validate_function_score()?;
+        }
+        let function_score = matches!(search.query, Some(Query::FunctionScore { .. }));
+        let scorers = [search.score_expression.is_some(), function_score, search.bm25.is_some()];
+        if scorers.iter().filter(|s| **s).count() > 1 {
+            let msg = "Only one of score_expression, function_score and bm25 can be used in a search";
+            return Err(Error::QueryError(msg.into()));
+        }
+        for agg in search.aggs.values() {
+            agg.validate_complexity(self.max_query_clauses, self.max_query_depth)?;
+        }
+        if search.early_terminate.is_some()
+            && (!search.sort_by.is_empty() || search.facets.is_some() || !search.aggs.is_empty() || search.collapse.is_some())
+        {
+            let msg = "early_terminate can't be combined with sort_by, facets, aggs or collapse, they need every match";
+            return Err(Error::QueryError(msg.into()));
+        }
+        let _permit = match &self.search_permits {
+            Some(permits) => Some(permits.try_acquire().map_err(|_| Error::TooManySearches(self.name.clone()))?),
+            None => None,
+        };
+        let start = Instant::now();
         let searcher = self.reader.searcher();
         let schema = self.index.schema();
+        let settings = self.get_settings();
+        let gen_query = match &search.query {
+            Some(query) => Some(build_query(&self.index, query.clone(), search.conjunction_by_default, &settings)?),
+            None => None,
+        };
+        // Nothing but the count is collected when there are no facets or aggregations to fill in
+        if search.count_only && search.facets.is_none() && search.aggs.is_empty() {
+            if let Some(query) = &gen_query {
+                let hits = searcher.search(query.as_ref(), &Count)?;
+                let took_ms = millis(start.elapsed());
+                let timing = SearchTiming {
+                    took_ms,
+                    query_ms: took_ms,
+                    fetch_ms: 0.0,
+                };
+                return Ok(SearchResults::from_count(hits).with_timing(timing));
+            }
+        }
         let mut multi_collector = MultiCollector::new();
+        // Skipped documents still have to be collected, they're dropped before they're loaded
+        // No document collectors are added when no documents will be returned, which also keeps a
+        // limit of 0 away from TopDocs, since it can't collect fewer than one document
+        let collect_docs = search.limit > 0 && !search.count_only;
+        // Collapsing ranks every match, a page of distinct values can reach arbitrarily far down the
+        // matches and every value has to be seen to count them
+        let collect_limit = match (&search.collapse, &gen_query) {
+            (Some(_), Some(query)) if collect_docs => searcher.search(query.as_ref(), &Count)?.max(1),
+            _ => search.limit + search.offset,
+        };
+        let count_handle = multi_collector.add_collector(Count);
 
-        let sorted_top_handle = search.sort_by.clone().and_then(|sort_by| {
-            info!("Sorting with: {}", sort_by);
-            if let Some(f) = schema.get_field(&sort_by) {
-                let entry = schema.get_field_entry(f);
-                if entry.is_fast() && entry.is_stored() {
-                    let c = TopDocs::with_limit(search.limit).order_by_u64_field(f);
-                    return Some(multi_collector.add_collector(c));
+        let mut doc_order_handle = None;
+        let sorted_top_handle = match &search.sort_by[..] {
+            _ if !collect_docs => None,
+            [] => None,
+            [sort] if sort.field == Search::DOC_ORDER => {
+                doc_order_handle = Some(multi_collector.add_collector(DocOrder { limit: collect_limit }));
+                None
+            }
+            // Score descending on its own is the order of the plain top docs collected below
+            [sort] if sort.field == Search::SCORE && sort.order == SortOrder::Desc => None,
+            sort_by => {
+                let mut fields = Vec::with_capacity(sort_by.len());
+                for sort in sort_by {
+                    info!("Sorting with: {} {:?}", sort.field, sort.order);
+                    if sort.field == Search::DOC_ORDER {
+                        let msg = format!("{} can't be combined with other sort fields", Search::DOC_ORDER);
+                        return Err(Error::QueryError(msg));
+                    }
+                    if sort.field == Search::SCORE {
+                        fields.push((None, sort.order));
+                        continue;
+                    }
+                    let f = schema
+                        .get_field(&sort.field)
+                        .ok_or_else(|| Error::UnknownIndexField(sort.field.clone()))?;
+                    let entry = schema.get_field_entry(f);
+                    let is_u64 = matches!(entry.field_type(), FieldType::U64(_));
+                    if !(is_u64 && entry.is_fast() && entry.is_stored()) {
+                        let msg = format!("Sort field: {} must be a fast and stored u64 field", sort.field);
+                        return Err(Error::QueryError(msg));
+                    }
+                    fields.push((Some(f), sort.order));
+                }
+                // Documents are only scored when the score is one of the sort fields
+                if fields.iter().any(|(f, _)| f.is_none()) {
+                    Some(multi_collector.add_collector(scored_sorted_top_docs(fields, collect_limit, search.sort_missing)))
+                } else {
+                    Some(multi_collector.add_collector(sorted_top_docs(fields, collect_limit, search.sort_missing)))
                 }
             }
-            None
-        });
+        };
 
-        let top_handle = multi_collector.add_collector(TopDocs::with_limit(search.limit));
+        if let (Some(matches), Some(query)) = (search.early_terminate, &gen_query) {
+            return self.search_first_matches(&searcher, &search, query.as_ref(), matches, &settings, start);
+        }
+        // Scores aren't needed when results are in index order, so no top docs are collected at all
+        let top_handle = match (&search.query, &gen_query, search.bm25.or(settings.bm25), &search.score_expression) {
+            _ if doc_order_handle.is_some() || !collect_docs => None,
+            (_, _, _, Some(expr)) => Some(multi_collector.add_collector(expr.top_docs(&schema, collect_limit)?)),
+            (Some(Query::FunctionScore { function_score }), _, _, _) => {
+                Some(multi_collector.add_collector(function_score.top_docs(&schema, collect_limit)?))
+            }
+            (_, Some(query), Some(bm25), _) => {
+                Some(multi_collector.add_collector(bm25.top_docs(&searcher, query.as_ref(), collect_limit)?))
+            }
+            _ => Some(multi_collector.add_collector(TopDocs::with_limit(collect_limit))),
+        };
         let facet_handle = search.facets.clone().and_then(|f| {
             if let Some(field) = schema.get_field(f.get_facets_fields()) {
                 let mut col = FacetCollector::for_field(field);
@@ -104,86 +731,172 @@ impl IndexHandle for LocalIndex {
             }
         });
 
-        if let Some(query) = search.query {
-            let gen_query = match query {
-                Query::Regex(regex) => regex.create_query(&schema)?,
-                Query::Phrase(phrase) => phrase.create_query(&schema)?,
-                Query::Fuzzy(fuzzy) => fuzzy.create_query(&schema)?,
-                Query::Exact(term) => term.create_query(&schema)?,
-                Query::Range(range) => range.create_query(&schema)?,
-                Query::Boolean { bool } => bool.create_query(&schema)?,
-                Query::Raw { raw } => {
-                    let fields: Vec<Field> = schema.fields().filter_map(|f| schema.get_field(f.1.name())).collect();
-                    let query_parser = QueryParser::for_index(&self.index, fields);
-                    query_parser.parse_query(&raw)?
-                }
-                Query::All => Box::new(AllQuery),
-            };
+        let mut agg_handles = Vec::with_capacity(search.aggs.len());
+        for (name, agg) in &search.aggs {
+            let handle = multi_collector.add_collector(agg.collector(&schema)?);
+            agg_handles.push((name.clone(), handle));
+        }
 
+        if let Some(gen_query) = gen_query {
             trace!("{:?}", gen_query);
+            let query_start = Instant::now();
             let mut scored_docs = searcher.search(&*gen_query, &multi_collector)?;
+            let query_ms = millis(query_start.elapsed());
+            let fetch_start = Instant::now();
 
             // FruitHandle isn't a public type which leads to some duplicate code like this.
-            let docs: Vec<ScoredDoc<FlatNamedDocument>> = if let Some(h) = sorted_top_handle {
+            let ranked: Vec<(Option<Score>, DocAddress)> = if let Some(h) = sorted_top_handle {
                 h.extract(&mut scored_docs)
                     .into_iter()
-                    .map(|(score, doc)| {
-                        let d = searcher.doc(doc).expect("Doc not found in segment");
-                        ScoredDoc::<FlatNamedDocument>::new(Some(score as f32), schema.to_named_doc(&d).into())
-                    })
+                    // The first sort field's value stands in for the score
+                    .map(|(key, doc)| (key.first().and_then(|(_, _, value)| *value), doc))
                     .collect()
+            } else if let Some(h) = doc_order_handle {
+                h.extract(&mut scored_docs).into_iter().map(|doc| (None, doc)).collect()
             } else {
                 top_handle
-                    .extract(&mut scored_docs)
+                    .map(|h| h.extract(&mut scored_docs))
+                    .unwrap_or_default()
                     .into_iter()
-                    .map(|(score, doc)| {
-                        let d = searcher.doc(doc).expect("Doc not found in segment");
-                        ScoredDoc::<FlatNamedDocument>::new(Some(score), schema.to_named_doc(&d).into())
-                    })
+                    .map(|(score, doc)| (Some(score), doc))
                     .collect()
             };
+            // Pages are cut from the distinct values, so their total is what's paginated over
+            let total = count_handle.extract(&mut scored_docs);
+            let (ranked, total) = match &search.collapse {
+                Some(field) if collect_docs => {
+                    let ranked = collapse_ranked(&searcher, ranked, field)?;
+                    let total = ranked.len();
+                    (ranked, total)
+                }
+                _ => (ranked, total),
+            };
+            let docs: Vec<ScoredDoc<FlatNamedDocument>> = ranked
+                .into_iter()
+                .skip(search.offset)
+                .take(search.limit)
+                .map(|(score, doc)| {
+                    let d = load_doc(&searcher, doc, &search, &settings).expect("Doc not found in segment");
+                    ScoredDoc::<FlatNamedDocument>::new(score, d)
+                })
+                .collect();
+            let docs = self.finish_docs(&searcher, docs, &search, gen_query.as_ref(), &settings)?;
+            let fetch_ms = millis(fetch_start.elapsed());
+            let pagination = Pagination::new(search.offset, search.limit, total, docs.len());
+            let term_stats = if search.term_stats {
+                term_stats(&searcher, gen_query.as_ref())?
+            } else {
+                Vec::new()
+            };
+
+            let aggregations: BTreeMap<String, AggregationResult> = agg_handles
+                .into_iter()
+                .map(|(name, handle)| (name, handle.extract(&mut scored_docs)))
+                .collect();
 
             if let Some(facets) = facet_handle {
                 if let Some(t) = &search.facets {
-                    let facet_counts = facets
-                        .extract(&mut scored_docs)
-                        .get(&t.get_facets_values()[0])
-                        .map(|(f, c)| KeyValue::new(f.to_string(), c))
+                    let counts = facets.extract(&mut scored_docs);
+                    let path = t.get_facets_values().swap_remove(0);
+                    let facet_counts: Vec<KeyValue<String, u64>> = match t.get_top_k() {
+                        Some(k) => counts
+                            .top_k(path.as_str(), k)
+                            .into_iter()
+                            .map(|(f, c)| KeyValue::new(f.to_string(), c))
+                            .collect(),
+                        None => counts.get(path.as_str()).map(|(f, c)| KeyValue::new(f.to_string(), c)).collect(),
+                    };
+                    let values = facet_counts
+                        .iter()
+                        .map(|kv| FacetCount {
+                            value: kv.field.clone(),
+                            count: kv.value,
+                        })
                         .collect();
-                    return Ok(SearchResults::with_facets(docs, facet_counts));
+                    let facet_results = vec![FacetResult::new(t.get_facets_fields().into(), values)];
+                    let timing = SearchTiming {
+                        took_ms: millis(start.elapsed()),
+                        query_ms,
+                        fetch_ms,
+                    };
+                    let mut results = SearchResults::with_facets(docs, facet_counts)
+                        .with_facet_results(facet_results)
+                        .with_aggregations(aggregations)
+                        .with_timing(timing)
+                        .with_term_stats(term_stats)
+                        .with_pagination(pagination);
+                    if search.count_only {
+                        results.hits = pagination.total;
+                    }
+                    return Ok(results);
                 }
             }
-            Ok(SearchResults::new(docs))
+            let timing = SearchTiming {
+                took_ms: millis(start.elapsed()),
+                query_ms,
+                fetch_ms,
+            };
+            let mut results = SearchResults::new(docs)
+                .with_aggregations(aggregations)
+                .with_timing(timing)
+                .with_term_stats(term_stats)
+                .with_pagination(pagination);
+            // Counted searches return no documents, so their hits are the matched total instead
+            if search.count_only {
+                results.hits = pagination.total;
+            }
+            Ok(results)
         } else {
             Err(Error::QueryError("Empty Query Provided".into()))
         }
     }
 
     async fn add_document(&self, add_doc: AddDocument) -> Result<()> {
+        // An array is added all or nothing, every document is converted before any of them is queued
+        let values = match add_doc.document {
+            serde_json::Value::Array(values) => values,
+            value => vec![value],
+        };
+        if self.remaining_docs().map_or(false, |remaining| remaining < values.len() as u64) {
+            return Err(Error::QuotaExceeded(
+                self.name.clone(),
+                self.get_settings().max_docs.unwrap_or_default(),
+            ));
+        }
         let index_schema = self.index.schema();
-        let writer_lock = self.get_writer();
+        let strict_mapping = self.get_settings().strict_mapping.unwrap_or(self.strict_mapping);
+        let term_limit = self.term_limit();
+        let mut docs = Vec::with_capacity(values.len());
+        for value in &values {
+            if strict_mapping {
+                let unknown = unknown_fields(&index_schema, value);
+                if !unknown.is_empty() {
+                    let msg = format!("Fields: {} are not in the schema of index: {}", unknown.join(", "), self.name);
+                    return Err(Error::QueryError(msg));
+                }
+            }
+            let mut doc: Document = LocalIndex::parse_doc(&index_schema, &value.to_string())?;
+            if let Some(limit) = term_limit {
+                doc = limit_terms(&self.index, doc, limit)?;
+            }
+            docs.push(doc);
+        }
+        let writer_lock = self.get_writer()?;
+        // Queueing documents only needs a shared reference to the writer, so the lock is held just
+        // long enough to keep a commit from landing in the middle of them
         {
             let index_writer = writer_lock.lock().await;
-            let doc: Document = LocalIndex::parse_doc(&index_schema, &add_doc.document.to_string())?;
-            index_writer.add_document(doc)?;
-        }
-        if let Some(opts) = add_doc.options {
-            if opts.commit {
-                let mut commit_writer = writer_lock.lock().await;
-                commit_writer.commit()?;
-                self.set_opstamp(0);
-            } else {
-                self.set_opstamp(self.get_opstamp() + 1);
+            for doc in docs {
+                index_writer.add_document(doc)?;
             }
-        } else {
-            self.set_opstamp(self.get_opstamp() + 1);
         }
-        Ok(())
+        self.finish_ops(values.len(), add_doc.options.map(|o| o.commit).unwrap_or(false))
+            .await
     }
 
     async fn delete_term(&self, term: DeleteDoc) -> Result<DocsAffected> {
         let index_schema = self.index.schema();
-        let writer_lock = self.get_writer();
+        let writer_lock = self.get_writer()?;
         let before: u64;
         {
             let index_writer = writer_lock.lock().await;
@@ -196,66 +909,403 @@ impl IndexHandle for LocalIndex {
                 }
             }
         }
-        if let Some(opts) = term.options {
-            if opts.commit {
-                let mut commit_writer = writer_lock.lock().await;
-                commit_writer.commit()?;
-                self.set_opstamp(0);
-            }
-        }
+        self.finish_op(term.options.map(|o| o.commit).unwrap_or(false)).await?;
         let docs_affected = before - self.reader.searcher().num_docs();
         let current = self.deleted_docs.load(Ordering::SeqCst);
         self.deleted_docs.store(current + docs_affected, Ordering::SeqCst);
         Ok(DocsAffected { docs_affected })
     }
+
+    async fn delete_by_query(&self, delete: DeleteQuery) -> Result<DocsAffected> {
+        delete.query.validate_complexity(self.max_query_clauses, self.max_query_depth)?;
+        let query = build_query(&self.index, delete.query, false, &self.get_settings())?;
+        let writer_lock = self.get_writer()?;
+        let docs_affected: u64;
+        if delete.options.map(|o| o.commit).unwrap_or(false) {
+            // Pending writes are committed first, so the documents counted are the ones that are
+            // actually in the index before and after the delete
+            let mut index_writer = writer_lock.lock().await;
+            index_writer.commit()?;
+            self.reader.reload()?;
+            let before = self.reader.searcher().num_docs();
+            index_writer.delete_query(query)?;
+            index_writer.commit()?;
+            self.set_opstamp(0);
+            self.reader.reload()?;
+            docs_affected = before.saturating_sub(self.reader.searcher().num_docs());
+        } else {
+            {
+                let index_writer = writer_lock.lock().await;
+                // Only an estimate from the last commit, documents still pending aren't seen by it
+                docs_affected = self.reader.searcher().search(query.as_ref(), &Count)? as u64;
+                index_writer.delete_query(query)?;
+            }
+            self.finish_op(false).await?;
+        }
+        self.deleted_docs.fetch_add(docs_affected, Ordering::SeqCst);
+        Ok(DocsAffected { docs_affected })
+    }
+
+    fn get_settings(&self) -> IndexSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    async fn set_settings(&self, mut settings: IndexSettings) -> Result<()> {
+        let schema = self.index.schema();
+        if let Some(id_field) = &settings.id_field {
+            let field = schema
+                .get_field(id_field)
+                .ok_or_else(|| Error::UnknownIndexField(id_field.clone()))?;
+            if !schema.get_field_entry(field).is_stored() {
+                return Err(Error::QueryError(format!("Id field: {} must be stored", id_field)));
+            }
+        }
+        if let Some(duration) = settings.auto_commit_duration.filter(|d| !(*d > 0.0 && d.is_finite())) {
+            return Err(Error::QueryError(format!(
+                "Auto commit duration: {} must be a positive number of seconds",
+                duration
+            )));
+        }
+        if let Some(unknown) = settings.default_operators.keys().find(|f| schema.get_field(f).is_none()) {
+            return Err(Error::UnknownIndexField(unknown.clone()));
+        }
+        settings.frozen = self.is_frozen();
+        if let Some(path) = &self.settings_path {
+            fs::write(path, serde_json::to_vec(&settings)?)?;
+        }
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    fn warmup(&self) -> Result<usize> {
+        let searcher = self.reader.searcher();
+        searcher.search(&AllQuery, &Count)?;
+        let schema = self.index.schema();
+        for segment in searcher.segment_readers() {
+            for (field, entry) in schema.fields() {
+                if entry.is_indexed() {
+                    segment.inverted_index(field)?;
+                }
+                if entry.is_fast() {
+                    // Reading every value pulls the column into the page cache. Multivalued fields
+                    // can't be opened as a single column and are skipped.
+                    let fast_fields = segment.fast_fields();
+                    let _ = match entry.field_type() {
+                        FieldType::U64(_) => fast_fields.u64(field).map(|c| c.iter().count()),
+                        FieldType::I64(_) => fast_fields.i64(field).map(|c| c.iter().count()),
+                        FieldType::F64(_) => fast_fields.f64(field).map(|c| c.iter().count()),
+                        FieldType::Date(_) => fast_fields.date(field).map(|c| c.iter().count()),
+                        _ => Ok(0),
+                    };
+                }
+                if let FieldType::Facet(_) = entry.field_type() {
+                    segment.facet_reader(field)?;
+                }
+            }
+        }
+        Ok(searcher.segment_readers().len())
+    }
+
+    async fn recreate_writer(&self) -> Result<()> {
+        let writer = self.get_writer()?;
+        let mut writer = writer.lock().await;
+        // Rolling back rebuilds the writer from the last commit, but with Tantivy's default merge policy
+        let merge_policy = writer.get_merge_policy();
+        writer.rollback()?;
+        writer.set_merge_policy(Box::new(SharedMergePolicy(merge_policy)));
+        self.set_opstamp(0);
+        info!("Recreated writer for: {}", self.name);
+        Ok(())
+    }
+
+    async fn freeze(&self) -> Result<()> {
+        let _transition = self.transition.lock().await;
+        let writer = {
+            let mut state = self.writer.write().unwrap();
+            let merge_policy = match &*state {
+                // Writes that already took the writer would keep using it after the index is frozen
+                WriterState::Open(writer) if Arc::strong_count(writer) > 1 => {
+                    return Err(Error::WritesInProgress(self.name.clone()));
+                }
+                WriterState::Open(writer) => match writer.try_lock() {
+                    Ok(writer) => writer.get_merge_policy(),
+                    Err(_) => return Err(Error::WritesInProgress(self.name.clone())),
+                },
+                WriterState::Frozen(_) => return Ok(()),
+            };
+            // Nothing else can take the writer once the state is swapped, so it's only held here
+            match std::mem::replace(&mut *state, WriterState::Frozen(merge_policy)) {
+                WriterState::Open(writer) => Arc::try_unwrap(writer)
+                    .map_err(|_| Error::WritesInProgress(self.name.clone()))?
+                    .into_inner(),
+                WriterState::Frozen(_) => unreachable!("Index was checked to be open"),
+            }
+        };
+        let mut writer = writer;
+        if let Err(e) = writer.commit() {
+            *self.writer.write().unwrap() = WriterState::Open(Arc::new(Mutex::new(writer)));
+            return Err(e.into());
+        }
+        self.set_opstamp(0);
+        // Waiting on merges drops the writer and with it the directory lock a thawed writer needs
+        writer.wait_merging_threads()?;
+        self.persist_frozen(true)?;
+        info!("Froze index: {}", self.name);
+        Ok(())
+    }
+
+    async fn thaw(&self) -> Result<()> {
+        let _transition = self.transition.lock().await;
+        let merge_policy = match &*self.writer.read().unwrap() {
+            WriterState::Frozen(merge_policy) => Arc::clone(merge_policy),
+            WriterState::Open(_) => return Ok(()),
+        };
+        // Built before the state is locked so searches and writes checking it aren't held up
+        let writer = self.index.writer(self.writer_memory)?;
+        writer.set_merge_policy(Box::new(SharedMergePolicy(merge_policy)));
+        *self.writer.write().unwrap() = WriterState::Open(Arc::new(Mutex::new(writer)));
+        self.persist_frozen(false)?;
+        info!("Thawed index: {}", self.name);
+        Ok(())
+    }
+
+    fn is_frozen(&self) -> bool {
+        matches!(&*self.writer.read().unwrap(), WriterState::Frozen(_))
+    }
+
+    fn term_limit(&self) -> Option<TermLimit> {
+        let settings = self.get_settings();
+        let max_length = settings.max_term_length.unwrap_or(self.max_term_length);
+        (max_length > 0).then(|| TermLimit {
+            max_length,
+            truncate: settings.truncate_long_terms.unwrap_or(self.truncate_long_terms),
+        })
+    }
+}
+
+/// An index's writer, or the merge policy to give a new one once a frozen index is thawed. Whether
+/// an index is frozen is also kept in its settings, so it's still frozen once it's reopened.
+enum WriterState {
+    Open(Arc<Mutex<IndexWriter>>),
+    Frozen(Arc<dyn MergePolicy>),
+}
+
+/// Lets a merge policy taken from one writer be handed to another
+#[derive(Debug)]
+struct SharedMergePolicy(Arc<dyn MergePolicy>);
+
+impl MergePolicy for SharedMergePolicy {
+    fn compute_merge_candidates(&self, segments: &[SegmentMeta]) -> Vec<MergeCandidate> {
+        self.0.compute_merge_candidates(segments)
+    }
 }
 
 impl LocalIndex {
-    pub fn new(
-        mut base_path: PathBuf,
-        index_name: &str,
-        schema: Schema,
-        writer_memory: usize,
-        merge_policy: Box<dyn MergePolicy>,
-    ) -> Result<Self> {
-        base_path.push(index_name);
+    pub fn new(mut base_path: PathBuf, index_name: &str, schema: Schema, settings: &Settings) -> Result<Self> {
+        base_path.push(settings.index_dir_name(index_name));
         if !base_path.exists() {
             fs::create_dir(&base_path)?;
         }
+        let settings_path = base_path.join(INDEX_SETTINGS_FILE);
+        let index_settings = read_index_settings(&settings_path)?;
         let dir = MmapDirectory::open(base_path)?;
         let index = Index::open_or_create(dir, schema)?;
-        let index = register_tokenizers(index);
-        let i = index.writer(writer_memory)?;
-        i.set_merge_policy(merge_policy);
+        let mut index = register_tokenizers(index);
+        // Segments are searched one after another on the calling thread unless given a pool
+        if settings.search_threads > 0 {
+            index.set_multithread_executor(settings.search_threads)?;
+        }
+        // A frozen index isn't given a writer, which would also hold its directory's lock
+        let state = if index_settings.frozen {
+            WriterState::Frozen(Arc::from(settings.get_merge_policy()))
+        } else {
+            let i = index.writer(settings.writer_memory)?;
+            i.set_merge_policy(settings.get_merge_policy());
+            WriterState::Open(Arc::new(Mutex::new(i)))
+        };
         let current_opstamp = Arc::new(AtomicUsize::new(0));
-        let writer = Arc::new(Mutex::new(i));
+        let writer = Arc::new(std::sync::RwLock::new(state));
         let reader = index.reader_builder().reload_policy(ReloadPolicy::OnCommit).try_into()?;
         Ok(Self {
             index,
             reader,
             writer,
+            writer_memory: settings.writer_memory,
+            transition: Arc::default(),
             current_opstamp,
             deleted_docs: Arc::new(AtomicU64::new(0)),
             name: index_name.into(),
+            settings: Arc::new(std::sync::RwLock::new(index_settings)),
+            settings_path: Some(settings_path),
+            max_pending_ops: settings.max_pending_ops,
+            search_permits: (settings.max_concurrent_searches > 0).then(|| Arc::new(Semaphore::new(settings.max_concurrent_searches))),
+            max_query_clauses: settings.max_query_clauses,
+            max_query_depth: settings.max_query_depth,
+            strict_mapping: settings.strict_mapping,
+            max_term_length: settings.max_term_length,
+            truncate_long_terms: settings.truncate_long_terms,
         })
     }
 
     pub(crate) fn from_existing(name: String, index: Index) -> Result<Self> {
+        let index = register_tokenizers(index);
         let i = index.writer(DEFAULT_WRITER_MEMORY)?;
         i.set_merge_policy(Settings::default().get_merge_policy());
         let current_opstamp = Arc::new(AtomicUsize::new(0));
-        let writer = Arc::new(Mutex::new(i));
+        let writer = Arc::new(std::sync::RwLock::new(WriterState::Open(Arc::new(Mutex::new(i)))));
         let reader = index.reader_builder().reload_policy(ReloadPolicy::OnCommit).try_into()?;
         Ok(Self {
             index,
             reader,
             writer,
+            writer_memory: DEFAULT_WRITER_MEMORY,
+            transition: Arc::default(),
             current_opstamp,
             deleted_docs: Arc::new(AtomicU64::new(0)),
             name,
+            settings: Arc::new(std::sync::RwLock::new(IndexSettings::default())),
+            settings_path: None,
+            max_pending_ops: Settings::default().max_pending_ops,
+            search_permits: None,
+            max_query_clauses: Settings::default().max_query_clauses,
+            max_query_depth: Settings::default().max_query_depth,
+            strict_mapping: Settings::default().strict_mapping,
+            max_term_length: Settings::default().max_term_length,
+            truncate_long_terms: Settings::default().truncate_long_terms,
+        })
+    }
+
+    /// Record whether the index is frozen in its settings, so it's reopened the same way
+    fn persist_frozen(&self, frozen: bool) -> Result<()> {
+        let mut settings = self.settings.write().unwrap();
+        let updated = IndexSettings {
+            frozen,
+            ..settings.clone()
+        };
+        if let Some(path) = &self.settings_path {
+            fs::write(path, serde_json::to_vec(&updated)?)?;
+        }
+        *settings = updated;
+        Ok(())
+    }
+
+    /// Whether anything besides this handle holds a clone of it, every clone shares the same writer
+    pub(crate) fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.writer) > 1
+    }
+
+    /// Permits that tests hold on to in place of searches that are still running
+    #[cfg(test)]
+    pub(crate) fn search_permits(&self) -> Option<&Arc<Semaphore>> {
+        self.search_permits.as_ref()
+    }
+
+    /// Annotate and identify the documents a search is returning
+    fn finish_docs(
+        &self,
+        searcher: &Searcher,
+        docs: Vec<ScoredDoc<FlatNamedDocument>>,
+        search: &Search,
+        query: &dyn TantivyQuery,
+        settings: &IndexSettings,
+    ) -> Result<Vec<ScoredDoc<FlatNamedDocument>>> {
+        let docs = if search.return_match_offsets {
+            let terms = query_term_texts(query);
+            docs.into_iter()
+                .map(|d| {
+                    let offsets = match_offsets(&self.index, &terms, &d.doc)?;
+                    Ok(d.with_match_offsets(offsets))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            docs
+        };
+        let docs = match &search.highlight {
+            Some(options) => {
+                let generators = snippet_generators(searcher, query, options);
+                docs.into_iter()
+                    .map(|d| {
+                        let highlights = highlights(&generators, &d.doc);
+                        d.with_highlights(highlights)
+                    })
+                    .collect()
+            }
+            None => docs,
+        };
+        Ok(match &settings.id_field {
+            Some(id_field) => docs
+                .into_iter()
+                .map(|d| {
+                    let id = doc_id(&d.doc, id_field);
+                    d.with_id(id)
+                })
+                .collect(),
+            None => docs,
         })
     }
 
+    /// Search for only the first `matches` documents in index order, nothing past them is visited
+    /// so the total in the results is a lower bound of how many documents match
+    fn search_first_matches(
+        &self,
+        searcher: &Searcher,
+        search: &Search,
+        query: &dyn TantivyQuery,
+        matches: usize,
+        settings: &IndexSettings,
+        start: Instant,
+    ) -> Result<SearchResults> {
+        let query_start = Instant::now();
+        let found = first_matches(searcher, query, matches)?;
+        let query_ms = millis(query_start.elapsed());
+        let fetch_start = Instant::now();
+        let docs = found
+            .iter()
+            .skip(search.offset)
+            .take(search.limit)
+            .map(|doc| {
+                Ok(ScoredDoc::<FlatNamedDocument>::new(
+                    None,
+                    load_doc(searcher, *doc, search, settings)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let docs = self.finish_docs(searcher, docs, search, query, settings)?;
+        let fetch_ms = millis(fetch_start.elapsed());
+        let pagination = Pagination::new(search.offset, search.limit, found.len(), docs.len());
+        let term_stats = if search.term_stats {
+            term_stats(searcher, query)?
+        } else {
+            Vec::new()
+        };
+        let timing = SearchTiming {
+            took_ms: millis(start.elapsed()),
+            query_ms,
+            fetch_ms,
+        };
+        Ok(SearchResults::new(docs)
+            .with_timing(timing)
+            .with_term_stats(term_stats)
+            .with_pagination(pagination))
+    }
+
+    /// Count an operation as pending, committing if asked to or if `max_pending_ops` is reached
+    async fn finish_op(&self, commit: bool) -> Result<()> {
+        self.finish_ops(1, commit).await
+    }
+
+    async fn finish_ops(&self, ops: usize, commit: bool) -> Result<()> {
+        // Counted atomically since concurrent adds can finish at the same time
+        let pending = self.current_opstamp.fetch_add(ops, Ordering::SeqCst) + ops;
+        if commit || (self.max_pending_ops > 0 && pending >= self.max_pending_ops) {
+            let writer = self.get_writer()?;
+            let mut commit_writer = writer.lock().await;
+            commit_writer.commit()?;
+            self.set_opstamp(0);
+        }
+        Ok(())
+    }
+
     fn parse_doc(schema: &Schema, bytes: &str) -> Result<Document> {
         schema.parse_document(bytes).map_err(Into::into)
     }
@@ -1,32 +1,107 @@
 use bytes::BytesMut;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
-use flume::{unbounded, Receiver, Sender};
+use flume::{bounded, unbounded, Receiver, Sender};
 use futures::StreamExt;
-use hyper::Body;
 use hyper::StatusCode;
+use hyper::{Body, Response};
 
 use log::*;
-use tantivy::schema::Schema;
-use tantivy::{Document, IndexWriter};
+use tantivy::schema::{Field, Value};
+use tantivy::{Document, Index, IndexWriter, Term};
 use tokio::sync::Mutex;
-use tokio::time::timeout;
 use tokio_util::codec::{Decoder, LinesCodec, LinesCodecError};
 
-use toshi_types::{Catalog, Error, IndexHandle};
+use toshi_types::{Catalog, Error, IndexHandle, QueryOptions, TermLimit};
 
+use crate::handle::limit_terms;
 use crate::handlers::ResponseFuture;
+use crate::settings::Settings;
+use crate::tasks::Task;
 use crate::utils::{empty_with_code, error_response, not_found};
 
-const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+/// The term identifying a document by its first value for `field`
+fn upsert_term(doc: &Document, field: Field) -> Option<Term> {
+    match doc.get_first(field)? {
+        Value::Str(s) => Some(Term::from_field_text(field, s)),
+        Value::U64(v) => Some(Term::from_field_u64(field, *v)),
+        Value::I64(v) => Some(Term::from_field_i64(field, *v)),
+        Value::F64(v) => Some(Term::from_field_f64(field, *v)),
+        _ => None,
+    }
+}
+
+/// How the lines of a bulk insert are read and parsed, taken from the server's [`Settings`]
+#[derive(Debug, Clone, Copy)]
+pub struct BulkOptions {
+    /// Number of tasks parsing lines into documents
+    pub num_threads: usize,
+    /// The longest line accepted, 0 accepts lines of any length
+    pub max_line_length: usize,
+    /// Lines and parsed documents buffered at a time, 0 leaves them unbounded
+    pub buffer_size: usize,
+}
+
+impl From<&Settings> for BulkOptions {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            num_threads: settings.json_parsing_threads,
+            max_line_length: settings.max_line_length,
+            buffer_size: settings.bulk_buffer_size,
+        }
+    }
+}
+
+/// The documents a bulk insert may add before its index reaches `max_docs`
+struct DocQuota {
+    remaining: u64,
+    max_docs: u64,
+}
+
+/// How parsed documents are added to the index
+struct Indexing {
+    commit_every: Option<usize>,
+    upsert_field: Option<Field>,
+    quota: Option<DocQuota>,
+}
 
-async fn index_documents(iw: Arc<Mutex<IndexWriter>>, dr: Receiver<Document>, wr: Arc<AtomicBool>) -> Result<(), Error> {
+async fn index_documents(
+    iw: Arc<Mutex<IndexWriter>>,
+    dr: Receiver<Document>,
+    wr: Arc<AtomicBool>,
+    indexing: Indexing,
+    index: &str,
+    task: &Task,
+) -> Result<(), Error> {
+    let Indexing {
+        commit_every,
+        upsert_field,
+        quota,
+    } = indexing;
     let start = Instant::now();
-    while let Ok(Ok(doc)) = timeout(DEFAULT_TIMEOUT, dr.recv_async()).await {
-        let w = iw.lock().await;
+    let mut indexed = 0;
+    while let Ok(doc) = dr.recv_async().await {
+        if task.is_cancelled() {
+            break;
+        }
+        if let Some(quota) = quota.as_ref().filter(|q| indexed as u64 >= q.remaining) {
+            wr.store(false, Ordering::SeqCst);
+            return Err(Error::QuotaExceeded(index.into(), quota.max_docs));
+        }
+        let mut w = iw.lock().await;
+        // Deletes only apply to documents added before them, so the replacement itself is kept
+        if let Some(term) = upsert_field.and_then(|f| upsert_term(&doc, f)) {
+            w.delete_term(term);
+        }
         w.add_document(doc)?;
+        indexed += 1;
+        task.indexed(1);
+        if let Some(n) = commit_every.filter(|n| *n > 0 && indexed % n == 0) {
+            debug!("Committing after {} documents, every {}", indexed, n);
+            w.commit()?;
+        }
     }
 
     info!("Piping Documents took: {:?}", start.elapsed());
@@ -34,16 +109,53 @@ async fn index_documents(iw: Arc<Mutex<IndexWriter>>, dr: Receiver<Document>, wr
     Ok(())
 }
 
-async fn parsing_documents(s: Schema, ds: Sender<Document>, lr: Receiver<String>, ec: Sender<Error>) -> Result<(), ()> {
-    while let Ok(Ok(line)) = timeout(DEFAULT_TIMEOUT, lr.recv_async()).await {
+/// The first `max` characters of a rejected line, so it can be found in the source
+fn truncate_line(line: &str, max: usize) -> String {
+    match line.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+async fn parsing_documents(
+    i: Index,
+    ds: Sender<Document>,
+    lr: Receiver<String>,
+    ec: Sender<Error>,
+    rejected_line_length: Option<usize>,
+    term_limit: Option<TermLimit>,
+) -> Result<(), ()> {
+    let s = i.schema();
+    while let Ok(line) = lr.recv_async().await {
         if !line.is_empty() {
             match s.parse_document(&line) {
                 Ok(doc) => {
+                    let limited = match term_limit {
+                        Some(limit) => limit_terms(&i, doc, limit),
+                        None => Ok(doc),
+                    };
+                    let doc = match limited {
+                        Ok(doc) => doc,
+                        Err(e) => {
+                            ec.send_async(e).await.expect("Parsing thread loop failed.");
+                            break;
+                        }
+                    };
                     info!("Piped document... {}", doc.len());
-                    ds.send_async(doc).await.expect("Parsing Thread failed.");
+                    // The indexer only stops early once the bulk insert has already failed
+                    if ds.send_async(doc).await.is_err() {
+                        break;
+                    }
                 }
                 Err(e) => {
-                    let err = anyhow::Error::msg("Error parsing document").context(line).context(e);
+                    let err = match rejected_line_length {
+                        Some(max) => anyhow::Error::msg(format!(
+                            "Error parsing document: {}, rejected line: {}",
+                            e,
+                            truncate_line(&line, max)
+                        )),
+                        None => anyhow::Error::msg("Error parsing document").context(line).context(e),
+                    };
                     ec.send_async(Error::TantivyError(err)).await.expect("Parsing thread loop failed.");
                     break;
                 }
@@ -53,37 +165,33 @@ async fn parsing_documents(s: Schema, ds: Sender<Document>, lr: Receiver<String>
     Ok(())
 }
 
-pub async fn bulk_insert<C: Catalog>(
-    catalog: Arc<C>,
-    watcher: Arc<AtomicBool>,
-    mut body: Body,
-    index: &str,
-    num_threads: usize,
-    max_line_length: usize,
-) -> ResponseFuture {
-    if !catalog.exists(index) {
-        return not_found().await;
+/// A channel holding at most `size` messages so a slow consumer holds back its producer, 0 leaves
+/// it unbounded
+fn bulk_channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    if size > 0 {
+        bounded(size)
+    } else {
+        unbounded()
     }
-    watcher.store(true, Ordering::SeqCst);
-    let index_handle = catalog.get_index(index).unwrap();
-    let writer = index_handle.get_writer();
-    let i = index_handle.get_index();
-    let schema = i.schema();
+}
 
-    let (line_sender, line_recv) = unbounded::<String>();
-    let (doc_sender, doc_recv) = unbounded::<Document>();
-    let (err_snd, err_rcv) = unbounded();
+async fn rollback(writer: &Mutex<IndexWriter>, index: &str) {
+    writer
+        .lock()
+        .await
+        .rollback()
+        .unwrap_or_else(|e| panic!("Error rolling back index: {}, this should be reported as a bug. {}", index, e));
+}
 
-    info!("Spawning {} parsing threads...", num_threads);
-    let mut parsing_handles = Vec::with_capacity(num_threads);
-    for _ in 0..num_threads {
-        let schema = schema.clone();
-        let doc_sender = doc_sender.clone();
-        let line_recv = line_recv.clone();
-        let err_snd = err_snd.clone();
-        parsing_handles.push(tokio::spawn(parsing_documents(schema, doc_sender, line_recv, err_snd)));
-    }
-    info!("Spawned threads finished...");
+/// Split the body into lines for the parsers, stopping early once the bulk insert is cancelled or a
+/// parser has failed
+async fn read_lines(
+    mut body: Body,
+    line_sender: Sender<String>,
+    errors: &Receiver<Error>,
+    max_line_length: usize,
+    task: &Task,
+) -> Result<(), Error> {
     let mut buf = BytesMut::new();
     let mut decoder = if max_line_length > 0 {
         LinesCodec::new_with_max_length(max_line_length)
@@ -91,14 +199,25 @@ pub async fn bulk_insert<C: Catalog>(
         LinesCodec::new()
     };
 
-    while let Some(Ok(line)) = body.next().await {
+    loop {
+        let line = tokio::select! {
+            line = body.next() => line,
+            _ = task.token().cancelled() => break,
+        };
+        let line = match line {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
         buf.extend_from_slice(&line);
 
         loop {
             match decoder.decode_eof(&mut buf) {
                 Ok(Some(l)) if !l.is_empty() => {
-                    let l = l.trim();
-                    line_sender.send_async(l.into()).await.unwrap();
+                    // Every parser is gone once one fails with a single thread
+                    if !errors.is_empty() || line_sender.send_async(l.trim().into()).await.is_err() {
+                        return Ok(());
+                    }
+                    task.received(1);
                 }
                 Ok(None) | Ok(Some(_)) => break,
                 Err(LinesCodecError::MaxLineLengthExceeded) => {
@@ -106,30 +225,137 @@ pub async fn bulk_insert<C: Catalog>(
                         "Line exceeded max length of {}, you can increase this with the max_line_length config option",
                         max_line_length
                     );
-                    let err_msg = anyhow::Error::msg(err_txt);
-                    return Ok(error_response(StatusCode::BAD_REQUEST, Error::TantivyError(err_msg)));
+                    return Err(Error::TantivyError(anyhow::Error::msg(err_txt)));
                 }
                 Err(err) => {
                     let err_msg = anyhow::Error::msg("Error with codec.").context(err);
-                    return Ok(error_response(StatusCode::BAD_REQUEST, Error::TantivyError(err_msg)));
+                    return Err(Error::TantivyError(err_msg));
                 }
             }
         }
     }
+    Ok(())
+}
+
+/// Roll back whatever a cancelled bulk insert left uncommitted
+async fn cancel_bulk(writer: Arc<Mutex<IndexWriter>>, watcher: Arc<AtomicBool>, index: &str, task: &Task) -> ResponseFuture {
+    info!("Bulk insert task {} into {} was cancelled", task.id(), index);
+    rollback(&writer, index).await;
+    watcher.store(false, Ordering::SeqCst);
+    let err = Error::QueryError(format!("Bulk insert task {} was cancelled", task.id()));
+    Ok(error_response(StatusCode::BAD_REQUEST, err))
+}
+
+/// Stream newline delimited documents into an index, reporting progress to and stopping early
+/// when cancelled through `task`. Lines and parsed documents are buffered `bulk.buffer_size` at a time
+/// so reading the body waits on slower parsing and indexing rather than piling up in memory.
+pub async fn bulk_insert<C: Catalog>(
+    catalog: Arc<C>,
+    watcher: Arc<AtomicBool>,
+    body: Body,
+    index: &str,
+    bulk: BulkOptions,
+    options: &QueryOptions,
+    task: Task,
+) -> ResponseFuture {
+    let BulkOptions {
+        num_threads,
+        max_line_length,
+        buffer_size,
+    } = bulk;
+    if !catalog.exists(index) {
+        return not_found().await;
+    }
+    let index_handle = catalog.get_index(index).await.unwrap();
+    let writer = match index_handle.get_writer() {
+        Ok(writer) => writer,
+        Err(e) => return Ok(Response::from(e)),
+    };
+    let i = index_handle.get_index();
+    let schema = i.schema();
+    let upsert_field = match options.upsert_field() {
+        Some(name) => match schema.get_field(name) {
+            Some(f) if schema.get_field_entry(f).is_indexed() => Some(f),
+            Some(_) => {
+                let err = Error::QueryError(format!("Upsert field: {} must be indexed", name));
+                return Ok(error_response(StatusCode::BAD_REQUEST, err));
+            }
+            None => return Ok(error_response(StatusCode::BAD_REQUEST, Error::UnknownIndexField(name.into()))),
+        },
+        None => None,
+    };
+    let quota = index_handle.remaining_docs().map(|remaining| DocQuota {
+        remaining,
+        max_docs: index_handle.get_settings().max_docs.unwrap_or_default(),
+    });
+    if let Some(DocQuota { remaining: 0, max_docs }) = quota {
+        return Ok(Response::from(Error::QuotaExceeded(index.into(), max_docs)));
+    }
+    watcher.store(true, Ordering::SeqCst);
+
+    let (line_sender, line_recv) = bulk_channel::<String>(buffer_size);
+    let (doc_sender, doc_recv) = bulk_channel::<Document>(buffer_size);
+    let (err_snd, err_rcv) = unbounded();
+
+    info!("Spawning {} parsing threads...", num_threads);
+    let mut parsing_handles = Vec::with_capacity(num_threads);
+    let term_limit = index_handle.term_limit();
+    for _ in 0..num_threads {
+        let i = i.clone();
+        let doc_sender = doc_sender.clone();
+        let line_recv = line_recv.clone();
+        let err_snd = err_snd.clone();
+        let rejected_line_length = options.rejected_line_length();
+        parsing_handles.push(tokio::spawn(parsing_documents(
+            i,
+            doc_sender,
+            line_recv,
+            err_snd,
+            rejected_line_length,
+            term_limit,
+        )));
+    }
+    info!("Spawned threads finished...");
+    // Only the parsers may hold on to these, so each side sees the other finish
+    drop((doc_sender, line_recv));
 
+    let (read, indexed) = tokio::join!(
+        read_lines(body, line_sender, &err_rcv, max_line_length, &task),
+        index_documents(
+            Arc::clone(&writer),
+            doc_recv,
+            Arc::clone(&watcher),
+            Indexing {
+                commit_every: options.commit_every(),
+                upsert_field,
+                quota,
+            },
+            index,
+            &task,
+        )
+    );
     futures::future::join_all(parsing_handles).await;
+    if task.is_cancelled() {
+        return cancel_bulk(writer, watcher, index, &task).await;
+    }
+    if let Err(err) = read {
+        rollback(&writer, index).await;
+        return Ok(error_response(StatusCode::BAD_REQUEST, err));
+    }
     if !err_rcv.is_empty() {
-        let mut iw = writer.lock().await;
-        iw.rollback()
-            .unwrap_or_else(|e| panic!("Error rolling back index: {}, this should be reported as a bug. {}", index, e));
+        rollback(&writer, index).await;
         match err_rcv.recv_async().await {
             Ok(err) => return Ok(error_response(StatusCode::BAD_REQUEST, err)),
             Err(err) => panic!("Panic receiving error: {:?}", err),
         }
     }
 
-    match index_documents(writer, doc_recv, Arc::clone(&watcher)).await {
+    match indexed {
         Ok(_) => Ok(empty_with_code(StatusCode::CREATED)),
+        Err(err @ Error::QuotaExceeded(..)) => {
+            rollback(&writer, index).await;
+            Ok(Response::from(err))
+        }
         Err(err) => Ok(error_response(StatusCode::BAD_REQUEST, err)),
     }
 }
@@ -138,14 +364,19 @@ pub async fn bulk_insert<C: Catalog>(
 mod tests {
     use std::time::Duration;
 
-    use crate::commit::tests::read_body;
-    use crate::handlers::all_docs;
+    use crate::commit::tests::{read_body, wait_until};
     use crate::handlers::summary::flush;
+    use crate::handlers::{all_docs, doc_search};
     use crate::index::create_test_catalog;
+    use crate::tasks::TaskRegistry;
     use crate::SearchResults;
 
     use super::*;
 
+    fn bulk_task() -> Task {
+        Arc::new(TaskRegistry::default()).register("bulk", "test")
+    }
+
     #[tokio::test]
     async fn test_bulk_index() -> Result<(), Box<dyn std::error::Error>> {
         let server = create_test_catalog("test_index_bulk");
@@ -155,7 +386,20 @@ mod tests {
         {"test_text": "asdf5678", "test_i64": 456, "test_u64": 678, "test_unindex": "asdf", "test_facet": "/cat/cat4"}
         {"test_text": "asdf9012", "test_i64": -12, "test_u64": 901, "test_unindex": "asdf", "test_facet": "/cat/cat4"}"#;
 
-        let index_docs = bulk_insert(Arc::clone(&server), lock, Body::from(body), "test_index_bulk", 2, 2048).await?;
+        let index_docs = bulk_insert(
+            Arc::clone(&server),
+            lock,
+            Body::from(body),
+            "test_index_bulk",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 10,
+            },
+            &QueryOptions::default(),
+            bulk_task(),
+        )
+        .await?;
         assert_eq!(index_docs.status(), StatusCode::CREATED);
 
         let f = flush(Arc::clone(&server), "test_index_bulk").await?;
@@ -171,6 +415,79 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_bulk_backpressure() -> Result<(), Box<dyn std::error::Error>> {
+        let (sender, _) = bulk_channel::<String>(4);
+        assert_eq!(sender.capacity(), Some(4));
+        let (sender, _) = bulk_channel::<String>(0);
+        assert_eq!(sender.capacity(), None);
+
+        let server = create_test_catalog("test_index_bulk");
+        let body: String = (0..2000)
+            .map(|i| {
+                format!(
+                    r#"{{"test_text": "asdf{}", "test_i64": {}, "test_u64": {}, "test_unindex": "asdf"}}"#,
+                    i, i, i
+                ) + "\n"
+            })
+            .collect();
+        let index_docs = bulk_insert(
+            Arc::clone(&server),
+            Arc::new(AtomicBool::new(false)),
+            Body::from(body),
+            "test_index_bulk",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 4,
+            },
+            &QueryOptions::default(),
+            bulk_task(),
+        )
+        .await?;
+        assert_eq!(index_docs.status(), StatusCode::CREATED);
+
+        flush(Arc::clone(&server), "test_index_bulk").await?;
+        let handle = server.get_index("test_index_bulk").await?;
+        assert_eq!(wait_until(|| async { handle.num_docs() }, |&docs| docs == 2005).await, 2005);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulk_commit_every() -> Result<(), Box<dyn std::error::Error>> {
+        let server = create_test_catalog("test_index_bulk");
+        let lock = Arc::new(AtomicBool::new(false));
+
+        let body = r#"{"test_text": "asdf1234", "test_i64": 123, "test_u64": 321, "test_unindex": "asdf", "test_facet": "/cat/cat4"}
+        {"test_text": "asdf5678", "test_i64": 456, "test_u64": 678, "test_unindex": "asdf", "test_facet": "/cat/cat4"}"#;
+
+        let options = serde_urlencoded::from_str("commit_every=1")?;
+        let index_docs = bulk_insert(
+            Arc::clone(&server),
+            lock,
+            Body::from(body),
+            "test_index_bulk",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 10,
+            },
+            &options,
+            bulk_task(),
+        )
+        .await?;
+        assert_eq!(index_docs.status(), StatusCode::CREATED);
+
+        // No flush, each document was committed as it was indexed
+        std::thread::sleep(Duration::from_secs(1));
+        let check_docs = all_docs(Arc::clone(&server), "test_index_bulk").await?;
+        let body: String = read_body(check_docs).await?;
+        let docs: SearchResults = serde_json::from_slice(body.as_bytes())?;
+
+        assert_eq!(docs.hits, 7);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_errors() -> Result<(), Box<dyn std::error::Error>> {
         let server = create_test_catalog("test_index");
@@ -180,11 +497,155 @@ mod tests {
         {"test_text": "asdf5678", "test_i64": 456, "test_u64": 678, "test_unindex": "asdf", "test_facet": "/cat/cat4"}
         {"test_text": "asdf9012", "test_i64": -12, "test_u64": -9, "test_unindex": "asdf", "test_facet": "/cat/cat4"}"#;
 
-        let index_docs = bulk_insert(Arc::clone(&server), lock, Body::from(body), "test_index", 2, 2048).await?;
+        let index_docs = bulk_insert(
+            Arc::clone(&server),
+            lock,
+            Body::from(body),
+            "test_index",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 10,
+            },
+            &QueryOptions::default(),
+            bulk_task(),
+        )
+        .await?;
         assert_eq!(index_docs.status(), StatusCode::BAD_REQUEST);
 
         let body = read_body(index_docs).await?;
         println!("{}", body);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_rejected_line() -> Result<(), Box<dyn std::error::Error>> {
+        let server = create_test_catalog("test_index");
+        let lock = Arc::new(AtomicBool::new(false));
+
+        let body = r#"{"test_text": "asdf1234", "test_i64": 123, "test_u64": 321, "test_unindex": "asdf", "test_facet": "/cat/cat4"}
+        {"test_text": "asdf9012", "test_i64": -12, "test_u64": -9, "test_unindex": "asdf", "test_facet": "/cat/cat4"}"#;
+        let options = serde_urlencoded::from_str("rejected_line_length=40")?;
+
+        let index_docs = bulk_insert(
+            Arc::clone(&server),
+            lock,
+            Body::from(body),
+            "test_index",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 10,
+            },
+            &options,
+            bulk_task(),
+        )
+        .await?;
+        assert_eq!(index_docs.status(), StatusCode::BAD_REQUEST);
+
+        let body = read_body(index_docs).await?;
+        let err: toshi_types::ErrorResponse = serde_json::from_str(&body)?;
+        assert!(err.message.contains("test_u64"), "{}", err.message);
+        assert!(
+            err.message
+                .contains(r#"rejected line: {"test_text": "asdf9012", "test_i64": -1..."#),
+            "{}",
+            err.message
+        );
+        assert!(!err.message.contains("test_facet"), "{}", err.message);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulk_max_docs() -> Result<(), Box<dyn std::error::Error>> {
+        let server = create_test_catalog("test_index_bulk");
+        let handle = server.get_index("test_index_bulk").await?;
+        let settings = toshi_types::IndexSettings {
+            max_docs: Some(7),
+            ..handle.get_settings()
+        };
+        handle.set_settings(settings).await?;
+
+        let body = r#"{"test_text": "asdf1234", "test_i64": 123, "test_u64": 321, "test_unindex": "asdf", "test_facet": "/cat/cat4"}
+        {"test_text": "asdf5678", "test_i64": 456, "test_u64": 678, "test_unindex": "asdf", "test_facet": "/cat/cat4"}
+        {"test_text": "asdf9012", "test_i64": -12, "test_u64": 901, "test_unindex": "asdf", "test_facet": "/cat/cat4"}"#;
+        let lock = Arc::new(AtomicBool::new(false));
+        let index_docs = bulk_insert(
+            Arc::clone(&server),
+            Arc::clone(&lock),
+            Body::from(body),
+            "test_index_bulk",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 10,
+            },
+            &QueryOptions::default(),
+            bulk_task(),
+        )
+        .await?;
+        assert_eq!(index_docs.status(), StatusCode::FORBIDDEN);
+        assert!(!lock.load(Ordering::SeqCst));
+
+        flush(Arc::clone(&server), "test_index_bulk").await?;
+        let body: String = read_body(all_docs(Arc::clone(&server), "test_index_bulk").await?).await?;
+        let docs: SearchResults = serde_json::from_str(&body)?;
+        assert_eq!(docs.hits, 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulk_upsert() -> Result<(), Box<dyn std::error::Error>> {
+        let server = create_test_catalog("test_index_upsert");
+        let options = serde_urlencoded::from_str("upsert_field=test_u64&commit_every=1")?;
+
+        for text in &["first", "second"] {
+            let body = format!(
+                r#"{{"test_text": "{}", "test_i64": 1, "test_u64": 500, "test_unindex": "asdf", "test_facet": "/cat/cat4"}}"#,
+                text
+            );
+            let lock = Arc::new(AtomicBool::new(false));
+            let index_docs = bulk_insert(
+                Arc::clone(&server),
+                lock,
+                Body::from(body),
+                "test_index_upsert",
+                BulkOptions {
+                    num_threads: 2,
+                    max_line_length: 2048,
+                    buffer_size: 10,
+                },
+                &options,
+                bulk_task(),
+            )
+            .await?;
+            assert_eq!(index_docs.status(), StatusCode::CREATED);
+        }
+
+        std::thread::sleep(Duration::from_secs(1));
+        let body = Body::from(r#"{ "query": { "raw": "test_u64:500" } }"#);
+        let docs: SearchResults =
+            serde_json::from_str(&read_body(doc_search(Arc::clone(&server), body, "test_index_upsert").await?).await?)?;
+        assert_eq!(docs.hits, 1);
+        assert_eq!(docs.get_docs()[0].doc.get_str("test_text"), Some("second".into()));
+
+        let options = serde_urlencoded::from_str("upsert_field=asdf")?;
+        let lock = Arc::new(AtomicBool::new(false));
+        let resp = bulk_insert(
+            Arc::clone(&server),
+            lock,
+            Body::from("{}"),
+            "test_index_upsert",
+            BulkOptions {
+                num_threads: 2,
+                max_line_length: 2048,
+                buffer_size: 10,
+            },
+            &options,
+            bulk_task(),
+        )
+        .await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
 }
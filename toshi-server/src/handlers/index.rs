@@ -1,12 +1,18 @@
+use bytes::Buf;
 use hyper::body::to_bytes;
 use hyper::{Body, Response, StatusCode};
+use log::warn;
+use serde_json::Value;
+use tantivy::schema::Schema;
 
 use toshi_types::{Catalog, IndexHandle};
-use toshi_types::{DeleteDoc, Error, SchemaBody};
+use toshi_types::{DeleteDoc, DeleteQuery, Error, IndexSettings, SchemaBody};
 
 use crate::handlers::ResponseFuture;
-use crate::utils::{empty_with_code, error_response, with_body};
-use crate::AddDocument;
+use crate::settings::DEFAULT_MAX_DOCUMENT_SIZE;
+use crate::utils::{empty_with_code, error_response, read_limited, with_body};
+use crate::{validate_tokenizers, AddDocument};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 pub async fn delete_term<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
@@ -15,7 +21,7 @@ pub async fn delete_term<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -
     }
     let agg_body = to_bytes(body).await?;
     match serde_json::from_slice::<DeleteDoc>(&agg_body) {
-        Ok(dd) => match catalog.get_index(index) {
+        Ok(dd) => match catalog.get_index(index).await {
             Ok(c) => c
                 .delete_term(dd)
                 .await
@@ -27,32 +33,195 @@ pub async fn delete_term<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -
     }
 }
 
+/// Delete every document matching the query in the body
+pub async fn delete_by_query<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    let c = match catalog.get_index(index).await {
+        Ok(c) => c,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e)),
+    };
+    let req = to_bytes(body).await?;
+    match serde_json::from_slice::<DeleteQuery>(&req) {
+        Ok(dq) => c
+            .delete_by_query(dq)
+            .await
+            .map(with_body)
+            .or_else(|e| Ok(error_response(StatusCode::BAD_REQUEST, e))),
+        Err(e) => Ok(error_response(StatusCode::BAD_REQUEST, e.into())),
+    }
+}
+
 pub async fn create_index<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    create_index_with_tokenizer(catalog, body, index, None).await
+}
+
+/// Name `tokenizer` in the indexing options of every text field that leaves it out
+fn apply_default_tokenizer(schema: &mut Value, tokenizer: &str) {
+    for field in schema.as_array_mut().into_iter().flatten() {
+        if field.get("type").and_then(Value::as_str) != Some("text") {
+            continue;
+        }
+        if let Some(indexing) = field.pointer_mut("/options/indexing").and_then(Value::as_object_mut) {
+            indexing.entry("tokenizer").or_insert_with(|| tokenizer.into());
+        }
+    }
+}
+
+/// Create an index, giving text fields that don't name a tokenizer `default_tokenizer` in place of
+/// Tantivy's default one
+pub async fn create_index_with_tokenizer<C: Catalog>(
+    catalog: Arc<C>,
+    body: Body,
+    index: &str,
+    default_tokenizer: Option<&str>,
+) -> ResponseFuture {
     if catalog.exists(index) {
         return Ok(error_response(StatusCode::BAD_REQUEST, Error::AlreadyExists(index.to_string())));
     }
     let req = to_bytes(body).await?;
-    match serde_json::from_slice::<SchemaBody>(&req) {
-        Ok(schema_body) => match catalog.add_index(index, schema_body.0).await {
-            Ok(_) => Ok(empty_with_code(StatusCode::CREATED)),
-            Err(e) => Ok(Response::from(e)),
+    let schema_body = match default_tokenizer {
+        Some(tokenizer) => serde_json::from_slice::<Value>(&req).and_then(|mut schema| {
+            apply_default_tokenizer(&mut schema, tokenizer);
+            serde_json::from_value::<SchemaBody>(schema)
+        }),
+        None => serde_json::from_slice::<SchemaBody>(&req),
+    };
+    match schema_body {
+        Ok(schema_body) => add_index_with_schema(catalog, index, schema_body.0).await,
+        Err(e) => Ok(error_response(StatusCode::BAD_REQUEST, e.into())),
+    }
+}
+
+/// Create an index from one of the schema templates registered in the settings
+pub async fn create_index_from_template<C: Catalog>(
+    catalog: Arc<C>,
+    index: &str,
+    template: &str,
+    templates: &BTreeMap<String, SchemaBody>,
+) -> ResponseFuture {
+    if catalog.exists(index) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, Error::AlreadyExists(index.to_string())));
+    }
+    match templates.get(template) {
+        Some(schema_body) => add_index_with_schema(catalog, index, schema_body.0.clone()).await,
+        None => {
+            let err = Error::QueryError(format!("Unknown schema template: {}", template));
+            Ok(error_response(StatusCode::BAD_REQUEST, err))
+        }
+    }
+}
+
+async fn add_index_with_schema<C: Catalog>(catalog: Arc<C>, index: &str, schema: Schema) -> ResponseFuture {
+    if let Err(e) = validate_tokenizers(&schema) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, e));
+    }
+    match catalog.add_index(index, schema).await {
+        Ok(_) => Ok(empty_with_code(StatusCode::CREATED)),
+        Err(e) => Ok(Response::from(e)),
+    }
+}
+
+pub async fn get_index_settings<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
+    match catalog.get_index(index).await {
+        Ok(c) => Ok(with_body(c.get_settings())),
+        Err(e) => Ok(error_response(StatusCode::NOT_FOUND, e)),
+    }
+}
+
+/// Replace the settings stored alongside an index
+pub async fn update_index_settings<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    let c = match catalog.get_index(index).await {
+        Ok(c) => c,
+        Err(e) => return Ok(error_response(StatusCode::NOT_FOUND, e)),
+    };
+    let req = to_bytes(body).await?;
+    match serde_json::from_slice::<IndexSettings>(&req) {
+        Ok(settings) => match c.set_settings(settings).await {
+            Ok(_) => Ok(with_body(c.get_settings())),
+            Err(e) => Ok(error_response(StatusCode::BAD_REQUEST, e)),
         },
         Err(e) => Ok(error_response(StatusCode::BAD_REQUEST, e.into())),
     }
 }
 
 pub async fn add_document<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    add_document_checked(catalog, body, index, false, DEFAULT_MAX_DOCUMENT_SIZE).await
+}
+
+/// Fields of a document that will be silently lossy, either because they can't be searched or
+/// because they won't be returned in results
+fn lossy_fields(schema: &Schema, doc: &Value) -> Vec<String> {
+    let mut lossy: Vec<String> = doc
+        .as_object()
+        .map(|o| {
+            o.keys()
+                .filter(|k| match schema.get_field(k) {
+                    Some(f) => {
+                        let entry = schema.get_field_entry(f);
+                        !entry.is_indexed() || !entry.is_stored()
+                    }
+                    None => false,
+                })
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    lossy.sort();
+    lossy
+}
+
+/// Add a document, or an array of documents sharing the same options, when `strict_fields` is set
+/// documents with values for fields that are not both indexed and stored are rejected rather than
+/// just logged. Bodies over `max_document_size` bytes are rejected before they're read in full.
+pub async fn add_document_checked<C: Catalog>(
+    catalog: Arc<C>,
+    body: Body,
+    index: &str,
+    strict_fields: bool,
+    max_document_size: usize,
+) -> ResponseFuture {
     if !catalog.exists(index) {
         return Ok(error_response(StatusCode::BAD_REQUEST, Error::UnknownIndex(index.to_string())));
     }
-    let full_body = to_bytes(body).await?;
-    match serde_json::from_slice::<AddDocument>(&full_body) {
-        Ok(v) => match catalog.get_index(index) {
-            Ok(c) => c
-                .add_document(v)
-                .await
-                .map(|_| empty_with_code(StatusCode::CREATED))
-                .or_else(|e| Ok(error_response(StatusCode::BAD_REQUEST, e))),
+    let full_body = match read_limited(body, max_document_size).await? {
+        Some(b) => b,
+        None => {
+            let err = Error::QueryError(format!(
+                "Document is larger than the max document size of {} bytes",
+                max_document_size
+            ));
+            return Ok(error_response(StatusCode::PAYLOAD_TOO_LARGE, err));
+        }
+    };
+    match serde_json::from_reader::<_, AddDocument>(full_body.reader()) {
+        Ok(AddDocument { options, document }) => match catalog.get_index(index).await {
+            Ok(c) => {
+                let docs = match document {
+                    Value::Array(docs) if docs.is_empty() => {
+                        return Ok(error_response(
+                            StatusCode::BAD_REQUEST,
+                            Error::QueryError("No documents to add".into()),
+                        ));
+                    }
+                    Value::Array(docs) => docs,
+                    doc => vec![doc],
+                };
+                let schema = c.get_index().schema();
+                for doc in &docs {
+                    let lossy = lossy_fields(&schema, doc);
+                    if !lossy.is_empty() {
+                        let msg = format!("Fields: {} are not both indexed and stored", lossy.join(", "));
+                        if strict_fields {
+                            return Ok(error_response(StatusCode::BAD_REQUEST, Error::QueryError(msg)));
+                        }
+                        warn!("{} in index: {}", msg, index);
+                    }
+                }
+                // The whole batch goes to the index at once, so one bad document keeps all of them out
+                match c.add_document(AddDocument::new(Value::Array(docs), options)).await {
+                    Ok(_) => Ok(empty_with_code(StatusCode::CREATED)),
+                    Err(e) => Ok(Response::from(e)),
+                }
+            }
             Err(e) => Ok(error_response(StatusCode::BAD_REQUEST, e)),
         },
         Err(e) => Ok(error_response(StatusCode::BAD_REQUEST, e.into())),
@@ -67,13 +236,13 @@ mod tests {
 
     use pretty_assertions::assert_eq;
 
-    use toshi_types::IndexOptions;
+    use toshi_types::{ErrorResponse, IndexOptions};
 
     use crate::handlers::all_docs;
     use crate::index::create_test_catalog;
 
     use super::*;
-    use crate::commit::tests::wait_json;
+    use crate::commit::tests::{wait_for_hits, wait_json, wait_until};
 
     fn test_index() -> String {
         String::from("test_index")
@@ -97,6 +266,148 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_create_index_duplicate_field() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let shared_cat = create_test_catalog("test_index");
+        let schema = r#"[
+            { "name": "test_text", "type": "text", "options": { "indexing": { "record": "position", "tokenizer": "default" }, "stored": true } },
+            { "name": "test_text", "type": "text", "options": { "stored": true } }
+         ]"#;
+
+        let resp = create_index(Arc::clone(&shared_cat), Body::from(schema), "duplicate_index").await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ErrorResponse = wait_json(resp).await;
+        assert!(err.message.contains("Duplicate field: 'test_text' in schema"), "{}", err.message);
+        assert!(!shared_cat.exists("duplicate_index"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_index_tokenizer() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::index::{catalog_fixture, clear_catalog_fixture, IndexCatalog};
+        use toshi_types::{ExactTerm, KeyValue, Query, Search};
+
+        let (path, settings, _) = catalog_fixture("toshi_test_tokenizer")?;
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+        let schema = r#"[
+            { "name": "test_text", "type": "text", "options": { "indexing": { "record": "position", "tokenizer": "en_stem" }, "stored": true } }
+         ]"#;
+        let resp = create_index(Arc::clone(&catalog), Body::from(schema), "stemmed_index").await?;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let schema = catalog.get_index("stemmed_index").await?.get_index().schema();
+        match schema.get_field_entry(schema.get_field("test_text").unwrap()).field_type() {
+            tantivy::schema::FieldType::Str(opts) => assert_eq!(opts.get_indexing_options().unwrap().tokenizer(), "en_stem"),
+            ft => panic!("Expected a text field, got: {:?}", ft),
+        }
+
+        let q = r#" {"options": {"commit": true }, "document": {"test_text": "Running dogs"} }"#;
+        add_document(Arc::clone(&catalog), Body::from(q), "stemmed_index").await?;
+        let search = Search::from_query(Query::Exact(ExactTerm::new(KeyValue::new("test_text".into(), "run".into()))));
+        let probe = || async {
+            let body = Body::from(serde_json::to_vec(&search).unwrap());
+            let b: crate::SearchResults = wait_json(
+                crate::handlers::doc_search(Arc::clone(&catalog), body, "stemmed_index")
+                    .await
+                    .unwrap(),
+            )
+            .await;
+            b.hits
+        };
+        assert_eq!(wait_until(probe, |&hits| hits == 1).await, 1);
+
+        let schema = r#"[
+            { "name": "test_text", "type": "text", "options": { "indexing": { "record": "position", "tokenizer": "asdf" }, "stored": true } }
+         ]"#;
+        let resp = create_index(Arc::clone(&catalog), Body::from(schema), "bad_index").await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorResponse = wait_json(resp).await;
+        assert_eq!(
+            body.message,
+            "Error in query execution: 'Unknown tokenizer: asdf for field: test_text'"
+        );
+        assert!(!catalog.exists("bad_index"));
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_index_default_tokenizer() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::index::{catalog_fixture, clear_catalog_fixture, IndexCatalog};
+        use tantivy::schema::FieldType;
+        use toshi_types::{ExactTerm, KeyValue, Query, Search};
+
+        let (path, settings, _) = catalog_fixture("toshi_test_default_tokenizer")?;
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+        let schema = r#"[
+            { "name": "test_text", "type": "text", "options": { "indexing": { "record": "position" }, "stored": true } },
+            { "name": "test_raw", "type": "text", "options": { "indexing": { "record": "basic", "tokenizer": "raw" }, "stored": true } }
+         ]"#;
+        let resp = create_index_with_tokenizer(Arc::clone(&catalog), Body::from(schema), "default_stemmed", Some("en_stem")).await?;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let schema = catalog.get_index("default_stemmed").await?.get_index().schema();
+        let tokenizer = |name: &str| match schema.get_field_entry(schema.get_field(name).unwrap()).field_type() {
+            FieldType::Str(opts) => opts.get_indexing_options().unwrap().tokenizer().to_string(),
+            ft => panic!("Expected a text field, got: {:?}", ft),
+        };
+        assert_eq!(tokenizer("test_text"), "en_stem");
+        assert_eq!(tokenizer("test_raw"), "raw");
+
+        let q = r#" {"options": {"commit": true }, "document": {"test_text": "Running dogs", "test_raw": "Running dogs"} }"#;
+        add_document(Arc::clone(&catalog), Body::from(q), "default_stemmed").await?;
+        let search = Search::from_query(Query::Exact(ExactTerm::new(KeyValue::new("test_text".into(), "run".into()))));
+        let probe = || async {
+            let body = Body::from(serde_json::to_vec(&search).unwrap());
+            let b: crate::SearchResults = wait_json(
+                crate::handlers::doc_search(Arc::clone(&catalog), body, "default_stemmed")
+                    .await
+                    .unwrap(),
+            )
+            .await;
+            b.hits
+        };
+        assert_eq!(wait_until(probe, |&hits| hits == 1).await, 1);
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_index_from_template() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::index::{catalog_fixture, clear_catalog_fixture, IndexCatalog};
+        use crate::settings::Settings;
+
+        let (path, settings, _) = catalog_fixture("toshi_test_template")?;
+        let template = r#"[
+            { "name": "message", "type": "text", "options": { "indexing": { "record": "position", "tokenizer": "default" }, "stored": true } },
+            { "name": "level", "type": "u64", "options": { "indexed": true, "stored": true, "fast": "single" } }
+         ]"#;
+        let template: SchemaBody = serde_json::from_str(template)?;
+        let mut templates = BTreeMap::new();
+        templates.insert("logs".to_string(), template.clone());
+        let settings = Settings {
+            schema_templates: templates.clone(),
+            ..settings
+        };
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+
+        let resp = create_index_from_template(Arc::clone(&catalog), "logs_index", "logs", &templates).await?;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let schema = catalog.get_index("logs_index").await?.get_index().schema();
+        assert_eq!(serde_json::to_value(&schema)?, serde_json::to_value(&template.0)?);
+
+        let resp = create_index_from_template(Arc::clone(&catalog), "logs_index", "logs", &templates).await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = create_index_from_template(Arc::clone(&catalog), "other_index", "metrics", &templates).await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorResponse = wait_json(resp).await;
+        assert_eq!(body.message, "Error in query execution: 'Unknown schema template: metrics'");
+        assert!(!catalog.exists("other_index"));
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
     #[cfg(feature = "extra_tokenizers")]
     #[tokio::test]
     async fn test_create_index_extra_tokenizers() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -123,6 +434,27 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_id_field() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let resp = update_index_settings(Arc::clone(&shared_cat), Body::from(r#"{ "id_field": "test_u64" }"#), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let settings: IndexSettings = wait_json(get_index_settings(Arc::clone(&shared_cat), &test_index()).await?).await;
+        assert_eq!(settings.id_field.as_deref(), Some("test_u64"));
+
+        let b: crate::SearchResults = wait_json(all_docs(Arc::clone(&shared_cat), &test_index()).await?).await;
+        assert_eq!(b.hits, 5);
+        for doc in b.get_docs() {
+            assert_eq!(doc.id, doc.doc.get_u64("test_u64").map(|v| v.to_string()));
+        }
+
+        let resp = update_index_settings(Arc::clone(&shared_cat), Body::from(r#"{ "id_field": "asdf" }"#), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = get_index_settings(Arc::clone(&shared_cat), "asdf").await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_doc_create() {
         let shared_cat = create_test_catalog("test_index");
@@ -131,6 +463,190 @@ mod tests {
         assert!(req.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_doc_create_strict_fields() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let q = r#" {"document": {"test_text": "Babbaboo!", "test_u64": 10, "test_i64": -10, "test_unindex": "asdf"} }"#;
+        let req = add_document_checked(
+            Arc::clone(&shared_cat),
+            Body::from(q),
+            &test_index(),
+            true,
+            DEFAULT_MAX_DOCUMENT_SIZE,
+        )
+        .await?;
+        assert_eq!(req.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorResponse = wait_json(req).await;
+        assert_eq!(
+            body.message,
+            "Error in query execution: 'Fields: test_unindex are not both indexed and stored'"
+        );
+
+        let req = add_document_checked(
+            Arc::clone(&shared_cat),
+            Body::from(q),
+            &test_index(),
+            false,
+            DEFAULT_MAX_DOCUMENT_SIZE,
+        )
+        .await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+
+        let q = r#" {"document": {"test_text": "Babbaboo!", "test_u64": 10, "test_i64": -10} }"#;
+        let req = add_document_checked(
+            Arc::clone(&shared_cat),
+            Body::from(q),
+            &test_index(),
+            true,
+            DEFAULT_MAX_DOCUMENT_SIZE,
+        )
+        .await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_create_many() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let q = r#" {"options": {"commit": true }, "document": [
+            {"test_text": "Babbaboo!", "test_u64": 10, "test_i64": -10},
+            {"test_text": "Babbabee!", "test_u64": 11, "test_i64": -11}
+        ] }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+
+        assert_eq!(wait_for_hits(&shared_cat, &test_index(), 7).await, 7);
+
+        let req = add_document(Arc::clone(&shared_cat), Body::from(r#"{"document": []}"#), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_doc_create_concurrent() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let adds = (0..50).map(|i| {
+            let cat = Arc::clone(&shared_cat);
+            let q = format!(
+                r#"{{"document": {{"test_text": "Concurrent {}", "test_u64": 10, "test_i64": -10}} }}"#,
+                i
+            );
+            tokio::spawn(async move { add_document(cat, Body::from(q), &test_index()).await.unwrap().status() })
+        });
+        for status in futures::future::join_all(adds).await {
+            assert_eq!(status?, StatusCode::CREATED);
+        }
+        let handle = shared_cat.get_index(&test_index()).await?;
+        assert_eq!(handle.get_opstamp(), 50);
+        handle.commit().await?;
+
+        assert_eq!(wait_until(|| async { handle.num_docs() }, |&docs| docs == 55).await, 55);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_create_too_large() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let q = format!(
+            r#" {{"document": {{"test_text": "{}", "test_u64": 10, "test_i64": -10}} }}"#,
+            "a".repeat(2048)
+        );
+        let req = add_document_checked(Arc::clone(&shared_cat), Body::from(q.clone()), &test_index(), false, 1024).await?;
+        assert_eq!(req.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = add_document_checked(Arc::clone(&shared_cat), Body::from(q), &test_index(), false, 4096).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_create_array_atomic() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let handle = shared_cat.get_index(&test_index()).await?;
+        let q = r#" {"document": [{"test_text": "First", "test_u64": 10, "test_i64": -10}, {"test_text": "Second", "test_u64": "asdf"}] }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(handle.get_opstamp(), 0);
+
+        let q = r#" {"document": [{"test_text": "First", "test_u64": 10, "test_i64": -10}, {"test_text": "Second", "test_u64": 11}] }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+        assert_eq!(handle.get_opstamp(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_create_strict_mapping() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let q = r#" {"document": {"test_text": "Babbaboo!", "test_u64": 10, "test_i64": -10, "asdf": 1, "qwer": 2} }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+
+        let resp = update_index_settings(Arc::clone(&shared_cat), Body::from(r#"{ "strict_mapping": true }"#), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorResponse = wait_json(req).await;
+        assert_eq!(
+            body.message,
+            "Error in query execution: 'Fields: asdf, qwer are not in the schema of index: test_index'"
+        );
+
+        let q = r#" {"document": {"test_text": "Babbaboo!", "test_u64": 10, "test_i64": -10} }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_create_max_term_length() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let resp = update_index_settings(Arc::clone(&shared_cat), Body::from(r#"{ "max_term_length": 8 }"#), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let q = r#" {"options": {"commit": true}, "document": {"test_text": "short abcdefghijklmnop", "test_u64": 10, "test_i64": -10} }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorResponse = wait_json(req).await;
+        assert_eq!(
+            body.message,
+            "Error in query execution: 'Field: test_text has a term longer than 8 bytes: abcdefgh...'"
+        );
+
+        let settings = r#"{ "max_term_length": 8, "truncate_long_terms": true }"#;
+        let resp = update_index_settings(Arc::clone(&shared_cat), Body::from(settings), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+
+        let search = r#"{ "query": { "term": { "test_text": "abcdefgh" } } }"#;
+        let probe = || async {
+            let b: crate::SearchResults = wait_json(
+                crate::handlers::doc_search(Arc::clone(&shared_cat), Body::from(search), &test_index())
+                    .await
+                    .unwrap(),
+            )
+            .await;
+            b.get_docs().first().and_then(|d| d.doc.get_str("test_text"))
+        };
+        assert_eq!(wait_until(probe, Option::is_some).await, Some("short abcdefgh".into()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_create_max_docs() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        let resp = update_index_settings(Arc::clone(&shared_cat), Body::from(r#"{ "max_docs": 6 }"#), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let q = r#" {"document": {"test_text": "Babbaboo!", "test_u64": 10, "test_i64": -10} }"#;
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::CREATED);
+        let req = add_document(Arc::clone(&shared_cat), Body::from(q), &test_index()).await?;
+        assert_eq!(req.status(), StatusCode::FORBIDDEN);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_doc_delete() {
         let shared_cat = create_test_catalog("test_index");
@@ -146,6 +662,27 @@ mod tests {
         assert!(del.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_delete_by_query() -> Result<(), Box<dyn std::error::Error>> {
+        let shared_cat = create_test_catalog("test_index");
+        // A document that isn't committed yet is still counted when the delete commits
+        let doc = r#"{"document": {"test_text": "Pending", "test_u64": 20, "test_i64": 3000} }"#;
+        add_document(Arc::clone(&shared_cat), Body::from(doc), &test_index()).await?;
+        let body = r#"{ "options": { "commit": true }, "query": { "range": { "test_i64": { "gte": 2015 } } } }"#;
+        let resp = delete_by_query(Arc::clone(&shared_cat), Body::from(body), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let affected: toshi_types::DocsAffected = wait_json(resp).await;
+        assert_eq!(affected.docs_affected, 3);
+
+        let handle = shared_cat.get_index(&test_index()).await?;
+        assert_eq!(wait_until(|| async { handle.num_docs() }, |&docs| docs == 3).await, 3);
+
+        let body = r#"{ "query": { "range": { "asdf": { "gte": 2015 } } } }"#;
+        let resp = delete_by_query(Arc::clone(&shared_cat), Body::from(body), &test_index()).await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_bad_json() {
         let shared_cat = create_test_catalog("test_index");
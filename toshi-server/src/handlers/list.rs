@@ -1,5 +1,8 @@
 use std::sync::Arc;
 
+use hyper::Response;
+use log::info;
+
 use toshi_types::Catalog;
 
 use crate::handlers::ResponseFuture;
@@ -9,6 +12,17 @@ pub async fn list_indexes<C: Catalog>(catalog: Arc<C>) -> ResponseFuture {
     Ok(with_body(catalog.list_indexes().await))
 }
 
+/// Pick up index directories that were added to the data path while the server was running
+pub async fn reload<C: Catalog>(catalog: Arc<C>) -> ResponseFuture {
+    match catalog.refresh_catalog().await {
+        Ok(registered) => {
+            info!("Reload registered {} new indexes", registered.len());
+            Ok(with_body(registered))
+        }
+        Err(e) => Ok(Response::from(e)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -23,4 +37,38 @@ mod tests {
         assert_eq!(body, "[\"test_index\"]");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_reload() -> Result<(), Box<dyn std::error::Error>> {
+        use tantivy::{doc, Index};
+
+        use crate::commit::tests::wait_json;
+        use crate::handlers::all_docs;
+        use crate::index::{catalog_fixture, clear_catalog_fixture, IndexCatalog};
+        use crate::SearchResults;
+
+        let (path, settings, schema) = catalog_fixture("toshi_test_reload")?;
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+
+        // Written straight to disk behind the catalog's back
+        let text = schema.get_field("test_text").unwrap();
+        std::fs::create_dir(path.join("late_index"))?;
+        let index = Index::create_in_dir(path.join("late_index"), schema)?;
+        let mut writer = index.writer(15_000_000)?;
+        writer.add_document(doc! { text => "Out of band document" })?;
+        writer.commit()?;
+        drop(writer);
+        assert!(!catalog.exists("late_index"));
+
+        let registered: Vec<String> = wait_json(reload(Arc::clone(&catalog)).await?).await;
+        assert_eq!(registered, vec!["late_index"]);
+        let docs: SearchResults = wait_json(all_docs(Arc::clone(&catalog), "late_index").await?).await;
+        assert_eq!(docs.hits, 1);
+
+        let registered: Vec<String> = wait_json(reload(Arc::clone(&catalog)).await?).await;
+        assert!(registered.is_empty());
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
 }
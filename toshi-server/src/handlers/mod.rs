@@ -1,12 +1,15 @@
 use hyper::Body;
 
-pub use {bulk::*, index::*, list::*, root::*, search::*, summary::*};
+pub use {bulk::*, index::*, list::*, root::*, search::*, snapshot::*, summary::*, tail::*, tasks::*};
 
 pub mod bulk;
 pub mod index;
 pub mod list;
 pub mod root;
 pub mod search;
+pub mod snapshot;
 pub mod summary;
+pub mod tail;
+pub mod tasks;
 
 pub type ResponseFuture = Result<hyper::Response<Body>, hyper::Error>;
@@ -4,20 +4,37 @@ use hyper::body::to_bytes;
 use hyper::Response;
 use hyper::{Body, StatusCode};
 use log::info;
+use serde::{Deserialize, Serialize};
 
 use toshi_types::*;
 
+use crate::handle::build_query;
 use crate::handlers::ResponseFuture;
-use crate::utils::{empty_with_code, with_body};
+use crate::settings::{DEFAULT_MAX_OFFSET, DEFAULT_MAX_RESULT_LIMIT};
+use crate::utils::{empty_with_code, error_response, with_body};
 
 pub async fn doc_search<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    doc_search_checked(catalog, body, index, DEFAULT_MAX_RESULT_LIMIT, DEFAULT_MAX_OFFSET).await
+}
+
+/// Search an index with its limit clamped to `max_limit`, rejecting searches that skip more than
+/// `max_offset` documents, see [`Search::normalize`]
+pub async fn doc_search_checked<C: Catalog>(
+    catalog: Arc<C>,
+    body: Body,
+    index: &str,
+    max_limit: usize,
+    max_offset: usize,
+) -> ResponseFuture {
     let b = to_bytes(body).await?;
-    match serde_json::from_slice::<Search>(&b) {
+    let req = serde_json::from_slice::<Search>(&b)
+        .map_err(|err| Error::QueryError(format!("Bad JSON Query: {}", err)))
+        .and_then(|req| req.normalize(max_limit, max_offset));
+    match req {
         Ok(req) => {
-            let req = if req.query.is_none() { Search::all_limit(req.limit) } else { req };
             if catalog.exists(index) {
                 info!("Query: {:?}", req);
-                let index = catalog.get_index(index).unwrap(); // If this unwrap fails, this is a bug.
+                let index = catalog.get_index(index).await.unwrap(); // If this unwrap fails, this is a bug.
                 match index.search_index(req).await {
                     Ok(results) => Ok(with_body(results)),
                     Err(e) => Ok(Response::from(e)),
@@ -26,13 +43,142 @@ pub async fn doc_search<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) ->
                 Ok(empty_with_code(StatusCode::NOT_FOUND))
             }
         }
-        Err(err) => Ok(Response::from(Error::QueryError(format!("Bad JSON Query: {}", err)))),
+        Err(err) => Ok(error_response(StatusCode::BAD_REQUEST, err)),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueryExplain {
+    pub query: String,
+}
+
+/// Return the Tantivy query a search compiles to without running it
+pub async fn query_explain<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    let b = to_bytes(body).await?;
+    match serde_json::from_slice::<Search>(&b) {
+        Ok(req) => match catalog.get_index(index).await {
+            Ok(handle) => {
+                let query = req.query.unwrap_or(Query::All);
+                match build_query(&handle.get_index(), query, req.conjunction_by_default, &handle.get_settings()) {
+                    Ok(q) => Ok(with_body(QueryExplain { query: format!("{:?}", q) })),
+                    Err(e) => Ok(Response::from(e)),
+                }
+            }
+            Err(_) => Ok(empty_with_code(StatusCode::NOT_FOUND)),
+        },
+        Err(err) => Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            Error::QueryError(format!("Bad JSON Query: {}", err)),
+        )),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueryValidation {
+    pub valid: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Check that a search is well formed and only references fields of the index, without running it
+pub async fn validate_query<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    let handle = match catalog.get_index(index).await {
+        Ok(handle) => handle,
+        Err(_) => return Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    };
+    let b = to_bytes(body).await?;
+    let result = serde_json::from_slice::<Search>(&b)
+        .map_err(|err| Error::QueryError(format!("Bad JSON Query: {}", err)))
+        .and_then(|req| validate_search(&handle.get_index(), req, &handle.get_settings()));
+    Ok(with_body(QueryValidation {
+        valid: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+    }))
+}
+
+/// Compile a search's query and check every field it sorts, facets, aggregates or collapses on
+fn validate_search(index: &tantivy::Index, search: Search, settings: &IndexSettings) -> toshi_types::Result<()> {
+    let schema = index.schema();
+    let fields = search
+        .sort_by
+        .iter()
+        .map(|s| s.field.as_str())
+        .filter(|f| *f != Search::DOC_ORDER && *f != Search::SCORE)
+        .chain(search.facets.as_ref().map(|f| f.get_facets_fields()))
+        .chain(search.collapse.as_deref());
+    for field in fields {
+        if schema.get_field(field).is_none() {
+            return Err(Error::UnknownIndexField(field.into()));
+        }
+    }
+    for agg in search.aggs.values() {
+        agg.collector(&schema)?;
+    }
+    if let Some(expr) = &search.score_expression {
+        expr.validate_fields(&schema)?;
+    }
+    build_query(index, search.query.unwrap_or(Query::All), search.conjunction_by_default, settings)?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CountBy {
+    pub field: String,
+    #[serde(default)]
+    pub query: Option<Query>,
+}
+
+const COUNT_BY: &str = "count_by";
+
+/// Count the documents matched by a query for each distinct value of a field, most frequent first
+pub async fn count_by<C: Catalog>(catalog: Arc<C>, body: Body, index: &str) -> ResponseFuture {
+    let b = to_bytes(body).await?;
+    let req = match serde_json::from_slice::<CountBy>(&b) {
+        Ok(req) => req,
+        Err(err) => {
+            return Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                Error::QueryError(format!("Bad JSON Query: {}", err)),
+            ))
+        }
+    };
+    let handle = match catalog.get_index(index).await {
+        Ok(handle) => handle,
+        Err(_) => return Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    };
+    let search = Search::builder()
+        .with_query(req.query.unwrap_or(Query::All))
+        .count_only()
+        .with_aggregation(COUNT_BY, Aggregation::terms(req.field))
+        .build();
+    match handle.search_index(search).await {
+        Ok(results) => match results.get_aggregations().get(COUNT_BY) {
+            Some(AggregationResult::Terms { buckets }) => Ok(with_body(buckets)),
+            _ => Ok(with_body(Vec::<TermCount>::new())),
+        },
+        Err(e) => Ok(Response::from(e)),
     }
 }
 
 pub async fn all_docs<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
-    let body = Body::from(serde_json::to_vec(&Search::all_docs()).unwrap());
-    doc_search(catalog, body, index).await
+    all_docs_limit(catalog, index, None, Search::default_limit()).await
+}
+
+/// Return all documents up to the requested limit, which is clamped to `max_limit`
+pub async fn all_docs_limit<C: Catalog>(catalog: Arc<C>, index: &str, limit: Option<usize>, max_limit: usize) -> ResponseFuture {
+    let limit = limit.unwrap_or_else(Search::default_limit);
+    let body = Body::from(serde_json::to_vec(&Search::all_limit(limit)).unwrap());
+    doc_search_checked(catalog, body, index, max_limit, DEFAULT_MAX_OFFSET).await
+}
+
+/// Count the documents of an index without searching for and loading any of them
+pub async fn all_docs_count<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
+    match catalog.get_index(index).await {
+        Ok(handle) => Ok(with_body(
+            SearchResults::<FlatNamedDocument>::from_count(handle.num_docs() as usize),
+        )),
+        Err(_) => Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    }
 }
 
 #[cfg(test)]
@@ -42,11 +188,17 @@ pub mod tests {
     use hyper::Body;
     use pretty_assertions::assert_eq;
 
-    use toshi_types::{ErrorResponse, ExactTerm, FuzzyQuery, FuzzyTerm, KeyValue, PhraseQuery, Query, Search, TermPair};
+    use toshi_types::{
+        Aggregation, AggregationResult, BoolQuery, ErrorResponse, ExactTerm, FuzzyQuery, FuzzyTerm, KeyValue, PhraseQuery, Query, Search,
+        SortOrder, TermCount, TermPair,
+    };
 
     use crate::commit::tests::*;
-    use crate::handlers::{doc_search, ResponseFuture};
-    use crate::index::create_test_catalog;
+    use crate::handlers::{
+        count_by, doc_search, doc_search_checked, query_explain, validate_query, QueryExplain, QueryValidation, ResponseFuture,
+    };
+    use crate::index::{create_fast_test_catalog, create_test_catalog};
+    use crate::settings::{DEFAULT_MAX_QUERY_DEPTH, DEFAULT_MAX_RESULT_LIMIT};
     use crate::SearchResults;
 
     type ReturnUnit = Result<(), Box<dyn std::error::Error>>;
@@ -56,6 +208,11 @@ pub mod tests {
         doc_search(Arc::clone(&cat), Body::from(serde_json::to_vec(&req).unwrap()), index).await
     }
 
+    pub async fn run_fast_query(req: Search, index: &str) -> ResponseFuture {
+        let cat = create_fast_test_catalog(index);
+        doc_search(Arc::clone(&cat), Body::from(serde_json::to_vec(&req).unwrap()), index).await
+    }
+
     #[tokio::test]
     async fn test_term_query() -> Result<(), Box<dyn std::error::Error>> {
         let term = KeyValue::new("test_text".into(), "document".into());
@@ -67,6 +224,18 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_search_timing() -> ReturnUnit {
+        let q = run_query(Search::all_docs(), "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let timing = body.get_timing().expect("Searches are timed");
+        assert!(timing.query_ms > 0.0 && timing.fetch_ms > 0.0);
+        // The rest of the total is compiling the query and gathering up the collectors' results
+        assert!(timing.query_ms + timing.fetch_ms <= timing.took_ms);
+        assert!(timing.took_ms - (timing.query_ms + timing.fetch_ms) < timing.took_ms * 0.5 + 1.0);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_phrase_query() -> Result<(), Box<dyn std::error::Error>> {
         let terms = TermPair::new(vec!["test".into(), "document".into()], None);
@@ -85,7 +254,10 @@ pub mod tests {
         let body = r#"{ "query" : { "raw": "asd*(@sq__" } }"#;
         let err = doc_search(Arc::clone(&cat), Body::from(body), "test_index").await?;
         let body: ErrorResponse = wait_json::<ErrorResponse>(err).await;
-        assert_eq!(body.message, "Error in Index: \'Syntax Error: asd*(@sq__\'");
+        assert_eq!(
+            body.message,
+            "Error in query execution: 'Syntax Error: asd*(@sq__, in raw query `asd*(@sq__`'"
+        );
         Ok(())
     }
 
@@ -95,18 +267,210 @@ pub mod tests {
         let body = r#"{ "query" : { "raw": "test_unindex:yes" } }"#;
         let r = doc_search(Arc::clone(&cat), Body::from(body), "test_index").await?;
         let b = read_body(r).await?;
-        let expected = r#"{"message":"Error in Index: 'The field 'test_unindex' is not declared as indexed'"}"#;
+        let expected = r#"{"message":"Error in query execution: 'The field 'test_unindex' is not declared as indexed, at clause `test_unindex:yes` (position 0) of raw query `test_unindex:yes`'"}"#;
         assert_eq!(b, expected);
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_raw_query_unknown_field() -> ReturnUnit {
+        let cat = create_test_catalog("test_index");
+        let body = r#"{ "query" : { "raw": "test_text:document OR asdf:yes" } }"#;
+        let r = doc_search(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        assert_eq!(r.status(), hyper::StatusCode::BAD_REQUEST);
+        let b: ErrorResponse = wait_json(r).await;
+        assert_eq!(
+            b.message,
+            "Error in query execution: 'Field does not exists: 'asdf', at clause `asdf:yes` (position 22) of raw query `test_text:document OR asdf:yes`'"
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_bad_term_field_syntax() -> ReturnUnit {
         let cat = create_test_catalog("test_index");
         let body = r#"{ "query" : { "term": { "asdf": "Document" } } }"#;
         let q = doc_search(Arc::clone(&cat), Body::from(body), "test_index").await?;
         let b: ErrorResponse = wait_json(q).await;
-        assert_eq!(b.message, "Error in query execution: 'Unknown field: asdf'");
+        assert_eq!(b.message, "Unknown Field: 'asdf' queried");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unsortable_field() -> ReturnUnit {
+        let search = Search::builder().sort_by("test_text").build();
+        let b: ErrorResponse = wait_json(run_fast_query(search, "test_index").await?).await;
+        assert_eq!(
+            b.message,
+            "Error in query execution: 'Sort field: test_text must be a fast and stored u64 field'"
+        );
+
+        let search = Search::builder().sort_by("asdf").build();
+        let b: ErrorResponse = wait_json(run_fast_query(search, "test_index").await?).await;
+        assert_eq!(b.message, "Unknown Field: 'asdf' queried");
+
+        let search = Search::builder().sort_by("test_u64").build();
+        let b: SearchResults = wait_json(run_fast_query(search, "test_index").await?).await;
+        assert_eq!(b.get_docs()[0].score, Some(14.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sort_order() -> ReturnUnit {
+        let search = Search::builder().sort_by_order("test_u64", SortOrder::Asc).build();
+        let b: SearchResults = wait_json(run_fast_query(search, "test_index").await?).await;
+        let values: Vec<Option<u64>> = b.get_docs().iter().map(|d| d.doc.get_u64("test_u64")).collect();
+        assert_eq!(values, vec![Some(10), Some(11), Some(12), Some(13), Some(14)]);
+
+        let body = r#"{ "sort_by": [{ "field": "test_u64", "order": "asc" }, "_doc"] }"#;
+        let b: ErrorResponse = wait_json(doc_search(create_fast_test_catalog("test_index"), Body::from(body), "test_index").await?).await;
+        assert_eq!(
+            b.message,
+            "Error in query execution: '_doc can't be combined with other sort fields'"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_doc_order() -> ReturnUnit {
+        let search = Search::builder().sort_by(Search::DOC_ORDER).build();
+        let b: SearchResults = wait_json(run_query(search, "test_index").await?).await;
+        let values: Vec<Option<u64>> = b.get_docs().iter().map(|d| d.doc.get_u64("test_u64")).collect();
+        assert_eq!(values, vec![Some(10), Some(11), Some(12), Some(13), Some(14)]);
+        assert!(b.get_docs().iter().all(|d| d.score.is_none()));
+
+        let body = r#"{ "query": { "term": { "test_text": "document" } }, "sort_by": "_doc", "offset": 1, "limit": 1 }"#;
+        let b: SearchResults = wait_json(doc_search(create_test_catalog("test_index"), Body::from(body), "test_index").await?).await;
+        let values: Vec<Option<u64>> = b.get_docs().iter().map(|d| d.doc.get_u64("test_u64")).collect();
+        assert_eq!(values, vec![Some(13)]);
+
+        let search = Search::builder().sort_by(Search::DOC_ORDER).with_limit(2).with_offset(2).build();
+        let b: SearchResults = wait_json(run_query(search, "test_index").await?).await;
+        let values: Vec<Option<u64>> = b.get_docs().iter().map(|d| d.doc.get_u64("test_u64")).collect();
+        assert_eq!(values, vec![Some(12), Some(13)]);
+        assert_eq!(b.get_pagination().map(|p| p.total), Some(5));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_aggregations_only() -> ReturnUnit {
+        let body = r#"{ "query": { "term": { "test_text": "document" } }, "aggs": { "u64": { "stats": { "field": "test_u64" } } } }"#;
+        let search: Search = serde_json::from_str(body)?;
+        let b: SearchResults = wait_json(run_fast_query(search.aggregations_only(), "test_index").await?).await;
+        assert_eq!(b.hits, 3);
+        assert!(b.get_docs().is_empty());
+        assert!(b.get_aggregations().contains_key("u64"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_count_only() -> ReturnUnit {
+        let search = Search::builder().count_only().build();
+        let b: SearchResults = wait_json(run_fast_query(search, "test_index").await?).await;
+        assert_eq!(b.hits, 5);
+        assert_eq!(b.get_docs().len(), 0);
+
+        let body = r#"{ "query": { "term": { "test_text": "document" } }, "count_only": true, "aggs": { "u64": { "stats": { "field": "test_u64" } } } }"#;
+        let b: SearchResults = wait_json(doc_search(create_fast_test_catalog("test_index"), Body::from(body), "test_index").await?).await;
+        assert_eq!(b.hits, 3);
+        assert_eq!(b.get_docs().len(), 0);
+        assert!(b.get_aggregations().contains_key("u64"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_misspelled_search_key() -> ReturnUnit {
+        let cat = create_test_catalog("test_index");
+        let body = r#"{ "query" : { "raw": "test_text:document" }, "limt": 1 }"#;
+        let q = doc_search(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        assert_eq!(q.status(), hyper::StatusCode::BAD_REQUEST);
+        let b: ErrorResponse = wait_json(q).await;
+        assert!(b.message.contains("Bad JSON Query: unknown field `limt`"), "{}", b.message);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_fields_reported_together() -> ReturnUnit {
+        let body = r#"{"query": { "bool": {
+                "must": [ { "term": { "asdf": "document" } } ],
+                "must_not": [ {"range": {"qwer": { "gt": 2017 } } } ] } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let b: ErrorResponse = wait_json(q).await;
+        assert_eq!(b.message, "Unknown Field: 'asdf, qwer' queried");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collapse() -> ReturnUnit {
+        let body = r#"{ "collapse": "test_facet" }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let b: SearchResults = wait_json(q).await;
+        assert_eq!(b.hits, 4);
+        let mut facets: Vec<String> = b.get_docs().iter().filter_map(|d| d.doc.get_str("test_facet")).collect();
+        facets.sort();
+        assert_eq!(facets, vec!["/cat/cat2", "/cat/cat3", "/cat/cat4", "/dog/cat2"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collapse_pages() -> ReturnUnit {
+        // The two highest ranked documents share a facet, so a page of two needs a third document
+        let page = |offset| {
+            Search::builder()
+                .collapse_on("test_facet")
+                .with_limit(2)
+                .with_offset(offset)
+                .build()
+        };
+        let first: SearchResults = wait_json(run_query(page(0), "test_index").await?).await;
+        let mut facets: Vec<String> = first.get_docs().iter().filter_map(|d| d.doc.get_str("test_facet")).collect();
+        assert_eq!(facets.len(), 2);
+        assert_eq!(first.get_pagination().map(|p| (p.total, p.has_more)), Some((4, true)));
+
+        let second: SearchResults = wait_json(run_query(page(2), "test_index").await?).await;
+        facets.extend(second.get_docs().iter().filter_map(|d| d.doc.get_str("test_facet")));
+        assert_eq!(second.get_pagination().map(|p| (p.total, p.has_more)), Some((4, false)));
+        facets.sort();
+        assert_eq!(facets, vec!["/cat/cat2", "/cat/cat3", "/cat/cat4", "/dog/cat2"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_function_score_decay() -> ReturnUnit {
+        let body = r#"{ "query": { "term": { "test_text": "document" } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let plain: SearchResults = wait_json(run_fast_query(req, "test_index").await?).await;
+        let scores: Vec<f32> = plain.get_docs().iter().filter_map(|d| d.score).collect();
+        assert!(scores.iter().all(|s| cmp_float(*s, scores[0])));
+
+        let body = r#"{ "query": { "function_score": {
+                "query": { "term": { "test_text": "document" } },
+                "field": "test_u64", "origin": 14, "lambda": 0.5 } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let decayed: SearchResults = wait_json(run_fast_query(req, "test_index").await?).await;
+        let order: Vec<u64> = decayed.get_docs().iter().filter_map(|d| d.doc.get_u64("test_u64")).collect();
+        assert_eq!(order, vec![14, 13, 10]);
+        assert!(cmp_float(decayed.get_docs()[0].score.unwrap(), scores[0]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_function_score_unsupported() -> ReturnUnit {
+        let cat = create_fast_test_catalog("test_index");
+        let function_score = r#"{ "function_score": {
+                "query": { "term": { "test_text": "document" } },
+                "field": "test_u64", "origin": 14, "lambda": 0.5 } }"#;
+        let bodies = [
+            format!(r#"{{ "query": {}, "score_expression": "_score * 2" }}"#, function_score),
+            format!(r#"{{ "query": {}, "bm25": {{ "k1": 1.2, "b": 0.75 }} }}"#, function_score),
+            format!(r#"{{ "query": {{ "bool": {{ "must": [{}] }} }} }}"#, function_score),
+        ];
+        for body in bodies {
+            let r = doc_search(Arc::clone(&cat), Body::from(body), "test_index").await?;
+            assert_eq!(r.status(), hyper::StatusCode::BAD_REQUEST);
+        }
         Ok(())
     }
 
@@ -122,6 +486,81 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_facets_without_docs() -> ReturnUnit {
+        let body = r#"{ "query" : { "term": { "test_text": "document" } }, "facets": { "test_facet": ["/cat"] }, "limit": 0 }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let b: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        assert_eq!(b.hits, 0);
+        assert!(b.get_docs().is_empty());
+        assert_eq!(b.get_facets().len(), 2);
+        assert_eq!(b.get_pagination().map(|p| (p.total, p.has_more)), Some((3, true)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_empty_sections_omitted() -> ReturnUnit {
+        let q = run_query(Search::all_docs(), "test_index").await?;
+        let b: serde_json::Value = serde_json::from_str(&read_body(q).await?)?;
+        assert!(b.get("facets").is_none());
+        assert!(b.get("facet_results").is_none());
+        assert!(b.get("aggregations").is_none());
+
+        let req: Search = serde_json::from_str(r#"{ "facets": { "test_facet": ["/cat"] } }"#)?;
+        let q = run_query(req, "test_index").await?;
+        let b: serde_json::Value = serde_json::from_str(&read_body(q).await?)?;
+        assert_eq!(b["facets"].as_array().map(Vec::len), Some(3));
+        assert!(b.get("facet_results").is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_facet_results_grouped_by_field() -> ReturnUnit {
+        let body = r#"{ "facets": { "test_facet": ["/cat"] } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let b: SearchResults = wait_json(q).await;
+        let results = b.get_facet_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].field, "test_facet");
+        assert_eq!(results[0].values.len(), 3);
+        assert_eq!(results[0].values[0].value, "/cat/cat2");
+        assert_eq!(results[0].values[0].count, 2);
+        assert_eq!(b.get_facets().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_facets_top_k() -> ReturnUnit {
+        let body = r#"{ "facets": { "test_facet": ["/cat"], "top_k": 3 } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let b: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        let counts: Vec<u64> = b.get_facets().iter().map(|kv| kv.value).collect();
+        assert_eq!(counts, vec![2, 1, 1]);
+        assert_eq!(b.get_facets()[0].field, "/cat/cat2");
+
+        let body = r#"{ "facets": { "test_facet": [], "top_k": 1 } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let b: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        assert_eq!(b.get_facets().len(), 1);
+        assert_eq!(b.get_facets()[0].field, "/cat");
+        assert_eq!(b.get_facets()[0].value, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_facets_custom_delimiter() -> ReturnUnit {
+        let body = r#"{ "facets": { "test_facet": ["cat"], "delimiter": "." } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let b: SearchResults = wait_json(q).await;
+        assert_eq!(b.get_facets().len(), 3);
+        assert_eq!(b.get_facets()[0].field, "/cat/cat2");
+        assert_eq!(b.get_facets()[0].value, 2);
+        assert_eq!(b.get_facets()[1].value, 1);
+        Ok(())
+    }
+
     // This code is just...the worst thing ever.
     #[tokio::test]
     async fn test_raw_query() -> ReturnUnit {
@@ -130,10 +569,234 @@ pub mod tests {
         let q = run_query(req, "test_index").await?;
         let body: SearchResults = wait_json(q).await;
         assert_eq!(body.hits as usize, body.get_docs().len());
-        let b2 = body;
-        let map = b2.get_docs()[0].clone().doc.0;
-        let text = String::from(map.remove("test_text").unwrap().1.as_str().unwrap());
-        assert_eq!(text, "Test Duckiment 3");
+        let text = body.get_docs()[0].doc.get_str("test_text");
+        assert_eq!(text.as_deref(), Some("Test Duckiment 3"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_raw_query_conjunction() -> ReturnUnit {
+        let body = r#"{ "query": { "raw": "test_text:test test_text:document" } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let body: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        assert_eq!(body.hits, 5);
+
+        let body = r#"{ "query": { "raw": "test_text:test test_text:document" }, "conjunction_by_default": true }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let body: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        assert_eq!(body.hits, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_raw_query_default_operators() -> ReturnUnit {
+        use std::collections::BTreeMap;
+
+        use tantivy::schema::{SchemaBuilder, STORED, TEXT};
+        use tantivy::{doc, Index};
+        use toshi_types::{Catalog, DefaultOperator, IndexHandle, IndexSettings};
+
+        use crate::index::IndexCatalog;
+
+        let mut builder = SchemaBuilder::new();
+        let code = builder.add_text_field("code", STORED | TEXT);
+        let notes = builder.add_text_field("notes", STORED | TEXT);
+        let idx = Index::create_in_ram(builder.build());
+        let mut writer = idx.writer(30_000_000)?;
+        writer.add_document(doc! { code => "red blue" })?;
+        writer.add_document(doc! { code => "red" })?;
+        writer.add_document(doc! { notes => "blue" })?;
+        writer.commit()?;
+        drop(writer);
+        let cat = Arc::new(IndexCatalog::from_index("ops_index".into(), idx)?);
+
+        let matches = |cat: Arc<IndexCatalog>| async move {
+            let body = Body::from(r#"{ "query": { "raw": "red blue" } }"#);
+            let results: SearchResults = wait_json(doc_search(cat, body, "ops_index").await.unwrap()).await;
+            let mut matched: Vec<String> = results
+                .get_docs()
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}|{}",
+                        d.doc.get_str("code").unwrap_or_default(),
+                        d.doc.get_str("notes").unwrap_or_default()
+                    )
+                })
+                .collect();
+            matched.sort();
+            matched
+        };
+        assert_eq!(matches(Arc::clone(&cat)).await, vec!["red blue|", "red|", "|blue"]);
+
+        let set_operators = |code_op, notes_op| {
+            let mut default_operators = BTreeMap::new();
+            default_operators.insert("code".to_string(), code_op);
+            default_operators.insert("notes".to_string(), notes_op);
+            IndexSettings {
+                default_operators,
+                ..Default::default()
+            }
+        };
+        let handle = cat.get_index("ops_index").await?;
+        handle
+            .set_settings(set_operators(DefaultOperator::And, DefaultOperator::Or))
+            .await?;
+        assert_eq!(matches(Arc::clone(&cat)).await, vec!["red blue|", "|blue"]);
+
+        handle
+            .set_settings(set_operators(DefaultOperator::Or, DefaultOperator::And))
+            .await?;
+        assert_eq!(matches(Arc::clone(&cat)).await, vec!["red blue|", "red|"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_stored_field_bytes() -> ReturnUnit {
+        use toshi_types::{AddDocument, Catalog, IndexHandle, IndexOptions, IndexSettings};
+
+        let cat = create_test_catalog("test_index");
+        let handle = cat.get_index("test_index").await?;
+        let document = serde_json::json!({ "test_text": format!("oversized {}", "a".repeat(4096)), "test_u64": 20, "test_i64": 0 });
+        let options = Some(IndexOptions { commit: true });
+        handle.add_document(AddDocument { options, document }).await?;
+        assert_eq!(wait_until(|| async { handle.num_docs() }, |&docs| docs == 6).await, 6);
+
+        let search = r#"{ "query": { "term": { "test_text": "oversized" } } }"#;
+        let body: SearchResults = wait_json(doc_search(Arc::clone(&cat), Body::from(search), "test_index").await?).await;
+        assert_eq!(body.get_docs()[0].doc.get_str("test_text").map(|t| t.len()), Some(4106));
+
+        let settings = IndexSettings {
+            max_stored_field_bytes: Some(16),
+            ..Default::default()
+        };
+        handle.set_settings(settings).await?;
+        let body: SearchResults = wait_json(doc_search(Arc::clone(&cat), Body::from(search), "test_index").await?).await;
+        assert_eq!(
+            body.get_docs()[0].doc.get_str("test_text"),
+            Some("oversized aaaaaa...[truncated]".into())
+        );
+        assert_eq!(body.get_docs()[0].doc.get_u64("test_u64"), Some(20));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_query_boost() -> ReturnUnit {
+        let scores = |boost: &str| {
+            let body = format!(
+                r#"{{ "query": {{ "bool": {{ "should": [
+                    {{ "range": {{ "test_u64": {{ "gte": 10 {} }} }} }},
+                    {{ "term": {{ "test_text": "document" }} }}
+                ] }} }} }}"#,
+                boost
+            );
+            async move {
+                let req: Search = serde_json::from_str(&body).unwrap();
+                let results: SearchResults = wait_json(run_query(req, "test_index").await.unwrap()).await;
+                results
+                    .get_docs()
+                    .iter()
+                    .map(|d| (d.doc.get_u64("test_u64").unwrap(), d.score.unwrap()))
+                    .collect::<std::collections::BTreeMap<u64, f32>>()
+            }
+        };
+        let plain = scores("").await;
+        let boosted = scores(r#", "boost": 3.0"#).await;
+        let ignored = scores(r#", "boost": -1.0"#).await;
+        assert_eq!(plain.len(), 5);
+        for (id, score) in &plain {
+            assert!(cmp_float(boosted[id] - score, 2.0));
+            assert!(cmp_float(ignored[id], *score));
+        }
+        // Test Dockument 2 only matches the range, so its score is the range's alone
+        assert!(cmp_float(boosted[&11] / plain[&11], 3.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bm25_parameters() -> ReturnUnit {
+        use tantivy::schema::{SchemaBuilder, STORED, TEXT};
+        use tantivy::{doc, Index};
+        use toshi_types::Bm25;
+
+        use crate::index::IndexCatalog;
+
+        let term_query = Query::Exact(ExactTerm::new(KeyValue::new("test_text".into(), "document".into())));
+        let scores = |r: SearchResults| r.get_docs().iter().map(|d| d.score.unwrap()).collect::<Vec<f32>>();
+        let tantivy = scores(wait_json(run_query(Search::from_query(term_query.clone()), "test_index").await?).await);
+        let search = Search::builder().with_query(term_query).with_bm25(Bm25::default()).build();
+        let default = scores(wait_json(run_query(search, "test_index").await?).await);
+        assert_eq!(tantivy.len(), 3);
+        for (t, d) in tantivy.iter().zip(default.iter()) {
+            assert!(cmp_float(*t, *d));
+        }
+
+        let mut builder = SchemaBuilder::new();
+        let text = builder.add_text_field("text", STORED | TEXT);
+        let idx = Index::create_in_ram(builder.build());
+        let mut writer = idx.writer(30_000_000)?;
+        writer.add_document(doc! { text => "red red red blue" })?;
+        writer.add_document(doc! { text => "red blue green yellow" })?;
+        writer.add_document(doc! { text => "green yellow green blue" })?;
+        writer.commit()?;
+        drop(writer);
+        let cat = Arc::new(IndexCatalog::from_index("bm25_index".into(), idx)?);
+
+        // How much more a document with three matches scores than one with a single match
+        let ratio = |k1: f32| {
+            let cat = Arc::clone(&cat);
+            async move {
+                let query = Query::Exact(ExactTerm::new(KeyValue::new("text".into(), "red".into())));
+                let search = Search::builder().with_query(query).with_bm25(Bm25::new(k1, 0.75)).build();
+                let body = Body::from(serde_json::to_vec(&search).unwrap());
+                let results: SearchResults = wait_json(doc_search(cat, body, "bm25_index").await.unwrap()).await;
+                let scores = scores(results);
+                assert_eq!(scores.len(), 2);
+                scores[0] / scores[1]
+            }
+        };
+        let (low, high) = (ratio(0.5).await, ratio(3.0).await);
+        assert!(low > 1.0);
+        assert!(high > low, "Expected k1 = 3.0 ratio: {} to be above k1 = 0.5 ratio: {}", high, low);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_match_offsets() -> ReturnUnit {
+        let body = r#"{ "query": { "raw": "test_text:document OR test_text:duckiment" }, "return_match_offsets": true }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let results: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        assert_eq!(results.hits, 4);
+        for doc in results.get_docs() {
+            let text = doc.doc.get_str("test_text").unwrap();
+            let offsets = &doc.match_offsets["test_text"];
+            assert_eq!(offsets.len(), 1);
+            let matched = &text[offsets[0].start..offsets[0].end];
+            assert!(matched == "Document" || matched == "Duckiment", "Unexpected match: {}", matched);
+        }
+
+        let body = r#"{ "query": { "raw": "test_text:document" } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let results: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+        assert!(results.get_docs().iter().all(|d| d.match_offsets.is_empty()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_highlight() -> ReturnUnit {
+        let highlight = r#""highlight": { "fields": ["test_text", "test_u64", "asdf"] }"#;
+        for query in [r#"{ "raw": "test_text:document" }"#, r#"{ "term": { "test_text": "document" } }"#] {
+            let body = format!(r#"{{ "query": {}, {} }}"#, query, highlight);
+            let req: Search = serde_json::from_str(&body)?;
+            let results: SearchResults = wait_json(run_query(req, "test_index").await?).await;
+            assert_eq!(results.hits, 3);
+            for doc in results.get_docs() {
+                assert_eq!(doc.highlights.keys().collect::<Vec<_>>(), vec!["test_text"]);
+                let fragment = &doc.highlights["test_text"][0];
+                let highlighted = &fragment.highlighted[0];
+                assert_eq!(&fragment.fragment[highlighted.start..highlighted.end], "Document");
+            }
+        }
         Ok(())
     }
 
@@ -195,4 +858,430 @@ pub mod tests {
         assert_eq!(body.hits, 2);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_bool_clause_occur() -> ReturnUnit {
+        let test_json = r#"{"query": { "bool": {
+                "must": [
+                    { "term": { "test_text": "test" } },
+                    { "occur": "must_not", "query": { "term": { "test_text": "document" } } },
+                    { "occur": "should", "query": { "term": { "test_text": "duckiment" } } }
+                ] } } }"#;
+
+        let query = serde_json::from_str::<Search>(test_json)?;
+        let q = run_query(query, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let mut texts: Vec<_> = body.get_docs().iter().filter_map(|d| d.doc.get_str("test_text")).collect();
+        texts.sort();
+        assert_eq!(texts, vec!["Test Dockument 2", "Test Duckiment 3"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_term_stats() -> ReturnUnit {
+        let body = r#"{ "query": { "term": { "test_text": "document" } }, "term_stats": true }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let stats = body.get_term_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].field, "test_text");
+        assert_eq!(stats[0].term, "document");
+        assert_eq!(stats[0].doc_freq, body.hits as u64);
+        assert_eq!(stats[0].doc_freq, 3);
+        assert_eq!(stats[0].total_term_freq, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_always_arrays() -> ReturnUnit {
+        let body = r#"{ "query": { "range": { "test_u64": { "gte": 10, "lte": 10 } } }, "always_arrays": true }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        assert_eq!(body.hits, 1);
+        let doc = serde_json::to_value(&body.get_docs()[0].doc)?;
+        assert_eq!(doc["test_u64"], serde_json::json!([10]));
+        assert!(doc["test_text"].is_array());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_score_expression() -> ReturnUnit {
+        let body = r#"{ "query": { "term": { "test_text": "test" } }, "score_expression": "_score * 0.7 + log(test_u64) * 0.3" }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_fast_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let values: Vec<Option<u64>> = body.get_docs().iter().map(|d| d.doc.get_u64("test_u64")).collect();
+        assert_eq!(values, vec![Some(14), Some(13), Some(12), Some(11), Some(10)]);
+
+        let search = Search::builder()
+            .with_score_expression("_score - test_u64".parse()?)
+            .with_limit(1)
+            .build();
+        let q = run_fast_query(search, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        assert_eq!(body.get_docs()[0].doc.get_u64("test_u64"), Some(10));
+        assert!(cmp_float(body.get_docs()[0].score.unwrap(), -9.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pagination() -> ReturnUnit {
+        let mut pages = Vec::new();
+        for offset in [0, 2, 4] {
+            let body = format!(
+                r#"{{ "query": {{ "term": {{ "test_text": "test" }} }}, "limit": 2, "offset": {} }}"#,
+                offset
+            );
+            let req: Search = serde_json::from_str(&body)?;
+            let q = run_query(req, "test_index").await?;
+            let body: SearchResults = wait_json(q).await;
+            let pagination = body.get_pagination().unwrap();
+            assert_eq!((pagination.offset, pagination.limit, pagination.total), (offset, 2, 5));
+            pages.push((body.hits, pagination.has_more));
+        }
+        assert_eq!(pages, vec![(2, true), (2, true), (1, false)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_nested_aggregation() -> ReturnUnit {
+        let body =
+            r#"{ "aggs": { "facets": { "terms": { "field": "test_facet", "aggs": { "u64": { "stats": { "field": "test_u64" } } } } } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_fast_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let AggregationResult::Terms { buckets } = &body.get_aggregations()["facets"] else {
+            panic!("Expected terms")
+        };
+        let averages: Vec<(&str, Option<f64>)> = buckets
+            .iter()
+            .map(|b| match &b.aggregations["u64"] {
+                AggregationResult::Stats { avg, .. } => (b.value.as_str(), *avg),
+                r => panic!("Expected stats, got: {:?}", r),
+            })
+            .collect();
+        let expected = vec![
+            ("/cat/cat2", Some(10.5)),
+            ("/cat/cat3", Some(12.0)),
+            ("/cat/cat4", Some(13.0)),
+            ("/dog/cat2", Some(14.0)),
+        ];
+        assert_eq!(averages, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_offset() -> ReturnUnit {
+        let cat = create_test_catalog("test_index");
+        let body = r#"{ "query": { "term": { "test_text": "test" } }, "offset": 3 }"#;
+        let q = doc_search_checked(Arc::clone(&cat), Body::from(body), "test_index", DEFAULT_MAX_RESULT_LIMIT, 2).await?;
+        assert_eq!(q.status(), hyper::StatusCode::BAD_REQUEST);
+        let b: ErrorResponse = wait_json(q).await;
+        assert_eq!(
+            b.message,
+            "Error in query execution: 'Offset: 3 is over the max offset of 2, narrow the query to reach documents further down'"
+        );
+
+        let body = r#"{ "query": { "term": { "test_text": "test" } }, "offset": 2 }"#;
+        let q = doc_search_checked(Arc::clone(&cat), Body::from(body), "test_index", DEFAULT_MAX_RESULT_LIMIT, 2).await?;
+        let b: SearchResults = wait_json(q).await;
+        assert_eq!(b.hits, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_depth_limit() -> ReturnUnit {
+        let mut query: Query = ExactTerm::with_term("test_text", "document").into();
+        for _ in 0..DEFAULT_MAX_QUERY_DEPTH {
+            query = BoolQuery::builder().must_match(query).build();
+        }
+        let body = serde_json::to_string(&Search::from_query(query.clone()))?;
+        let q = doc_search(create_test_catalog("test_index"), Body::from(body), "test_index").await?;
+        assert_eq!(q.status(), hyper::StatusCode::BAD_REQUEST);
+        let b: ErrorResponse = wait_json(q).await;
+        assert_eq!(
+            b.message,
+            "Error in query execution: 'Query is nested 21 levels deep which is over the limit of 20'"
+        );
+
+        // Filter aggregations are held to the same limit
+        let search = Search::builder().with_aggregation("deep", Aggregation::filter(query)).build();
+        let q = doc_search(
+            create_test_catalog("test_index"),
+            Body::from(serde_json::to_string(&search)?),
+            "test_index",
+        )
+        .await?;
+        assert_eq!(q.status(), hyper::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_percentiles_aggregation() -> ReturnUnit {
+        let body = r#"{ "aggs": { "latency": { "percentiles": { "field": "test_u64", "percents": [50] } } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_fast_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let AggregationResult::Percentiles { values } = &body.get_aggregations()["latency"] else {
+            panic!("Expected percentiles")
+        };
+        assert_eq!(values["50.0"], Some(12.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_count_by() -> ReturnUnit {
+        let cat = create_test_catalog("test_index");
+        let body = r#"{ "field": "test_facet" }"#;
+        let q = count_by(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        let counts: Vec<TermCount> = wait_json(q).await;
+        let expected: Vec<(String, u64)> = vec![
+            ("/cat/cat2".into(), 2),
+            ("/cat/cat3".into(), 1),
+            ("/cat/cat4".into(), 1),
+            ("/dog/cat2".into(), 1),
+        ];
+        assert_eq!(counts.into_iter().map(|c| (c.value, c.count)).collect::<Vec<_>>(), expected);
+
+        let body = r#"{ "field": "test_facet", "query": { "term": { "test_text": "document" } } }"#;
+        let q = count_by(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        let counts: Vec<TermCount> = wait_json(q).await;
+        assert_eq!(counts.iter().map(|c| c.count).sum::<u64>(), 3);
+
+        let body = r#"{ "field": "test_text" }"#;
+        let q = count_by(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        let b: ErrorResponse = wait_json(q).await;
+        assert_eq!(
+            b.message,
+            "Error in query execution: 'Field: test_text must be a numeric fast field or a facet field'"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_aggregation_unknown_field() -> ReturnUnit {
+        let body = r#"{ "aggs": { "latency": { "percentiles": { "field": "asdf" } } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_query(req, "test_index").await?;
+        let b: ErrorResponse = wait_json(q).await;
+        assert_eq!(b.message, "Unknown Field: 'asdf' queried");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stats_aggregation() -> ReturnUnit {
+        let body = r#"{ "aggs": { "years": { "stats": { "field": "test_i64" } } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_fast_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let expected = AggregationResult::Stats {
+            count: 5,
+            min: Some(-2017.0),
+            max: Some(2018.0),
+            sum: 2016.0,
+            avg: Some(403.2),
+        };
+        assert_eq!(body.get_aggregations()["years"], expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_filter_aggregation() -> ReturnUnit {
+        let body = r#"{ "aggs": {
+                "all": { "stats": { "field": "test_u64" } },
+                "recent": { "filter": {
+                    "query": { "range": { "test_u64": { "gte": 12 } } },
+                    "aggs": { "stats": { "stats": { "field": "test_u64" } } } } } } }"#;
+        let req: Search = serde_json::from_str(body)?;
+        let q = run_fast_query(req, "test_index").await?;
+        let body: SearchResults = wait_json(q).await;
+        let AggregationResult::Stats { count, .. } = &body.get_aggregations()["all"] else {
+            panic!("Expected stats");
+        };
+        assert_eq!(*count, 5);
+        let stats = AggregationResult::Stats {
+            count: 3,
+            min: Some(12.0),
+            max: Some(14.0),
+            sum: 39.0,
+            avg: Some(13.0),
+        };
+        let expected = AggregationResult::Filter {
+            doc_count: 3,
+            aggregations: vec![("stats".to_string(), stats)].into_iter().collect(),
+        };
+        assert_eq!(body.get_aggregations()["recent"], expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_explain() -> ReturnUnit {
+        let test_json = r#"{"query": { "bool": {
+                "must": [ { "term": { "test_text": "document" } } ],
+                "must_not": [ {"range": {"test_i64": { "gt": 2017 } } } ] } } }"#;
+        let cat = create_test_catalog("test_index");
+        let q = query_explain(Arc::clone(&cat), Body::from(test_json), "test_index").await?;
+        let body: QueryExplain = wait_json(q).await;
+        assert!(!body.query.is_empty());
+        assert!(body.query.starts_with("BooleanQuery"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_query() -> ReturnUnit {
+        let cat = create_test_catalog("test_index");
+        let body = r#"{ "query": { "term": { "test_text": "document" } }, "sort_by": "test_u64" }"#;
+        let q = validate_query(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        let res: QueryValidation = wait_json(q).await;
+        assert!(res.valid);
+        assert_eq!(res.error, None);
+
+        let body = r#"{ "query": { "term": { "asdf": "document" } } }"#;
+        let q = validate_query(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        let res: QueryValidation = wait_json(q).await;
+        assert!(!res.valid);
+        assert!(res.error.unwrap().contains("asdf"));
+
+        let body = r#"{ "query": { "term": { "test_text": "document" } }, "sort_by": "asdf" }"#;
+        let q = validate_query(Arc::clone(&cat), Body::from(body), "test_index").await?;
+        let res: QueryValidation = wait_json(q).await;
+        assert_eq!(res.error, Some("Unknown Field: 'asdf' queried".into()));
+
+        let q = validate_query(Arc::clone(&cat), Body::from(r#"{ "query": "#), "test_index").await?;
+        let res: QueryValidation = wait_json(q).await;
+        assert!(!res.valid);
+
+        let q = validate_query(cat, Body::from(body), "asdf").await?;
+        assert_eq!(q.status(), hyper::StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_early_terminate() -> ReturnUnit {
+        use tantivy::schema::{SchemaBuilder, STORED, TEXT};
+        use tantivy::{doc, Index};
+
+        use crate::index::IndexCatalog;
+
+        let mut builder = SchemaBuilder::new();
+        let text = builder.add_text_field("text", STORED | TEXT);
+        let idx = Index::create_in_ram(builder.build());
+        let mut writer = idx.writer(30_000_000)?;
+        for i in 0..10_000 {
+            writer.add_document(doc! { text => format!("match {}", i) })?;
+        }
+        writer.commit()?;
+        drop(writer);
+        let cat = Arc::new(IndexCatalog::from_index("early_index".into(), idx)?);
+
+        let query = Query::Exact(ExactTerm::new(KeyValue::new("text".into(), "match".into())));
+        let search = Search::builder().with_query(query.clone()).early_terminate(3).build();
+        let body = Body::from(serde_json::to_vec(&search)?);
+        let results: SearchResults = wait_json(doc_search(Arc::clone(&cat), body, "early_index").await?).await;
+        assert_eq!(results.hits, 3);
+        assert_eq!(results.get_pagination().map(|p| p.total), Some(3));
+        assert!(results.get_docs().iter().all(|d| d.score.is_none()));
+        assert_eq!(results.get_docs()[0].doc.get_str("text"), Some("match 0".into()));
+
+        let search = Search::builder().with_query(query.clone()).build();
+        let body = Body::from(serde_json::to_vec(&search)?);
+        let results: SearchResults = wait_json(doc_search(Arc::clone(&cat), body, "early_index").await?).await;
+        assert_eq!(results.get_pagination().map(|p| p.total), Some(10_000));
+
+        let search = Search::builder().with_query(query).early_terminate(3).sort_by("text").build();
+        let body = Body::from(serde_json::to_vec(&search)?);
+        let resp = doc_search(cat, body, "early_index").await?;
+        assert_eq!(resp.status(), hyper::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sort_missing() -> ReturnUnit {
+        use tantivy::schema::{SchemaBuilder, FAST, STORED, STRING};
+        use tantivy::{doc, Index};
+        use toshi_types::SortMissing;
+
+        use crate::index::IndexCatalog;
+
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", STORED | STRING);
+        let rank = builder.add_u64_field("rank", STORED | FAST);
+        let idx = Index::create_in_ram(builder.build());
+        let mut writer = idx.writer(30_000_000)?;
+        writer.add_document(doc! { name => "a", rank => 5u64 })?;
+        writer.add_document(doc! { name => "b", rank => 0u64 })?;
+        writer.add_document(doc! { name => "c" })?;
+        writer.add_document(doc! { name => "d", rank => 9u64 })?;
+        writer.commit()?;
+        drop(writer);
+        let cat = Arc::new(IndexCatalog::from_index("sort_index".into(), idx)?);
+
+        let sorted = |missing: SortMissing| {
+            let cat = Arc::clone(&cat);
+            async move {
+                let search = Search::builder().sort_by("rank").sort_missing(missing).build();
+                let body = Body::from(serde_json::to_vec(&search).unwrap());
+                let body: SearchResults = wait_json(doc_search(cat, body, "sort_index").await.unwrap()).await;
+                body.get_docs()
+                    .iter()
+                    .map(|d| (d.doc.get_str("name").unwrap(), d.score))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let last = sorted(SortMissing::Last).await;
+        let names: Vec<&str> = last.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["d", "a", "b", "c"]);
+        assert_eq!(last[2].1, Some(0.0));
+        assert_eq!(last[3].1, None);
+
+        let first = sorted(SortMissing::First).await;
+        let names: Vec<&str> = first.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["c", "d", "a", "b"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sort_score_tiebreak() -> ReturnUnit {
+        use tantivy::schema::{SchemaBuilder, FAST, STORED, STRING, TEXT};
+        use tantivy::{doc, Index};
+
+        use crate::index::IndexCatalog;
+
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", STORED | STRING);
+        let text = builder.add_text_field("text", TEXT);
+        let rank = builder.add_u64_field("rank", STORED | FAST);
+        let idx = Index::create_in_ram(builder.build());
+        let mut writer = idx.writer(30_000_000)?;
+        writer.add_document(doc! { name => "a", text => "fox jumps over the dog", rank => 1u64 })?;
+        writer.add_document(doc! { name => "b", text => "fox fox fox", rank => 1u64 })?;
+        writer.add_document(doc! { name => "c", text => "fox jumps over the dog", rank => 2u64 })?;
+        writer.commit()?;
+        drop(writer);
+        let cat = Arc::new(IndexCatalog::from_index("score_sort_index".into(), idx)?);
+
+        let sorted = |sort_by: &'static str| {
+            let cat = Arc::clone(&cat);
+            async move {
+                let body = format!(r#"{{ "query": {{ "term": {{ "text": "fox" }} }}, "sort_by": {} }}"#, sort_by);
+                let body: SearchResults = wait_json(doc_search(cat, Body::from(body), "score_sort_index").await.unwrap()).await;
+                body.get_docs().iter().map(|d| d.doc.get_str("name").unwrap()).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(sorted(r#"["rank", "_score"]"#).await, vec!["c", "b", "a"]);
+        assert_eq!(
+            sorted(r#"[{ "field": "rank", "order": "asc" }, "_score"]"#).await,
+            vec!["b", "a", "c"]
+        );
+        assert_eq!(
+            sorted(r#"[{ "field": "rank", "order": "asc" }, { "field": "_score", "order": "asc" }]"#).await,
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(sorted(r#""_score""#).await[0], "b");
+        Ok(())
+    }
 }
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::body::to_bytes;
+use hyper::{Body, Response, StatusCode};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tantivy::directory::{Directory, ManagedDirectory, MmapDirectory, TerminatingWrite};
+use tantivy::{Index, TantivyError};
+
+use toshi_types::*;
+
+use crate::handle::INDEX_SETTINGS_FILE;
+use crate::handlers::ResponseFuture;
+use crate::utils::{error_response, with_body};
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotResponse {
+    pub snapshot_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RestoreRequest {
+    pub snapshot_id: String,
+}
+
+const META_FILEPATH: &str = "meta.json";
+
+/// Commits the index and copies the files of its committed segments to a new directory under
+/// `snapshot_path`, the snapshot is only moved into place once every file has been copied
+pub async fn snapshot<C: Catalog>(catalog: Arc<C>, index: &str, snapshot_path: &str) -> ResponseFuture {
+    let local_index = match catalog.get_index(index).await {
+        Ok(i) => i,
+        Err(e) => return Ok(error_response(StatusCode::NOT_FOUND, e)),
+    };
+    // Frozen indexes have nothing left to commit and no writer to hold off while files are copied
+    let _write = match local_index.get_writer() {
+        Ok(writer) => {
+            let mut write = writer.lock_owned().await;
+            if let Err(e) = write.commit() {
+                return Ok(Response::from(Error::from(e)));
+            }
+            local_index.set_opstamp(0);
+            Some(write)
+        }
+        Err(_) => None,
+    };
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let snapshot_id = format!("{}-{}", index, millis);
+    let dest = PathBuf::from(snapshot_path).join(&snapshot_id);
+    match copy_index_files(&local_index.get_index(), &dest) {
+        Ok(count) => {
+            info!("Snapshot: {} of index: {} copied {} files", snapshot_id, index, count);
+            Ok(with_body(SnapshotResponse { snapshot_id }))
+        }
+        Err(e) => Ok(Response::from(e)),
+    }
+}
+
+/// Creates a new index named `index` from the files of a previous snapshot
+pub async fn restore<C: Catalog>(catalog: Arc<C>, body: Body, index: &str, snapshot_path: &str) -> ResponseFuture {
+    if catalog.exists(index) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, Error::AlreadyExists(index.to_string())));
+    }
+    let req = to_bytes(body).await?;
+    let restore = match serde_json::from_slice::<RestoreRequest>(&req) {
+        Ok(r) => r,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e.into())),
+    };
+    let source = PathBuf::from(snapshot_path).join(&restore.snapshot_id);
+    if !is_snapshot_id(&restore.snapshot_id) || !source.join(META_FILEPATH).exists() {
+        let err = Error::QueryError(format!("Unknown snapshot: {}", restore.snapshot_id));
+        return Ok(error_response(StatusCode::NOT_FOUND, err));
+    }
+    let schema = match Index::open_in_dir(&source) {
+        Ok(idx) => idx.schema(),
+        Err(e) => return Ok(Response::from(Error::from(e))),
+    };
+    let dest = catalog.index_path(index);
+    if let Err(e) = copy_dir(&source, &dest) {
+        return Ok(Response::from(e));
+    }
+    match catalog.add_index(index, schema).await {
+        Ok(_) => {
+            info!("Restored snapshot: {} into index: {}", restore.snapshot_id, index);
+            Ok(with_body(SnapshotResponse {
+                snapshot_id: restore.snapshot_id,
+            }))
+        }
+        Err(e) => {
+            // Left in the data path the copy would be registered as an index on the next reload
+            let _ = fs::remove_dir_all(&dest);
+            Ok(Response::from(e))
+        }
+    }
+}
+
+/// Whether `id` has the `<index>-<millis>` form snapshots are given, anything else could point
+/// outside of the snapshot path
+fn is_snapshot_id(id: &str) -> bool {
+    match id.rsplit_once('-') {
+        Some((index, millis)) => {
+            !index.is_empty()
+                && index != "."
+                && index != ".."
+                && !index.contains(['/', '\\'])
+                && !millis.is_empty()
+                && millis.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+fn copy_index_files(index: &Index, dest: &Path) -> Result<usize> {
+    // Named after the whole snapshot id, an extension would replace anything after a `.` in it
+    let id = dest.file_name().and_then(|f| f.to_str()).unwrap_or_default();
+    let tmp = dest.with_file_name(format!("{}.tmp", id));
+    fs::create_dir_all(&tmp)?;
+    // Reads through the index's managed directory strip each file's footer, writing through another
+    // managed directory puts it back.
+    let target = ManagedDirectory::wrap(Box::new(MmapDirectory::open(&tmp)?))?;
+    let directory = index.directory();
+    let meta = directory.atomic_read(Path::new(META_FILEPATH)).map_err(TantivyError::from)?;
+
+    let mut copied = 0;
+    for segment in index.searchable_segment_metas()? {
+        for file in segment.list_files() {
+            if !directory.exists(&file).map_err(TantivyError::from)? {
+                continue;
+            }
+            let bytes = directory.open_read(&file).map_err(TantivyError::from)?.read_bytes()?;
+            let mut write = target.open_write(&file).map_err(TantivyError::from)?;
+            write.write_all(bytes.as_slice())?;
+            write.terminate()?;
+            copied += 1;
+        }
+    }
+    target.atomic_write(Path::new(META_FILEPATH), &meta)?;
+    if let Ok(settings) = directory.atomic_read(Path::new(INDEX_SETTINGS_FILE)) {
+        fs::write(tmp.join(INDEX_SETTINGS_FILE), settings)?;
+    }
+    fs::rename(&tmp, dest)?;
+    Ok(copied + 1)
+}
+
+/// Copies every file of `source` into `dest`, which must not exist yet so nothing else's files are
+/// mixed in with the copy
+fn copy_dir(source: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir(dest)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        fs::copy(entry.path(), dest.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commit::tests::{create_test_index, wait_json};
+    use crate::handlers::all_docs;
+    use crate::index::{catalog_fixture, clear_catalog_fixture, IndexCatalog};
+    use crate::SearchResults;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (path, settings, _) = catalog_fixture("toshi_test_snapshot")?;
+        let snapshot_path = path.join("snapshots");
+        let mut catalog = IndexCatalog::new(settings)?;
+        catalog.add_test_index("test_index".into(), create_test_index());
+        catalog.add_test_index("test.index".into(), create_test_index());
+        let catalog = Arc::new(catalog);
+        let snapshot_dir = snapshot_path.clone();
+        let snapshot_path = snapshot_path.display().to_string();
+
+        // Temporary copies are named after the whole id, not just what comes before its first `.`
+        let resp = snapshot(Arc::clone(&catalog), "test.index", &snapshot_path).await?;
+        let snap: SnapshotResponse = wait_json(resp).await;
+        assert!(snapshot_dir.join(&snap.snapshot_id).join(META_FILEPATH).exists());
+        assert_eq!(fs::read_dir(&snapshot_dir)?.count(), 1);
+
+        let resp = snapshot(Arc::clone(&catalog), "test_index", &snapshot_path).await?;
+        let snap: SnapshotResponse = wait_json(resp).await;
+        assert!(snap.snapshot_id.starts_with("test_index-"));
+
+        let body = Body::from(serde_json::to_vec(&RestoreRequest {
+            snapshot_id: snap.snapshot_id,
+        })?);
+        let resp = restore(Arc::clone(&catalog), body, "restored_index", &snapshot_path).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let original: SearchResults = wait_json(all_docs(Arc::clone(&catalog), "test_index").await?).await;
+        let restored: SearchResults = wait_json(all_docs(Arc::clone(&catalog), "restored_index").await?).await;
+        assert_eq!(original.hits, 5);
+        assert_eq!(restored.hits, original.hits);
+
+        for id in ["asdf", ".", "..", "..-1", "../test_index-1", "test_index-"] {
+            let body = Body::from(serde_json::json!({ "snapshot_id": id }).to_string());
+            let resp = restore(Arc::clone(&catalog), body, "missing_index", &snapshot_path).await?;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", id);
+        }
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+}
@@ -9,14 +9,21 @@ use crate::handlers::ResponseFuture;
 use crate::utils::{empty_with_code, with_body};
 use std::sync::Arc;
 
+#[derive(Serialize)]
+struct WarmupResponse {
+    segments: usize,
+    took_millis: u128,
+}
+
 #[derive(Serialize)]
 struct FlushResponse {
     opstamp: u64,
+    pending_ops: usize,
 }
 
 pub async fn index_summary<C: Catalog>(catalog: Arc<C>, index: &str, options: QueryOptions) -> ResponseFuture {
     let start = Instant::now();
-    if let Ok(index) = catalog.get_index(index) {
+    if let Ok(index) = catalog.get_index(index).await {
         let metas = index.get_index().load_metas().unwrap();
         let summary = if options.include_sizes() {
             SummaryResponse::new(metas, Some(index.get_space()))
@@ -33,14 +40,183 @@ pub async fn index_summary<C: Catalog>(catalog: Arc<C>, index: &str, options: Qu
 }
 
 pub async fn flush<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
-    if let Ok(local_index) = catalog.get_index(index) {
-        let writer = local_index.get_writer();
+    if let Ok(local_index) = catalog.get_index(index).await {
+        let writer = match local_index.get_writer() {
+            Ok(writer) => writer,
+            Err(e) => return Ok(Response::from(e)),
+        };
         let mut write = writer.lock().await;
+        let pending_ops = local_index.get_opstamp();
         let opstamp = write.commit().unwrap();
-        info!("Successful commit: {}", index);
-        Ok(with_body(FlushResponse { opstamp }))
+        local_index.set_opstamp(0);
+        info!("Successful commit: {}, {} pending operations flushed", index, pending_ops);
+        Ok(with_body(FlushResponse { opstamp, pending_ops }))
     } else {
         debug!("Could not find index: {}", index);
         Ok(empty_with_code(StatusCode::NOT_FOUND))
     }
 }
+
+/// Load an index's caches ahead of the first real searches against it
+pub async fn warmup<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
+    let start = Instant::now();
+    match catalog.get_index(index).await {
+        Ok(local_index) => match local_index.warmup() {
+            Ok(segments) => {
+                let took_millis = start.elapsed().as_millis();
+                info!("Warmed up {} segments of: {} in {}ms", segments, index, took_millis);
+                Ok(with_body(WarmupResponse { segments, took_millis }))
+            }
+            Err(e) => Ok(Response::from(e)),
+        },
+        Err(_) => Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    }
+}
+
+/// Rebuild an index's writer, dropping any changes that haven't been committed yet
+pub async fn recreate_writer<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
+    match catalog.get_index(index).await {
+        Ok(local_index) => match local_index.recreate_writer().await {
+            Ok(()) => Ok(empty_with_code(StatusCode::OK)),
+            Err(e) => Ok(Response::from(e)),
+        },
+        Err(_) => Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    }
+}
+
+/// Commit an index and drop its writer, leaving it searchable but read-only until it's thawed
+pub async fn freeze<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
+    match catalog.get_index(index).await {
+        Ok(local_index) => match local_index.freeze().await {
+            Ok(()) => Ok(empty_with_code(StatusCode::OK)),
+            Err(e) => Ok(Response::from(e)),
+        },
+        Err(_) => Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    }
+}
+
+/// Give a frozen index a writer again
+pub async fn thaw<C: Catalog>(catalog: Arc<C>, index: &str) -> ResponseFuture {
+    match catalog.get_index(index).await {
+        Ok(local_index) => match local_index.thaw().await {
+            Ok(()) => Ok(empty_with_code(StatusCode::OK)),
+            Err(e) => Ok(Response::from(e)),
+        },
+        Err(_) => Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Body;
+    use serde::Deserialize;
+
+    use crate::commit::tests::{wait_for_hits, wait_json};
+    use crate::handlers::add_document;
+    use crate::index::create_test_catalog;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct FlushBody {
+        pending_ops: usize,
+    }
+
+    #[derive(Deserialize)]
+    struct WarmupBody {
+        segments: usize,
+    }
+
+    #[tokio::test]
+    async fn test_warmup() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let resp = warmup(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: WarmupBody = wait_json(resp).await;
+        assert_eq!(body.segments, 1);
+
+        let docs: crate::SearchResults = wait_json(crate::handlers::all_docs(Arc::clone(&catalog), "test_index").await?).await;
+        assert_eq!(docs.hits, 5);
+
+        let resp = warmup(Arc::clone(&catalog), "asdf").await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flush_reports_pending() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        for i in 0..3 {
+            let body = format!(
+                r#"{{"document": {{ "test_text": "Flushable {}", "test_u64": 10, "test_i64": -10 }} }}"#,
+                i
+            );
+            add_document(Arc::clone(&catalog), Body::from(body), "test_index").await?;
+        }
+        assert_eq!(catalog.get_index("test_index").await?.get_opstamp(), 3);
+
+        let resp = flush(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: FlushBody = wait_json(resp).await;
+        assert_eq!(body.pending_ops, 3);
+        assert_eq!(catalog.get_index("test_index").await?.get_opstamp(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recreate_writer() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let body = r#"{"document": { "test_text": "Uncommitted", "test_u64": 10, "test_i64": -10 } }"#;
+        add_document(Arc::clone(&catalog), Body::from(body), "test_index").await?;
+
+        let resp = recreate_writer(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(catalog.get_index("test_index").await?.get_opstamp(), 0);
+
+        let body = r#"{"options": { "commit": true }, "document": { "test_text": "Recreated", "test_u64": 10, "test_i64": -10 } }"#;
+        let resp = add_document(Arc::clone(&catalog), Body::from(body), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        assert_eq!(wait_for_hits(&catalog, "test_index", 6).await, 6);
+
+        let resp = recreate_writer(Arc::clone(&catalog), "asdf").await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_freeze_thaw() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        // A write still holding the writer would keep writing to a frozen index
+        let writer = catalog.get_index("test_index").await?.get_writer()?;
+        let resp = freeze(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert!(!catalog.get_index("test_index").await?.is_frozen());
+        drop(writer);
+
+        let resp = freeze(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(catalog.get_index("test_index").await?.is_frozen());
+
+        let docs: crate::SearchResults = wait_json(crate::handlers::all_docs(Arc::clone(&catalog), "test_index").await?).await;
+        assert_eq!(docs.hits, 5);
+
+        let body = r#"{"options": { "commit": true }, "document": { "test_text": "Thawed", "test_u64": 10, "test_i64": -10 } }"#;
+        let resp = add_document(Arc::clone(&catalog), Body::from(body), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = flush(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = thaw(Arc::clone(&catalog), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!catalog.get_index("test_index").await?.is_frozen());
+        let resp = add_document(Arc::clone(&catalog), Body::from(body), "test_index").await?;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        assert_eq!(wait_for_hits(&catalog, "test_index", 6).await, 6);
+
+        let resp = freeze(Arc::clone(&catalog), "asdf").await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+}
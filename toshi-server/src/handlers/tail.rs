@@ -0,0 +1,230 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Body, Response, StatusCode};
+use log::{error, info};
+use tantivy::schema::NamedFieldDocument;
+use tantivy::{Index, IndexMeta, SegmentId, SegmentMeta, SegmentReader};
+
+use toshi_types::{Catalog, FlatNamedDocument, IndexHandle, TailedDoc};
+
+use crate::handlers::ResponseFuture;
+use crate::utils::empty_with_code;
+use crate::Result;
+
+/// How often a tail checks its index for new commits
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where a tail is in its index's history, commits after the opstamp of its metas haven't been streamed yet
+struct Tail {
+    index: Index,
+    /// The index's metas as of the last poll, holding on to them keeps their segments readable after
+    /// a merge replaces them
+    metas: IndexMeta,
+    /// Lines from resuming that are sent before anything newly committed
+    backlog: Vec<u8>,
+}
+
+impl Tail {
+    /// A tail starting after the index's latest commit. Once merges mix documents from different
+    /// commits there's no telling which came after an older `since`, so a client that missed commits
+    /// is sent every document in the index before the tail carries on from there.
+    fn new(index: Index, since: Option<u64>) -> Result<Self> {
+        let metas = index.load_metas()?;
+        let mut tail = Self {
+            index,
+            metas,
+            backlog: Vec::new(),
+        };
+        if since.is_some_and(|since| since < tail.metas.opstamp) {
+            tail.backlog = tail.write_docs(&tail.metas.segments, tail.metas.opstamp, &mut HashMap::new())?;
+        }
+        Ok(tail)
+    }
+
+    /// Newline delimited documents from the commits since the last poll
+    fn poll(&mut self) -> Result<Vec<u8>> {
+        let mut lines = std::mem::take(&mut self.backlog);
+        let metas = self.index.load_metas()?;
+        // Merges save the metas without moving the opstamp, their segments only hold documents that were
+        // already streamed, so nothing is sent until a commit comes along.
+        if metas.opstamp != self.metas.opstamp {
+            let seen: HashSet<SegmentId> = self.metas.segments.iter().map(|s| s.id()).collect();
+            let current: HashSet<SegmentId> = metas.segments.iter().map(|s| s.id()).collect();
+            let added: Vec<SegmentMeta> = metas.segments.iter().filter(|s| !seen.contains(&s.id())).cloned().collect();
+            // A merge that finished alongside the commit shows up as another new segment, the documents of
+            // the segments it replaced are counted so their merged copies can be told apart from new ones.
+            let mut merged = HashMap::new();
+            if !added.is_empty() {
+                for segment in self.metas.segments.iter().filter(|s| !current.contains(&s.id())) {
+                    for_each_doc(&self.index, segment, |doc| {
+                        *merged.entry(doc_hash(&doc)?).or_insert(0) += 1;
+                        Ok(())
+                    })?;
+                }
+            }
+            lines.extend(self.write_docs(&added, metas.opstamp, &mut merged)?);
+        }
+        self.metas = metas;
+        Ok(lines)
+    }
+
+    /// Write the documents in `segments` as lines, skipping ones counted in `merged`
+    fn write_docs(&self, segments: &[SegmentMeta], opstamp: u64, merged: &mut HashMap<u64, usize>) -> Result<Vec<u8>> {
+        let mut lines = Vec::new();
+        for segment in segments {
+            for_each_doc(&self.index, segment, |doc| {
+                if !merged.is_empty() {
+                    if let Some(count) = merged.get_mut(&doc_hash(&doc)?).filter(|c| **c > 0) {
+                        *count -= 1;
+                        return Ok(());
+                    }
+                }
+                let doc: FlatNamedDocument = doc.into();
+                serde_json::to_writer(&mut lines, &TailedDoc { opstamp, doc })?;
+                lines.push(b'\n');
+                Ok(())
+            })?;
+        }
+        Ok(lines)
+    }
+}
+
+fn for_each_doc<F>(index: &Index, segment: &SegmentMeta, mut f: F) -> Result<()>
+where
+    F: FnMut(NamedFieldDocument) -> Result<()>,
+{
+    let reader = SegmentReader::open(&index.segment(segment.clone()))?;
+    let store = reader.get_store_reader(1)?;
+    let schema = index.schema();
+    for doc in reader.doc_ids_alive() {
+        f(schema.to_named_doc(&store.get(doc)?))?;
+    }
+    Ok(())
+}
+
+fn doc_hash(doc: &NamedFieldDocument) -> Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&serde_json::to_vec(doc)?);
+    Ok(hasher.finish())
+}
+
+/// Stream the documents committed to an index after this request was made as newline delimited
+/// [`TailedDoc`]s, the response stays open until the client goes away. Clients reconnecting pass the
+/// opstamp of the last document they got as `since` to pick up what they missed.
+pub async fn tail_index<C: Catalog>(catalog: Arc<C>, index: &str, since: Option<u64>) -> ResponseFuture {
+    let handle = match catalog.get_index(index).await {
+        Ok(handle) => handle,
+        Err(_) => return Ok(empty_with_code(StatusCode::NOT_FOUND)),
+    };
+    let tail = match Tail::new(handle.get_index(), since) {
+        Ok(tail) => tail,
+        Err(e) => return Ok(Response::from(e)),
+    };
+    info!("Tailing index: {}", index);
+    let name = index.to_string();
+    let lines = futures::stream::unfold(tail, move |mut tail| {
+        let name = name.clone();
+        async move {
+            loop {
+                tokio::time::sleep(TAIL_POLL_INTERVAL).await;
+                match tail.poll() {
+                    Ok(lines) if lines.is_empty() => continue,
+                    Ok(lines) => return Some((Ok::<_, Infallible>(lines), tail)),
+                    Err(e) => {
+                        error!("Stopped tailing index: {}, {}", name, e);
+                        return None;
+                    }
+                }
+            }
+        }
+    });
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::wrap_stream(lines))
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::time::timeout;
+
+    use crate::handlers::add_document;
+    use crate::index::create_test_catalog;
+
+    use super::*;
+
+    fn tailed(lines: &[u8]) -> serde_json::Result<Vec<TailedDoc<FlatNamedDocument>>> {
+        lines
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(serde_json::from_slice)
+            .collect()
+    }
+
+    fn texts(lines: &[u8]) -> serde_json::Result<Vec<String>> {
+        Ok(tailed(lines)?.iter().filter_map(|t| t.doc.get_str("test_text")).collect())
+    }
+
+    fn doc(text: &str) -> Body {
+        let q = r#" {"options": {"commit": true }, "document": {"test_text": "TEXT", "test_u64": 10, "test_i64": -10} }"#;
+        Body::from(q.replace("TEXT", text))
+    }
+
+    #[tokio::test]
+    async fn test_tail_index() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let resp = tail_index(Arc::clone(&catalog), "test_index", None).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let mut body = resp.into_body();
+
+        add_document(Arc::clone(&catalog), doc("Babbaboo!"), "test_index").await?;
+
+        let chunk = timeout(Duration::from_secs(5), body.next()).await?.expect("Tail ended")?;
+        let docs = tailed(&chunk)?;
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].doc.get_str("test_text"), Some("Babbaboo!".into()));
+
+        // Resuming from the last opstamp seen has nothing to catch up on, an older one replays the index
+        let since = docs[0].opstamp;
+        assert!(Tail::new(catalog.get_index("test_index").await?.get_index(), Some(since))?
+            .poll()?
+            .is_empty());
+        let resp = tail_index(Arc::clone(&catalog), "test_index", Some(since - 1)).await?;
+        let chunk = timeout(Duration::from_secs(5), resp.into_body().next())
+            .await?
+            .expect("Tail ended")?;
+        assert_eq!(tailed(&chunk)?.len(), 6);
+
+        let resp = tail_index(catalog, "asdf", None).await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tail_commit_during_merge() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let handle = catalog.get_index("test_index").await?;
+        let mut tail = Tail::new(handle.get_index(), None)?;
+        add_document(Arc::clone(&catalog), doc("First"), "test_index").await?;
+        assert_eq!(texts(&tail.poll()?)?, vec!["First"]);
+
+        // The merge and the commit both land before the next poll
+        let segments = handle.get_index().searchable_segment_ids()?;
+        let merge = handle.get_writer()?.lock().await.merge(&segments);
+        add_document(Arc::clone(&catalog), doc("Second"), "test_index").await?;
+        merge.await?;
+        assert_eq!(texts(&tail.poll()?)?, vec!["Second"]);
+
+        // A merge on its own has nothing new to stream
+        let segments = handle.get_index().searchable_segment_ids()?;
+        handle.get_writer()?.lock().await.merge(&segments).await?;
+        assert!(tail.poll()?.is_empty());
+        Ok(())
+    }
+}
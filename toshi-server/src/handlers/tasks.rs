@@ -0,0 +1,93 @@
+use hyper::StatusCode;
+
+use toshi_types::Error;
+
+use crate::handlers::ResponseFuture;
+use crate::tasks::TaskRegistry;
+use crate::utils::{error_response, with_body};
+
+pub async fn list_tasks(tasks: &TaskRegistry) -> ResponseFuture {
+    Ok(with_body(tasks.list()))
+}
+
+pub async fn cancel_task(tasks: &TaskRegistry, id: &str) -> ResponseFuture {
+    let task = id.parse().ok().and_then(|id| tasks.cancel(id));
+    match task {
+        Some(info) => Ok(with_body(info)),
+        None => Ok(error_response(
+            StatusCode::NOT_FOUND,
+            Error::QueryError(format!("Unknown task: {}", id)),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use hyper::Body;
+
+    use toshi_types::QueryOptions;
+
+    use crate::commit::tests::{wait_json, wait_until};
+    use crate::handlers::{all_docs, bulk_insert, BulkOptions};
+    use crate::index::create_test_catalog;
+    use crate::tasks::TaskInfo;
+    use crate::SearchResults;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_bulk() -> Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let tasks = Arc::new(TaskRegistry::default());
+        let (mut sender, body) = Body::channel();
+
+        let task = tasks.register("bulk", "test_index");
+        let cat = Arc::clone(&catalog);
+        let bulk = tokio::spawn(async move {
+            let watcher = Arc::new(AtomicBool::new(false));
+            bulk_insert(
+                cat,
+                watcher,
+                body,
+                "test_index",
+                BulkOptions {
+                    num_threads: 2,
+                    max_line_length: 2048,
+                    buffer_size: 10,
+                },
+                &QueryOptions::default(),
+                task,
+            )
+            .await
+        });
+        sender
+            .send_data(r#"{"test_text": "Cancelled Document", "test_i64": 1, "test_u64": 1}"#.into())
+            .await?;
+        sender.send_data("\n".into()).await?;
+
+        let probe = || async { wait_json::<Vec<TaskInfo>>(list_tasks(&tasks).await.unwrap()).await };
+        let listed = wait_until(probe, |listed| listed.iter().any(|t| t.docs_received == 1)).await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].kind.as_str(), listed[0].index.as_str()), ("bulk", "test_index"));
+        assert_eq!(listed[0].docs_received, 1);
+
+        let cancelled = cancel_task(&tasks, &listed[0].id.to_string()).await?;
+        assert_eq!(cancelled.status(), StatusCode::OK);
+        // The bulk insert stops even though the body was never finished
+        let resp = bulk.await??;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let listed: Vec<TaskInfo> = wait_json(list_tasks(&tasks).await?).await;
+        assert!(listed.is_empty());
+
+        let docs: SearchResults = wait_json(all_docs(Arc::clone(&catalog), "test_index").await?).await;
+        assert_eq!(docs.hits, 5);
+
+        let resp = cancel_task(&tasks, "1").await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        drop(sender);
+        Ok(())
+    }
+}
@@ -1,12 +1,15 @@
 use std::clone::Clone;
+use std::collections::VecDeque;
 use std::fs;
-use std::path::{PathBuf, MAIN_SEPARATOR};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use log::{debug, error};
 use tantivy::schema::Schema;
 use tantivy::Index;
 
-use toshi_types::{Catalog, Error};
+use toshi_types::{Catalog, Error, IndexHandle};
 
 use crate::handle::LocalIndex;
 use crate::settings::Settings;
@@ -16,6 +19,12 @@ pub struct IndexCatalog {
     settings: Settings,
     base_path: PathBuf,
     local_handles: DashMap<String, LocalIndex>,
+    /// Every index known to the catalog, whether or not it is currently open
+    index_names: DashSet<String>,
+    /// Open indexes, ordered from least to most recently used
+    recently_used: Mutex<VecDeque<String>>,
+    /// Serializes opens so two requests for a closed index don't both try to take its writer
+    opening: tokio::sync::Mutex<()>,
 }
 
 impl IndexCatalog {
@@ -32,38 +41,80 @@ impl Catalog for IndexCatalog {
         format!("{}", self.base_path.display())
     }
 
+    fn index_path(&self, name: &str) -> PathBuf {
+        self.base_path.join(self.settings.index_dir_name(name))
+    }
+
     fn get_collection(&self) -> &DashMap<String, Self::Handle> {
         &self.local_handles
     }
 
     async fn add_index(&self, name: &str, schema: Schema) -> Result<()> {
-        let handle = LocalIndex::new(
-            self.base_path.clone(),
-            name,
-            schema,
-            self.settings.writer_memory,
-            self.settings.get_merge_policy(),
-        )?;
+        // Creating an index hits the disk and spawns the writer's threads, doing that on a blocking
+        // thread keeps searches against existing indexes from queueing up behind it.
+        let (base_path, index_name, settings) = (self.base_path.clone(), name.to_string(), self.settings.clone());
+        let handle = tokio::task::spawn_blocking(move || LocalIndex::new(base_path, &index_name, schema, &settings))
+            .await
+            .map_err(|_| Error::SpawnError)??;
+        self.index_names.insert(name.to_string());
         self.local_handles.insert(name.to_string(), handle);
+        self.touch(name);
+        self.close_unused();
         Ok(())
     }
 
     async fn list_indexes(&self) -> Vec<String> {
         let mut local_keys: Vec<String> = self.local_handles.iter().map(|e| e.key().to_owned()).collect();
+        local_keys.extend(self.index_names.iter().map(|e| e.key().to_owned()));
         local_keys.sort();
         local_keys.dedup();
         local_keys
     }
 
-    fn get_index(&self, name: &str) -> Result<Self::Handle> {
-        self.local_handles.get(name).map(|r| r.value().to_owned()).ok_or_else(|| {
-            let _ = &name;
-            Error::UnknownIndex(name.into())
-        })
+    async fn get_index(&self, name: &str) -> Result<Self::Handle> {
+        if let Some(handle) = self.local_handles.get(name).map(|r| r.value().to_owned()) {
+            self.touch(name);
+            // Indexes that were in use the last time something was opened may be closable by now
+            self.close_unused();
+            Ok(handle)
+        } else if self.index_names.contains(name) {
+            self.open_index(name).await
+        } else {
+            Err(Error::UnknownIndex(name.into()))
+        }
     }
 
     fn exists(&self, index: &str) -> bool {
-        self.get_collection().contains_key(index)
+        self.get_collection().contains_key(index) || self.index_names.contains(index)
+    }
+
+    async fn refresh_catalog(&self) -> Result<Vec<String>> {
+        let mut registered = Vec::new();
+        for dir in fs::read_dir(self.base_path.clone())? {
+            let entry = dir?.path();
+            if let Some(entry_str) = entry.to_str() {
+                if entry.exists() {
+                    // Only directories holding an index are registered, anything else would fail on open
+                    if !entry.join("meta.json").exists() {
+                        continue;
+                    }
+                    if let Some(pth) = index_name(&entry, &self.settings.index_prefix) {
+                        // Indexes that are already known are left alone so open handles and their
+                        // in flight writes aren't replaced out from under anyone
+                        if !self.exists(&pth) {
+                            log::debug!("Registering Path: {} - {}", pth, entry_str);
+                            self.index_names.insert(pth.clone());
+                            registered.push(pth);
+                        }
+                    }
+                } else {
+                    return Err(Error::UnknownIndex(format!("Path {}", entry.display())));
+                }
+            } else {
+                return Err(Error::UnknownIndex(format!("Path {} is not a valid unicode path", entry.display())));
+            }
+        }
+        Ok(registered)
     }
 }
 
@@ -75,6 +126,9 @@ impl IndexCatalog {
             settings,
             base_path: path,
             local_handles: local_idxs,
+            index_names: DashSet::new(),
+            recently_used: Mutex::new(VecDeque::new()),
+            opening: tokio::sync::Mutex::new(()),
         };
 
         Ok(index_cat)
@@ -99,32 +153,90 @@ impl IndexCatalog {
         self.local_handles.insert(name, local);
     }
 
-    pub async fn refresh_catalog(&mut self) -> Result<()> {
-        self.local_handles.clear();
+    /// Opens an index that the catalog knows about on disk but has not opened yet, closing
+    /// the least recently used indexes if this puts the catalog over `max_open_indices`
+    async fn open_index(&self, name: &str) -> Result<LocalIndex> {
+        // Only opens are serialized here, lookups of already open indexes never wait on this lock
+        let _opening = self.opening.lock().await;
+        let handle = match self.local_handles.get(name).map(|r| r.value().clone()) {
+            Some(handle) => handle,
+            None => {
+                debug!("Opening index: {}", name);
+                let path = self.index_path(name);
+                let (base_path, index_name, settings) = (self.base_path.clone(), name.to_string(), self.settings.clone());
+                let handle = tokio::task::spawn_blocking(move || {
+                    let idx = IndexCatalog::load_index(&path.display().to_string())?;
+                    LocalIndex::new(base_path, &index_name, idx.schema(), &settings)
+                })
+                .await
+                .map_err(|_| Error::SpawnError)??;
+                self.local_handles.insert(name.to_string(), handle.clone());
+                handle
+            }
+        };
+        self.touch(name);
+        self.close_unused();
+        Ok(handle)
+    }
 
-        for dir in fs::read_dir(self.base_path.clone())? {
-            let entry = dir?.path();
-            if let Some(entry_str) = entry.to_str() {
-                if entry.exists() {
-                    if !entry_str.ends_with(".node_id") {
-                        let pth: String = entry_str.rsplit(MAIN_SEPARATOR).take(1).collect();
-                        log::debug!("Loading Path: {} - {}", pth, entry_str);
+    fn touch(&self, name: &str) {
+        if self.settings.max_open_indices == 0 {
+            return;
+        }
+        let mut recent = self.recently_used.lock().unwrap();
+        recent.retain(|n| n != name);
+        recent.push_back(name.to_string());
+    }
 
-                        let idx = IndexCatalog::load_index(entry_str)?;
-                        self.add_index(&pth, idx.schema()).await?;
-                    }
-                } else {
-                    return Err(Error::UnknownIndex(format!("Path {}", entry.display())));
-                }
-            } else {
-                return Err(Error::UnknownIndex(format!("Path {} is not a valid unicode path", entry.display())));
+    fn close_unused(&self) {
+        let limit = self.settings.max_open_indices;
+        if limit == 0 {
+            return;
+        }
+        let mut recent = self.recently_used.lock().unwrap();
+        // The most recently used index is never a candidate, it's the one that was just asked for.
+        let mut candidates = recent.len().saturating_sub(1);
+        while self.local_handles.len() > limit && candidates > 0 {
+            candidates -= 1;
+            let name = match recent.pop_front() {
+                Some(n) => n,
+                None => break,
+            };
+            // Handles still held by bulk inserts, tails or searches own the index's writer, closing
+            // those would leave the index locked until they finish so they're kept open instead.
+            // Both the check and the removal happen under the entry's lock, so a lookup can't clone
+            // the handle in between.
+            let closed = self
+                .local_handles
+                .remove_if(&name, |_, handle| !handle.is_shared() && Self::prepare_close(handle));
+            if closed.is_some() {
+                debug!("Closing least recently used index: {}", name);
+            } else if self.local_handles.contains_key(&name) {
+                recent.push_back(name);
             }
         }
-        Ok(())
+    }
+
+    /// Commits any pending writes so an index can be closed, returns false if the writer is busy
+    fn prepare_close(handle: &LocalIndex) -> bool {
+        let writer = match handle.get_writer() {
+            Ok(writer) => writer,
+            Err(_) => return true,
+        };
+        let result = match writer.try_lock() {
+            Ok(mut w) if handle.get_opstamp() > 0 => w.commit().map(|_| handle.set_opstamp(0)),
+            Ok(_) => Ok(()),
+            Err(_) => return false,
+        };
+        result
+            .map_err(|e| error!("Unable to commit index: {} before closing it: {}", handle.get_name(), e))
+            .is_ok()
     }
 
     pub async fn clear(&self) {
         self.local_handles.clear();
+        self.index_names.clear();
+        self.recently_used.lock().unwrap().clear();
     }
 
     #[doc(hidden)]
@@ -144,13 +256,302 @@ impl IndexCatalog {
             settings,
             base_path: PathBuf::new(),
             local_handles: map,
+            index_names: DashSet::new(),
+            recently_used: Mutex::new(VecDeque::new()),
+            opening: tokio::sync::Mutex::new(()),
         })
     }
 }
 
+/// The name of the index stored at `path`, only directories starting with `prefix` hold indexes
+fn index_name(path: &Path, prefix: &str) -> Option<String> {
+    // Either separator ends a component, so paths written on Windows give the same names everywhere
+    let name = path.to_str()?.rsplit(['/', '\\']).find(|c| !c.is_empty())?.strip_prefix(prefix)?;
+    (!name.is_empty() && !name.ends_with(".node_id")).then(|| name.to_string())
+}
+
 #[cfg(test)]
 pub fn create_test_catalog(name: &str) -> crate::SharedCatalog {
     let idx = crate::commit::tests::create_test_index();
     let catalog = IndexCatalog::from_index(name.into(), idx).unwrap();
     std::sync::Arc::new(catalog)
 }
+
+#[cfg(test)]
+pub fn create_fast_test_catalog(name: &str) -> crate::SharedCatalog {
+    let idx = crate::commit::tests::create_fast_test_index();
+    let catalog = IndexCatalog::from_index(name.into(), idx).unwrap();
+    std::sync::Arc::new(catalog)
+}
+
+/// An empty directory named `name` in the temp dir, settings keeping indexes there and a schema
+/// with a single stored text field
+#[cfg(test)]
+pub(crate) fn catalog_fixture(name: &str) -> std::io::Result<(PathBuf, Settings, Schema)> {
+    use tantivy::schema::{SchemaBuilder, STORED, TEXT};
+
+    let path = std::env::temp_dir().join(name);
+    let _ = remove_dir_all::remove_dir_all(&path);
+    fs::create_dir_all(&path)?;
+    let settings = Settings {
+        path: path.display().to_string(),
+        writer_memory: 15_000_000,
+        ..Default::default()
+    };
+    let mut builder = SchemaBuilder::new();
+    builder.add_text_field("test_text", STORED | TEXT);
+    Ok((path, settings, builder.build()))
+}
+
+/// Drops the writers of every index in `catalog` so the fixture directory at `path` can be removed
+#[cfg(test)]
+pub(crate) async fn clear_catalog_fixture(catalog: &IndexCatalog, path: &Path) {
+    catalog.clear().await;
+    let _ = remove_dir_all::remove_dir_all(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::Body;
+
+    use crate::commit::tests::{wait_for_hits, wait_json, wait_until};
+    use crate::handlers::{add_document, all_docs, flush};
+    use crate::SearchResults;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_max_open_indices() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (path, settings, schema) = catalog_fixture("toshi_test_max_open_indices")?;
+        let settings = Settings {
+            max_open_indices: 1,
+            ..settings
+        };
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+
+        for name in &["first", "second"] {
+            catalog.add_index(name, schema.clone()).await?;
+            let doc = r#"{"document": { "test_text": "Lazy Document" } }"#;
+            add_document(Arc::clone(&catalog), Body::from(doc), name).await?;
+            flush(Arc::clone(&catalog), name).await?;
+        }
+        assert_eq!(catalog.get_collection().len(), 1);
+        assert_eq!(catalog.list_indexes().await, vec!["first", "second"]);
+
+        for name in &["first", "second", "first"] {
+            let resp = all_docs(Arc::clone(&catalog), name).await?;
+            let docs: SearchResults = wait_json(resp).await;
+            assert_eq!(docs.hits, 1);
+            assert_eq!(catalog.get_collection().len(), 1);
+            assert!(catalog.get_collection().contains_key(*name));
+        }
+
+        // An index someone still holds a handle to keeps its writer, so it isn't closed under them
+        let held = catalog.get_index("first").await?;
+        let resp = all_docs(Arc::clone(&catalog), "second").await?;
+        assert_eq!(wait_json::<SearchResults>(resp).await.hits, 1);
+        assert_eq!(catalog.get_collection().len(), 2);
+        drop(held);
+        let resp = all_docs(Arc::clone(&catalog), "second").await?;
+        assert_eq!(wait_json::<SearchResults>(resp).await.hits, 1);
+        assert_eq!(catalog.get_collection().len(), 1);
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_pending_ops() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (path, settings, schema) = catalog_fixture("toshi_test_max_pending_ops")?;
+        let settings = Settings {
+            max_pending_ops: 2,
+            ..settings
+        };
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+        catalog.add_index("pending", schema).await?;
+        let doc = r#"{"document": { "test_text": "Pending Document" } }"#;
+        add_document(Arc::clone(&catalog), Body::from(doc), "pending").await?;
+        assert_eq!(catalog.get_index("pending").await?.get_opstamp(), 1);
+        add_document(Arc::clone(&catalog), Body::from(doc), "pending").await?;
+        assert_eq!(catalog.get_index("pending").await?.get_opstamp(), 0);
+
+        assert_eq!(wait_for_hits(&catalog, "pending", 2).await, 2);
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_threads() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::handlers::doc_search;
+
+        let (path, settings, schema) = catalog_fixture("toshi_test_search_threads")?;
+        let settings = |search_threads| Settings {
+            search_threads,
+            ..settings.clone()
+        };
+        let search = |catalog: Arc<IndexCatalog>| async move {
+            let body = Body::from(r#"{ "query": { "raw": "test_text:document" } }"#);
+            let docs: SearchResults = wait_json(doc_search(catalog, body, "segments").await.unwrap()).await;
+            docs.get_docs()
+                .iter()
+                .map(|d| (d.score, d.doc.get_str("test_text")))
+                .collect::<Vec<_>>()
+        };
+
+        let catalog = Arc::new(IndexCatalog::new(settings(4))?);
+        catalog.add_index("segments", schema).await?;
+        // A commit per document leaves each one in its own segment
+        for text in &["First Document", "Second Document document", "Third Document", "Fourth"] {
+            let doc = format!(r#"{{"options": {{ "commit": true }}, "document": {{ "test_text": "{}" }} }}"#, text);
+            add_document(Arc::clone(&catalog), Body::from(doc), "segments").await?;
+        }
+        let parallel = wait_until(|| search(Arc::clone(&catalog)), |docs| docs.len() == 3).await;
+        assert_eq!(parallel.len(), 3);
+        assert!(catalog.get_index("segments").await?.get_index().searchable_segment_ids()?.len() >= 3);
+        catalog.clear().await;
+        drop(catalog);
+
+        let catalog = Arc::new(IndexCatalog::new(settings(0))?);
+        catalog.refresh_catalog().await?;
+        assert_eq!(search(Arc::clone(&catalog)).await, parallel);
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_searches() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::handlers::doc_search;
+        use hyper::StatusCode;
+
+        let (path, settings, schema) = catalog_fixture("toshi_test_max_concurrent_searches")?;
+        let settings = Settings {
+            max_concurrent_searches: 2,
+            ..settings
+        };
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+        catalog.add_index("limited", schema).await?;
+        let search = || {
+            doc_search(
+                Arc::clone(&catalog),
+                Body::from(r#"{ "query": { "raw": "test_text:document" } }"#),
+                "limited",
+            )
+        };
+
+        // Both permits are taken as if two searches were still running
+        let handle = catalog.get_index("limited").await?;
+        let running = handle.search_permits().unwrap().try_acquire_many(2)?;
+        let (first, second, third) = tokio::join!(search(), search(), search());
+        for resp in [first?, second?, third?] {
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(resp.headers()[hyper::header::RETRY_AFTER], "1");
+        }
+
+        drop(running);
+        let (first, second) = tokio::join!(search(), search());
+        assert_eq!(first?.status(), StatusCode::OK);
+        assert_eq!(second?.status(), StatusCode::OK);
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_search_while_creating() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (path, settings, schema) = catalog_fixture("toshi_test_search_while_creating")?;
+        let catalog = Arc::new(IndexCatalog::new(settings)?);
+        catalog.add_index("existing", schema.clone()).await?;
+        let doc = r#"{"document": { "test_text": "Searchable Document" }, "options": { "commit": true } }"#;
+        add_document(Arc::clone(&catalog), Body::from(doc), "existing").await?;
+        // The reader reloads in the background after a commit
+        assert_eq!(wait_for_hits(&catalog, "existing", 1).await, 1);
+
+        let creator = {
+            let catalog = Arc::clone(&catalog);
+            tokio::spawn(async move {
+                for i in 0..10 {
+                    catalog.add_index(&format!("created_{}", i), schema.clone()).await.unwrap();
+                }
+            })
+        };
+
+        let mut slowest = std::time::Duration::default();
+        while !creator.is_finished() {
+            let start = std::time::Instant::now();
+            let resp = all_docs(Arc::clone(&catalog), "existing").await?;
+            let docs: SearchResults = wait_json(resp).await;
+            assert_eq!(docs.hits, 1);
+            slowest = slowest.max(start.elapsed());
+        }
+        creator.await?;
+        assert!(slowest < std::time::Duration::from_secs(1), "Slowest search took {:?}", slowest);
+        assert_eq!(catalog.list_indexes().await.len(), 11);
+
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frozen_reopened() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (path, settings, schema) = catalog_fixture("toshi_test_frozen_reopened")?;
+        let catalog = IndexCatalog::new(settings.clone())?;
+        catalog.add_index("frozen", schema).await?;
+        catalog.get_index("frozen").await?.freeze().await?;
+        catalog.clear().await;
+        drop(catalog);
+
+        let catalog = IndexCatalog::new(settings)?;
+        catalog.refresh_catalog().await?;
+        let handle = catalog.get_index("frozen").await?;
+        assert!(handle.is_frozen());
+        assert!(handle.get_settings().frozen);
+        // Settings updates leave the frozen flag to freezing and thawing
+        handle.set_settings(toshi_types::IndexSettings::default()).await?;
+        assert!(handle.get_settings().frozen);
+
+        handle.thaw().await?;
+        assert!(!handle.is_frozen());
+        assert!(!handle.get_settings().frozen);
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_name() {
+        let path = Path::new("..").join("data").join("toshi_idx");
+        assert_eq!(index_name(&path, ""), Some("toshi_idx".into()));
+        assert_eq!(index_name(&path, "toshi_"), Some("idx".into()));
+        assert_eq!(index_name(&path, "other_"), None);
+        assert_eq!(index_name(&path, "toshi_idx"), None);
+        assert_eq!(index_name(&Path::new("data").join(".node_id"), ""), None);
+        assert_eq!(index_name(Path::new("/var/data/toshi_idx/"), "toshi_"), Some("idx".into()));
+        assert_eq!(index_name(Path::new("..\\data\\toshi_idx"), "toshi_"), Some("idx".into()));
+        assert_eq!(index_name(Path::new("C:\\data\\toshi_idx\\"), ""), Some("toshi_idx".into()));
+    }
+
+    #[tokio::test]
+    async fn test_index_prefix() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (path, settings, schema) = catalog_fixture("toshi_test_index_prefix")?;
+        fs::create_dir_all(path.join("unrelated"))?;
+        fs::create_dir_all(path.join("toshi_empty"))?;
+        let settings = Settings {
+            index_prefix: "toshi_".into(),
+            ..settings
+        };
+        let catalog = IndexCatalog::new(settings.clone())?;
+        catalog.add_index("prefixed", schema).await?;
+        assert!(path.join("toshi_prefixed").join("meta.json").exists());
+        catalog.clear().await;
+        drop(catalog);
+
+        let catalog = IndexCatalog::new(settings)?;
+        assert_eq!(catalog.refresh_catalog().await?, vec!["prefixed"]);
+        assert_eq!(catalog.get_index("prefixed").await?.get_name(), "prefixed");
+        clear_catalog_fixture(&catalog, &path).await;
+        Ok(())
+    }
+}
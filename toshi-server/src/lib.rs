@@ -19,6 +19,7 @@ pub mod index;
 pub mod router;
 pub mod settings;
 pub mod shutdown;
+pub mod tasks;
 pub mod utils;
 
 pub type Result<T> = std::result::Result<T, toshi_types::Error>;
@@ -75,3 +76,31 @@ pub fn register_tokenizers(idx: tantivy::Index) -> tantivy::Index {
 pub fn register_tokenizers(idx: tantivy::Index) -> tantivy::Index {
     idx
 }
+
+/// Tokenizers `register_tokenizers` adds on top of Tantivy's defaults
+#[cfg(feature = "extra_tokenizers")]
+const EXTRA_TOKENIZERS: &[&str] = &[cang_jie::CANG_JIE];
+#[cfg(not(feature = "extra_tokenizers"))]
+const EXTRA_TOKENIZERS: &[&str] = &[];
+
+/// Check that every tokenizer a schema names will be available once the index is opened, an
+/// unknown tokenizer otherwise only shows up as an error when the first document is indexed
+pub fn validate_tokenizers(schema: &tantivy::schema::Schema) -> Result<()> {
+    use tantivy::schema::FieldType;
+
+    let defaults = tantivy::tokenizer::TokenizerManager::default();
+    for (_, entry) in schema.fields() {
+        let indexing = match entry.field_type() {
+            FieldType::Str(opts) => opts.get_indexing_options(),
+            FieldType::JsonObject(opts) => opts.get_text_indexing_options(),
+            _ => None,
+        };
+        if let Some(tokenizer) = indexing.map(|i| i.tokenizer()) {
+            if defaults.get(tokenizer).is_none() && !EXTRA_TOKENIZERS.contains(&tokenizer) {
+                let msg = format!("Unknown tokenizer: {} for field: {}", tokenizer, entry.name());
+                return Err(toshi_types::Error::QueryError(msg));
+            }
+        }
+    }
+    Ok(())
+}
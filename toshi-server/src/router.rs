@@ -4,16 +4,17 @@ use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Method, Request, Response, Server};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
 
 use log::*;
 use tower_util::BoxService;
 
-use toshi_types::{Catalog, QueryOptions};
+use toshi_types::{Catalog, Error, QueryOptions};
 
 use crate::handlers::*;
 use crate::settings::Settings;
-use crate::utils::{not_found, parse_path};
+use crate::tasks::TaskRegistry;
+use crate::utils::{error_response, not_found, parse_path, strip_base_path};
 
 pub type BoxedFn = BoxService<Request<Body>, Response<Body>, hyper::Error>;
 
@@ -22,6 +23,7 @@ pub struct Router<C: Catalog> {
     pub cat: Arc<C>,
     pub watcher: Arc<AtomicBool>,
     pub settings: Settings,
+    pub tasks: Arc<TaskRegistry>,
 }
 
 impl<C: Catalog> Router<C> {
@@ -30,12 +32,18 @@ impl<C: Catalog> Router<C> {
     }
 
     pub fn from_settings(cat: Arc<C>, watcher: Arc<AtomicBool>, settings: Settings) -> Self {
-        Self { cat, watcher, settings }
+        Self {
+            cat,
+            watcher,
+            settings,
+            tasks: Arc::new(TaskRegistry::default()),
+        }
     }
 
     pub async fn route(
         catalog: Arc<C>,
         watcher: Arc<AtomicBool>,
+        tasks: Arc<TaskRegistry>,
         req: Request<Body>,
         settings: Settings,
     ) -> Result<Response<Body>, hyper::Error> {
@@ -47,25 +55,60 @@ impl<C: Catalog> Router<C> {
             .unwrap_or_default();
 
         let method = parts.method;
-        let path = parse_path(parts.uri.path());
+        // Deployments behind a proxy at a sub path only serve requests under the configured base path
+        let path = match strip_base_path(parse_path(parts.uri.path()), &settings.base_path) {
+            Some(path) => path,
+            None => return not_found().await,
+        };
 
         match (&method, &path[..]) {
             (m, ["_list"]) if m == Method::GET => list_indexes(catalog).await,
-            (m, [idx, "_create"]) if m == Method::PUT => create_index(catalog, body, idx).await,
+            (m, ["_reload"]) if m == Method::POST => reload(catalog).await,
+            (m, ["_tasks"]) if m == Method::GET => list_tasks(&tasks).await,
+            (m, ["_tasks", id]) if m == Method::DELETE => cancel_task(&tasks, id).await,
+            (m, [idx, "_create"]) if m == Method::PUT => match query_options.template() {
+                Some(template) => create_index_from_template(catalog, idx, template, &settings.schema_templates).await,
+                None => create_index_with_tokenizer(catalog, body, idx, settings.default_tokenizer.as_deref()).await,
+            },
             (m, [idx, "_summary"]) if m == Method::GET => index_summary(catalog, idx, query_options).await,
             (m, [idx, "_flush"]) if m == Method::GET => flush(catalog, idx).await,
+            (m, [idx, "_warmup"]) if m == Method::POST => warmup(catalog, idx).await,
+            (m, [idx, "_recreate_writer"]) if m == Method::POST => recreate_writer(catalog, idx).await,
+            (m, [idx, "_freeze"]) if m == Method::POST => freeze(catalog, idx).await,
+            (m, [idx, "_thaw"]) if m == Method::POST => thaw(catalog, idx).await,
+            (m, [idx, "_tail"]) if m == Method::GET => tail_index(catalog, idx, query_options.since()).await,
+            (m, [idx, "_settings"]) if m == Method::GET => get_index_settings(catalog, idx).await,
+            (m, [idx, "_settings"]) if m == Method::PUT => update_index_settings(catalog, body, idx).await,
+            (m, [idx, "_count_by"]) if m == Method::POST => count_by(catalog, body, idx).await,
+            (m, [idx, "_delete_by_query"]) if m == Method::POST => delete_by_query(catalog, body, idx).await,
+            (m, [idx, "_query_explain"]) if m == Method::POST => query_explain(catalog, body, idx).await,
+            (m, [idx, "_validate_query"]) if m == Method::POST => validate_query(catalog, body, idx).await,
+            (m, [idx, "_snapshot"]) if m == Method::POST => snapshot(catalog, idx, &settings.snapshot_path).await,
+            (m, [idx, "_restore"]) if m == Method::POST => restore(catalog, body, idx, &settings.snapshot_path).await,
             (m, [idx, "_bulk"]) if m == Method::POST => {
                 let w = Arc::clone(&watcher);
-                bulk_insert(catalog, w, body, idx, settings.json_parsing_threads, settings.max_line_length).await
+                let task = tasks.register("bulk", idx);
+                bulk_insert(catalog, w, body, idx, BulkOptions::from(&settings), &query_options, task).await
+            }
+            (m, ["_search"]) if m == Method::POST => match &settings.default_index {
+                Some(idx) => doc_search_checked(catalog, body, idx, settings.max_result_limit, settings.max_offset).await,
+                None => {
+                    let err = Error::QueryError("No index given and no default_index is configured".into());
+                    Ok(error_response(StatusCode::BAD_REQUEST, err))
+                }
+            },
+            (m, [idx]) if m == Method::POST => doc_search_checked(catalog, body, idx, settings.max_result_limit, settings.max_offset).await,
+            (m, [idx]) if m == Method::PUT => {
+                add_document_checked(catalog, body, idx, settings.strict_fields, settings.max_document_size).await
             }
-            (m, [idx]) if m == Method::POST => doc_search(catalog, body, idx).await,
-            (m, [idx]) if m == Method::PUT => add_document(catalog, body, idx).await,
             (m, [idx]) if m == Method::DELETE => delete_term(catalog, body, idx).await,
             (m, [idx]) if m == Method::GET => {
                 if idx == &"favicon.ico" {
                     not_found().await
+                } else if query_options.count_only() {
+                    all_docs_count(catalog, idx).await
                 } else {
-                    all_docs(catalog, idx).await
+                    all_docs_limit(catalog, idx, query_options.limit(), settings.max_result_limit).await
                 }
             }
             (m, []) if m == Method::GET => root().await,
@@ -73,15 +116,33 @@ impl<C: Catalog> Router<C> {
         }
     }
 
-    pub async fn service_call(catalog: Arc<C>, watcher: Arc<AtomicBool>, settings: Settings) -> Result<BoxedFn, Infallible> {
+    pub async fn service_call(
+        catalog: Arc<C>,
+        watcher: Arc<AtomicBool>,
+        tasks: Arc<TaskRegistry>,
+        settings: Settings,
+    ) -> Result<BoxedFn, Infallible> {
         Ok(BoxService::new(service_fn(move |req| {
             info!("REQ = {:?}", &req);
-            Self::route(Arc::clone(&catalog), Arc::clone(&watcher), req, settings.clone())
+            Self::route(
+                Arc::clone(&catalog),
+                Arc::clone(&watcher),
+                Arc::clone(&tasks),
+                req,
+                settings.clone(),
+            )
         })))
     }
 
     pub async fn router_with_catalog(self, addr: SocketAddr) -> Result<(), hyper::Error> {
-        let routes = make_service_fn(move |_| Self::service_call(Arc::clone(&self.cat), Arc::clone(&self.watcher), self.settings.clone()));
+        let routes = make_service_fn(move |_| {
+            Self::service_call(
+                Arc::clone(&self.cat),
+                Arc::clone(&self.watcher),
+                Arc::clone(&self.tasks),
+                self.settings.clone(),
+            )
+        });
         let server = Server::bind(&addr).serve(routes);
         if let Err(err) = server.await {
             trace!("server error: {}", err);
@@ -91,7 +152,14 @@ impl<C: Catalog> Router<C> {
 
     #[allow(dead_code)]
     pub(crate) async fn router_from_tcp(self, listener: TcpListener) -> Result<(), hyper::Error> {
-        let routes = make_service_fn(move |_| Self::service_call(Arc::clone(&self.cat), Arc::clone(&self.watcher), self.settings.clone()));
+        let routes = make_service_fn(move |_| {
+            Self::service_call(
+                Arc::clone(&self.cat),
+                Arc::clone(&self.watcher),
+                Arc::clone(&self.tasks),
+                self.settings.clone(),
+            )
+        });
         let server = Server::from_tcp(listener)?.serve(routes);
         if let Err(err) = server.await {
             trace!("server error: {}", err);
@@ -99,3 +167,113 @@ impl<C: Catalog> Router<C> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commit::tests::wait_json;
+    use crate::index::create_test_catalog;
+    use crate::SearchResults;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_all_docs_limit() -> Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let watcher = Arc::new(AtomicBool::new(false));
+        let req = Request::get("/test_index?limit=3").body(Body::empty())?;
+        let resp = Router::route(Arc::clone(&catalog), Arc::clone(&watcher), Arc::default(), req, Settings::default()).await?;
+        let docs: SearchResults = wait_json(resp).await;
+        assert_eq!(docs.hits, 3);
+
+        let settings = Settings {
+            max_result_limit: 2,
+            ..Default::default()
+        };
+        let req = Request::get("/test_index?limit=3").body(Body::empty())?;
+        let resp = Router::route(Arc::clone(&catalog), Arc::clone(&watcher), Arc::default(), req, settings).await?;
+        let docs: SearchResults = wait_json(resp).await;
+        assert_eq!(docs.hits, 2);
+
+        // Query strings that don't parse fall back to the default options like every other route
+        let req = Request::get("/test_index?limit=asdf").body(Body::empty())?;
+        let resp = Router::route(catalog, watcher, Arc::default(), req, Settings::default()).await?;
+        let docs: SearchResults = wait_json(resp).await;
+        assert_eq!(docs.hits, 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_limit_clamped() -> Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let watcher = Arc::new(AtomicBool::new(false));
+        let settings = Settings {
+            max_result_limit: 2,
+            ..Default::default()
+        };
+        let req = Request::post("/test_index").body(Body::from(r#"{ "limit": 4 }"#))?;
+        let resp = Router::route(Arc::clone(&catalog), Arc::clone(&watcher), Arc::default(), req, settings.clone()).await?;
+        let posted: SearchResults = wait_json(resp).await;
+        let req = Request::get("/test_index?limit=4").body(Body::empty())?;
+        let resp = Router::route(catalog, watcher, Arc::default(), req, settings).await?;
+        let got: SearchResults = wait_json(resp).await;
+        assert_eq!(posted.hits, 2);
+        assert_eq!(posted.get_pagination(), got.get_pagination());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_base_path() -> Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let watcher = Arc::new(AtomicBool::new(false));
+        let settings = Settings {
+            base_path: "/toshi".into(),
+            ..Default::default()
+        };
+        let req = Request::post("/toshi/test_index").body(Body::from(r#"{ "limit": 2 }"#))?;
+        let resp = Router::route(Arc::clone(&catalog), Arc::clone(&watcher), Arc::default(), req, settings.clone()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let docs: SearchResults = wait_json(resp).await;
+        assert_eq!(docs.hits, 2);
+
+        let req = Request::post("/test_index").body(Body::from(r#"{ "limit": 2 }"#))?;
+        let resp = Router::route(catalog, watcher, Arc::default(), req, settings).await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_count_only() -> Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let watcher = Arc::new(AtomicBool::new(false));
+        let req = Request::get("/test_index?count_only=true").body(Body::empty())?;
+        let resp = Router::route(Arc::clone(&catalog), Arc::clone(&watcher), Arc::default(), req, Settings::default()).await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let docs: SearchResults = wait_json(resp).await;
+        assert_eq!(docs.hits, 5);
+        assert!(docs.get_docs().is_empty());
+
+        let req = Request::get("/asdf?count_only=true").body(Body::empty())?;
+        let resp = Router::route(catalog, watcher, Arc::default(), req, Settings::default()).await?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_index_search() -> Result<(), Box<dyn std::error::Error>> {
+        let catalog = create_test_catalog("test_index");
+        let watcher = Arc::new(AtomicBool::new(false));
+        let settings = Settings {
+            default_index: Some("test_index".into()),
+            ..Default::default()
+        };
+        let req = Request::post("/_search").body(Body::from(r#"{ "query": { "raw": "test_text:document" } }"#))?;
+        let resp = Router::route(Arc::clone(&catalog), Arc::clone(&watcher), Arc::default(), req, settings).await?;
+        let docs: SearchResults = wait_json(resp).await;
+        assert_eq!(docs.hits, 3);
+
+        let req = Request::post("/_search").body(Body::empty())?;
+        let resp = Router::route(catalog, watcher, Arc::default(), req, Settings::default()).await?;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 use config::{Config, ConfigError, File, FileFormat, Source};
 use serde::Deserialize;
 use structopt::StructOpt;
 use tantivy::merge_policy::*;
+use toshi_types::{SchemaBody, Search};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -47,6 +49,19 @@ pub const DEFAULT_RPC_PORT: u16 = 8081;
 pub const DEFAULT_LEVEL_LOG_SIZE: f64 = 0.75;
 pub const DEFAULT_MIN_LAYER_SIZE: u32 = 10_000;
 pub const DEFAULT_MIN_MERGE_SIZE: usize = 8;
+pub const DEFAULT_MAX_OPEN_INDICES: usize = 0;
+pub const DEFAULT_MAX_RESULT_LIMIT: usize = Search::DEFAULT_MAX_LIMIT;
+pub const DEFAULT_SNAPSHOT_PATH: &str = "snapshots/";
+pub const DEFAULT_INDEX_PREFIX: &str = "";
+pub const DEFAULT_BASE_PATH: &str = "";
+pub const DEFAULT_MAX_DOCUMENT_SIZE: usize = 10_485_760;
+pub const DEFAULT_MAX_PENDING_OPS: usize = 0;
+pub const DEFAULT_SEARCH_THREADS: usize = 0;
+pub const DEFAULT_MAX_CONCURRENT_SEARCHES: usize = 0;
+pub const DEFAULT_MAX_OFFSET: usize = Search::DEFAULT_MAX_OFFSET;
+pub const DEFAULT_MAX_QUERY_CLAUSES: usize = 1024;
+pub const DEFAULT_MAX_QUERY_DEPTH: usize = 20;
+pub const DEFAULT_MAX_TERM_LENGTH: usize = 0;
 
 pub fn default_merge_policy() -> ConfigMergePolicy {
     ConfigMergePolicy {
@@ -137,6 +152,44 @@ pub struct Settings {
     pub bulk_buffer_size: usize,
     #[structopt(short, long, default_value = "10000")]
     pub max_line_length: usize,
+    #[structopt(long, default_value = "0")]
+    pub max_open_indices: usize,
+    #[structopt(long, default_value = "10000")]
+    pub max_result_limit: usize,
+    #[structopt(long, default_value = "snapshots/")]
+    pub snapshot_path: String,
+    #[structopt(long, default_value = "")]
+    pub index_prefix: String,
+    #[structopt(long, default_value = "")]
+    pub base_path: String,
+    #[structopt(long)]
+    pub default_index: Option<String>,
+    #[structopt(long)]
+    pub strict_fields: bool,
+    #[structopt(long)]
+    pub strict_mapping: bool,
+    #[structopt(long, default_value = "10485760")]
+    pub max_document_size: usize,
+    #[structopt(long, default_value = "0")]
+    pub max_pending_ops: usize,
+    #[structopt(long, default_value = "0")]
+    pub search_threads: usize,
+    #[structopt(long, default_value = "0")]
+    pub max_concurrent_searches: usize,
+    #[structopt(long)]
+    pub default_tokenizer: Option<String>,
+    #[structopt(long, default_value = "10000")]
+    pub max_offset: usize,
+    #[structopt(long, default_value = "1024")]
+    pub max_query_clauses: usize,
+    #[structopt(long, default_value = "20")]
+    pub max_query_depth: usize,
+    #[structopt(long, default_value = "0")]
+    pub max_term_length: usize,
+    #[structopt(long)]
+    pub truncate_long_terms: bool,
+    #[structopt(skip)]
+    pub schema_templates: BTreeMap<String, SchemaBody>,
     #[structopt(flatten)]
     pub merge_policy: ConfigMergePolicy,
     #[structopt(short, long)]
@@ -159,6 +212,25 @@ impl Default for Settings {
             auto_commit_duration: DEFAULT_AUTO_COMMIT_DURATION,
             bulk_buffer_size: DEFAULT_BULK_BUFFER_SIZE,
             max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            max_open_indices: DEFAULT_MAX_OPEN_INDICES,
+            max_result_limit: DEFAULT_MAX_RESULT_LIMIT,
+            snapshot_path: DEFAULT_SNAPSHOT_PATH.into(),
+            index_prefix: DEFAULT_INDEX_PREFIX.into(),
+            base_path: DEFAULT_BASE_PATH.into(),
+            default_index: None,
+            strict_fields: false,
+            strict_mapping: false,
+            max_document_size: DEFAULT_MAX_DOCUMENT_SIZE,
+            max_pending_ops: DEFAULT_MAX_PENDING_OPS,
+            search_threads: DEFAULT_SEARCH_THREADS,
+            max_concurrent_searches: DEFAULT_MAX_CONCURRENT_SEARCHES,
+            default_tokenizer: None,
+            max_offset: DEFAULT_MAX_OFFSET,
+            max_query_clauses: DEFAULT_MAX_QUERY_CLAUSES,
+            max_query_depth: DEFAULT_MAX_QUERY_DEPTH,
+            max_term_length: DEFAULT_MAX_TERM_LENGTH,
+            truncate_long_terms: false,
+            schema_templates: BTreeMap::new(),
             merge_policy: ConfigMergePolicy::default(),
             experimental: false,
             experimental_features: Experimental::default(),
@@ -183,6 +255,11 @@ impl Settings {
         Config::builder().add_source(c).build()?.try_deserialize::<Self>()
     }
 
+    /// The name of the directory an index is stored in under `path`
+    pub fn index_dir_name(&self, index: &str) -> String {
+        format!("{}{}", self.index_prefix, index)
+    }
+
     pub fn get_nodes(&self) -> Vec<String> {
         self.experimental_features.nodes.clone()
     }
@@ -196,7 +273,7 @@ impl Settings {
                 mp.set_max_docs_before_merge(self.merge_policy.min_merge_size);
                 Box::new(mp)
             }
-            MergePolicyType::NoMerge => Box::new(NoMergePolicy::default()),
+            MergePolicyType::NoMerge => Box::new(NoMergePolicy),
         }
     }
 }
@@ -218,6 +295,25 @@ mod tests {
         assert_eq!(default.json_parsing_threads, 4);
         assert_eq!(default.bulk_buffer_size, 10000);
         assert_eq!(default.max_line_length, 10000);
+        assert_eq!(default.max_open_indices, 0);
+        assert_eq!(default.max_result_limit, 10_000);
+        assert_eq!(default.snapshot_path, "snapshots/");
+        assert_eq!(default.index_prefix, "");
+        assert_eq!(default.base_path, "");
+        assert_eq!(default.default_index, None);
+        assert!(!default.strict_fields);
+        assert!(!default.strict_mapping);
+        assert_eq!(default.max_document_size, 10_485_760);
+        assert_eq!(default.max_pending_ops, 0);
+        assert_eq!(default.search_threads, 0);
+        assert_eq!(default.max_concurrent_searches, 0);
+        assert_eq!(default.default_tokenizer, None);
+        assert_eq!(default.max_offset, 10_000);
+        assert_eq!(default.max_query_clauses, 1024);
+        assert_eq!(default.max_query_depth, 20);
+        assert_eq!(default.max_term_length, 0);
+        assert!(!default.truncate_long_terms);
+        assert!(default.schema_templates.is_empty());
         assert_eq!(default.merge_policy.kind, "log");
         assert!(cmp_float(default.merge_policy.level_log_size as f32, 0.75));
         assert_eq!(default.merge_policy.min_layer_size, 10_000);
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+/// A snapshot of an in-flight operation as it's listed by `GET /_tasks`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaskInfo {
+    pub id: u64,
+    /// What sort of operation this is, such as "bulk"
+    pub kind: String,
+    pub index: String,
+    /// Documents read from the request so far
+    pub docs_received: u64,
+    /// Documents handed to the index writer so far
+    pub docs_indexed: u64,
+}
+
+#[derive(Default)]
+struct Progress {
+    received: AtomicU64,
+    indexed: AtomicU64,
+}
+
+struct TaskState {
+    kind: &'static str,
+    index: String,
+    progress: Arc<Progress>,
+    token: CancellationToken,
+}
+
+/// Long running operations that are currently in flight, tasks remove themselves once dropped
+#[derive(Default)]
+pub struct TaskRegistry {
+    next_id: AtomicU64,
+    tasks: DashMap<u64, TaskState>,
+}
+
+impl TaskRegistry {
+    pub fn register(self: &Arc<Self>, kind: &'static str, index: &str) -> Task {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let progress = Arc::new(Progress::default());
+        let token = CancellationToken::new();
+        let state = TaskState {
+            kind,
+            index: index.into(),
+            progress: Arc::clone(&progress),
+            token: token.clone(),
+        };
+        self.tasks.insert(id, state);
+        Task {
+            id,
+            progress,
+            token,
+            registry: Arc::clone(self),
+        }
+    }
+
+    pub fn list(&self) -> Vec<TaskInfo> {
+        let mut tasks: Vec<TaskInfo> = self.tasks.iter().map(|e| Self::info(*e.key(), e.value())).collect();
+        tasks.sort_by_key(|t| t.id);
+        tasks
+    }
+
+    /// Signal a task to stop, returning what it looked like when it was cancelled
+    pub fn cancel(&self, id: u64) -> Option<TaskInfo> {
+        self.tasks.get(&id).map(|e| {
+            e.value().token.cancel();
+            Self::info(id, e.value())
+        })
+    }
+
+    fn info(id: u64, state: &TaskState) -> TaskInfo {
+        TaskInfo {
+            id,
+            kind: state.kind.into(),
+            index: state.index.clone(),
+            docs_received: state.progress.received.load(Ordering::SeqCst),
+            docs_indexed: state.progress.indexed.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// The operation's side of a registered task, used to report progress and check for cancellation
+pub struct Task {
+    id: u64,
+    progress: Arc<Progress>,
+    token: CancellationToken,
+    registry: Arc<TaskRegistry>,
+}
+
+impl Task {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub fn received(&self, docs: u64) {
+        self.progress.received.fetch_add(docs, Ordering::SeqCst);
+    }
+
+    pub fn indexed(&self, docs: u64) {
+        self.progress.indexed.fetch_add(docs, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.registry.tasks.remove(&self.id);
+    }
+}
@@ -1,4 +1,7 @@
+use bytes::BytesMut;
+use futures::StreamExt;
 use http::{Response, StatusCode};
+use hyper::body::HttpBody;
 use hyper::Body;
 use serde::Serialize;
 use toshi_types::{Error, ErrorResponse};
@@ -29,10 +32,33 @@ pub async fn not_found() -> Result<Response<Body>, hyper::Error> {
     Ok(empty_with_code(StatusCode::NOT_FOUND))
 }
 
+/// Read a request body, returning `None` as soon as it's known to be larger than `max_bytes`
+/// rather than buffering the rest of it
+pub async fn read_limited(mut body: Body, max_bytes: usize) -> Result<Option<BytesMut>, hyper::Error> {
+    if body.size_hint().lower() > max_bytes as u64 {
+        return Ok(None);
+    }
+    let mut buf = BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > max_bytes {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Some(buf))
+}
+
 pub fn parse_path(path: &str) -> Vec<&str> {
     path.trim_matches('/').split('/').filter(|s| !s.is_empty()).collect()
 }
 
+/// The segments of `path` that follow `base_path`, or None when `path` isn't under it
+pub fn strip_base_path<'a>(path: Vec<&'a str>, base_path: &str) -> Option<Vec<&'a str>> {
+    let base = parse_path(base_path);
+    path.starts_with(&base).then(|| path[base.len()..].to_vec())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -54,4 +80,13 @@ mod tests {
         assert_eq!(parsed_two[0], "path");
         assert_eq!(parsed_two[1], "two");
     }
+
+    #[test]
+    fn test_strip_base_path() {
+        assert_eq!(strip_base_path(parse_path("/idx/_flush"), ""), Some(vec!["idx", "_flush"]));
+        assert_eq!(strip_base_path(parse_path("/toshi/idx"), "/toshi/"), Some(vec!["idx"]));
+        assert_eq!(strip_base_path(parse_path("/toshi"), "toshi"), Some(vec![]));
+        assert_eq!(strip_base_path(parse_path("/toshio/idx"), "/toshi"), None);
+        assert_eq!(strip_base_path(parse_path("/idx"), "/toshi"), None);
+    }
 }
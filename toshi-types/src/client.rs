@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::iter::Sum;
 use std::ops::Add;
 
@@ -5,22 +6,158 @@ use serde::{Deserialize, Serialize};
 use tantivy::space_usage::SearcherSpaceUsage;
 use tantivy::IndexMeta;
 
+use crate::query::agg::AggregationResult;
 use crate::query::KeyValue;
 
+/// The byte offsets of a single query match inside a field's text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchOffset {
+    /// Offset of the first byte of the match
+    pub start: usize,
+    /// Offset just past the last byte of the match
+    pub end: usize,
+}
+
+/// A fragment of a stored text value around where the query matched
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    /// The text of the fragment
+    pub fragment: String,
+    /// Where the query matched, as byte offsets into the fragment
+    pub highlighted: Vec<MatchOffset>,
+}
+
 /// A single document returned from a Tantivy Index
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ScoredDoc<D: Clone> {
     /// The document's relevancy score
     pub score: Option<f32>,
+    /// The value of the index's id field, when one is configured
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     /// The actual document
     pub doc: D,
+    /// Where the query matched in each text field, when requested
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub match_offsets: BTreeMap<String, Vec<MatchOffset>>,
+    /// Fragments of each highlighted field, when requested
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub highlights: BTreeMap<String, Vec<Highlight>>,
 }
 
 impl<D: Clone> ScoredDoc<D> {
     /// Constructor for a new ScoredDoc
     pub fn new(score: Option<f32>, doc: D) -> Self {
-        Self { score, doc }
+        Self {
+            score,
+            id: None,
+            doc,
+            match_offsets: BTreeMap::new(),
+            highlights: BTreeMap::new(),
+        }
+    }
+
+    /// Set the document's id
+    pub fn with_id(mut self, id: Option<String>) -> Self {
+        self.id = id;
+        self
     }
+
+    /// Set where the query matched in the document
+    pub fn with_match_offsets(mut self, match_offsets: BTreeMap<String, Vec<MatchOffset>>) -> Self {
+        self.match_offsets = match_offsets;
+        self
+    }
+
+    /// Set the highlighted fragments of the document
+    pub fn with_highlights(mut self, highlights: BTreeMap<String, Vec<Highlight>>) -> Self {
+        self.highlights = highlights;
+        self
+    }
+}
+
+/// The number of matched documents under a single facet path
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FacetCount {
+    /// The facet path
+    pub value: String,
+    /// Number of matched documents with this facet
+    pub count: u64,
+}
+
+/// Facet counts grouped under the field they were collected from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FacetResult {
+    /// The facet field
+    pub field: String,
+    /// The counts for each facet path in the field
+    pub values: Vec<FacetCount>,
+}
+
+impl FacetResult {
+    /// Constructor for a field's facet counts
+    pub fn new(field: String, values: Vec<FacetCount>) -> Self {
+        Self { field, values }
+    }
+}
+
+/// How long a search took in milliseconds, broken down into executing the query and fetching the
+/// stored documents it returned
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchTiming {
+    /// The whole search, from compiling the query to assembling the results
+    pub took_ms: f64,
+    /// Executing the query and its collectors
+    pub query_ms: f64,
+    /// Loading the stored documents of the hits
+    pub fetch_ms: f64,
+}
+
+/// Where a page of results sits among everything a search matched
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pagination {
+    /// Number of top documents skipped before this page
+    pub offset: usize,
+    /// Max number of documents in a page
+    pub limit: usize,
+    /// Number of documents the search matched, or of distinct values when it collapses on a field
+    pub total: usize,
+    /// Whether there are matched documents after this page
+    pub has_more: bool,
+}
+
+impl Pagination {
+    /// Pagination for a page of `returned` documents
+    pub fn new(offset: usize, limit: usize, total: usize, returned: usize) -> Self {
+        Self {
+            offset,
+            limit,
+            total,
+            has_more: offset + returned < total,
+        }
+    }
+}
+
+/// A document streamed by an index's tail once the commit that added it is searchable
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TailedDoc<D> {
+    /// The opstamp of the commit the document was seen in
+    pub opstamp: u64,
+    /// The document itself
+    pub doc: D,
+}
+
+/// How often a single term of a query occurs across an index
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TermStats {
+    /// The field the term belongs to
+    pub field: String,
+    /// The term's value
+    pub term: String,
+    /// The number of documents containing the term, including deleted documents that haven't been merged away
+    pub doc_freq: u64,
+    /// The number of times the term occurs across all of those documents
+    pub total_term_freq: u64,
 }
 
 /// The Search response object from Toshi
@@ -31,7 +168,23 @@ pub struct SearchResults<D: Clone> {
     /// The actual documents, see [`ScoredDoc`]: ScoredDoc
     docs: Vec<ScoredDoc<D>>,
     /// The, if any, facets returned
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     facets: Vec<KeyValue<String, u64>>,
+    /// The same facet counts as `facets`, grouped by the field they belong to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    facet_results: Vec<FacetResult>,
+    /// The, if any, aggregations computed over the matched documents
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aggregations: BTreeMap<String, AggregationResult>,
+    /// How long the search took, if it was timed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timing: Option<SearchTiming>,
+    /// Statistics for each term of the query, if they were asked for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    term_stats: Vec<TermStats>,
+    /// Where these results sit among everything the search matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pagination: Option<Pagination>,
 }
 
 impl<D: Clone> Add for SearchResults<D> {
@@ -40,11 +193,52 @@ impl<D: Clone> Add for SearchResults<D> {
     fn add(self, mut rhs: SearchResults<D>) -> Self::Output {
         let mut docs = self.docs;
         let mut facets = self.facets;
+        let mut facet_results = self.facet_results;
+        let mut aggregations = self.aggregations;
         let hits = self.hits + rhs.hits;
         facets.append(&mut rhs.facets);
+        facet_results.append(&mut rhs.facet_results);
+        aggregations.append(&mut rhs.aggregations);
         docs.append(&mut rhs.get_docs().to_vec());
+        // Results are combined from searches that ran side by side, so the slowest of each is kept
+        let timing = match (self.timing, rhs.timing) {
+            (Some(l), Some(r)) => Some(SearchTiming {
+                took_ms: l.took_ms.max(r.took_ms),
+                query_ms: l.query_ms.max(r.query_ms),
+                fetch_ms: l.fetch_ms.max(r.fetch_ms),
+            }),
+            (l, r) => l.or(r),
+        };
+        let pagination = match (self.pagination, rhs.pagination) {
+            (Some(l), Some(r)) => Some(Pagination {
+                total: l.total + r.total,
+                has_more: l.has_more || r.has_more,
+                ..l
+            }),
+            (l, r) => l.or(r),
+        };
+        // Each side only counted its own documents, so the stats of the same term are summed
+        let mut term_stats = self.term_stats;
+        for stats in rhs.term_stats {
+            match term_stats.iter_mut().find(|s| s.field == stats.field && s.term == stats.term) {
+                Some(s) => {
+                    s.doc_freq += stats.doc_freq;
+                    s.total_term_freq += stats.total_term_freq;
+                }
+                None => term_stats.push(stats),
+            }
+        }
 
-        Self { hits, docs, facets }
+        Self {
+            hits,
+            docs,
+            facets,
+            facet_results,
+            aggregations,
+            timing,
+            term_stats,
+            pagination,
+        }
     }
 }
 
@@ -63,6 +257,34 @@ impl<D: Clone> SearchResults<D> {
     pub fn get_facets(&self) -> &[KeyValue<String, u64>] {
         &self.facets
     }
+    /// Getter for the returned facets grouped by field
+    pub fn get_facet_results(&self) -> &[FacetResult] {
+        &self.facet_results
+    }
+    /// Getter for the computed aggregations
+    pub fn get_aggregations(&self) -> &BTreeMap<String, AggregationResult> {
+        &self.aggregations
+    }
+    /// Whether any facet counts were returned, without them the `facets` sections are left out
+    pub fn has_facets(&self) -> bool {
+        !self.facets.is_empty() || !self.facet_results.is_empty()
+    }
+    /// Whether any aggregations were computed, without them the `aggregations` section is left out
+    pub fn has_aggregations(&self) -> bool {
+        !self.aggregations.is_empty()
+    }
+    /// Getter for how long the search took
+    pub fn get_timing(&self) -> Option<SearchTiming> {
+        self.timing
+    }
+    /// Getter for the statistics of the query's terms
+    pub fn get_term_stats(&self) -> &[TermStats] {
+        &self.term_stats
+    }
+    /// Getter for where these results sit among everything the search matched
+    pub fn get_pagination(&self) -> Option<Pagination> {
+        self.pagination
+    }
 
     /// Constructor for just documents
     pub fn new(docs: Vec<ScoredDoc<D>>) -> Self {
@@ -70,17 +292,64 @@ impl<D: Clone> SearchResults<D> {
             hits: docs.len(),
             docs,
             facets: Vec::new(),
+            facet_results: Vec::new(),
+            aggregations: BTreeMap::new(),
+            timing: None,
+            term_stats: Vec::new(),
+            pagination: None,
         }
     }
 
+    /// Constructor for a hit count without any documents
+    pub fn from_count(hits: usize) -> Self {
+        let mut results = Self::new(Vec::new());
+        results.hits = hits;
+        results
+    }
+
     /// Constructor for documents with facets
     pub fn with_facets(docs: Vec<ScoredDoc<D>>, facets: Vec<KeyValue<String, u64>>) -> Self {
         Self {
             hits: docs.len(),
             docs,
             facets,
+            facet_results: Vec::new(),
+            aggregations: BTreeMap::new(),
+            timing: None,
+            term_stats: Vec::new(),
+            pagination: None,
         }
     }
+
+    /// Attach facet counts grouped by field to these results
+    pub fn with_facet_results(mut self, facet_results: Vec<FacetResult>) -> Self {
+        self.facet_results = facet_results;
+        self
+    }
+
+    /// Attach computed aggregations to these results
+    pub fn with_aggregations(mut self, aggregations: BTreeMap<String, AggregationResult>) -> Self {
+        self.aggregations = aggregations;
+        self
+    }
+
+    /// Attach how long the search took
+    pub fn with_timing(mut self, timing: SearchTiming) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Attach statistics for the terms of the query
+    pub fn with_term_stats(mut self, term_stats: Vec<TermStats>) -> Self {
+        self.term_stats = term_stats;
+        self
+    }
+
+    /// Attach where these results sit among everything the search matched
+    pub fn with_pagination(mut self, pagination: Pagination) -> Self {
+        self.pagination = Some(pagination);
+        self
+    }
 }
 
 /// A response gotten from the _summary route for an index
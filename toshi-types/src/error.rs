@@ -4,6 +4,7 @@
 
 use std::fmt::Debug;
 
+use http::StatusCode;
 use serde::{Deserialize, Serialize};
 use tantivy::directory::error::OpenDirectoryError;
 use tantivy::query::QueryParserError;
@@ -60,6 +61,9 @@ pub enum Error {
     /// Any Error related to Tantivy
     #[error("Error in Index: '{0}'")]
     TantivyError(#[from] anyhow::Error),
+    /// A document that couldn't be parsed against its index's schema
+    #[error("Error in Index: '{0}'")]
+    DocParsing(String),
     /// Any error related to serde_json
     #[error("Error Parsing Json: '{0}'")]
     JsonParsing(#[from] serde_json::Error),
@@ -72,11 +76,77 @@ pub enum Error {
     /// When attempting to create an index that already exists
     #[error("Index: '{0}' already exists")]
     AlreadyExists(String),
+    /// An index is already running as many searches as it's allowed to at once
+    #[error("Too many concurrent searches against: '{0}'")]
+    TooManySearches(String),
+    /// A write would take an index past the number of documents it's allowed to hold
+    #[error("Index: '{0}' is at its limit of {1} documents")]
+    QuotaExceeded(String, u64),
+    /// A write was sent to an index that has been frozen
+    #[error("Index: '{0}' is frozen, thaw it before writing to it")]
+    IndexFrozen(String),
+    /// An index couldn't be frozen because writes that already hold its writer are still running
+    #[error("Index: '{0}' has writes in progress, freeze it once they finish")]
+    WritesInProgress(String),
     /// When an invalid log config is provided
     #[error("Error Deserializing Error: '{0}'")]
     TomlError(toml::de::Error),
 }
 
+impl Error {
+    /// The HTTP status a response carrying this error is sent with
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Error::UnknownIndexField(_) | Error::QueryError(_) | Error::DocParsing(_) | Error::JsonParsing(_) => StatusCode::BAD_REQUEST,
+            Error::UnknownIndex(_) => StatusCode::NOT_FOUND,
+            Error::AlreadyExists(_) | Error::WritesInProgress(_) => StatusCode::CONFLICT,
+            Error::RPCError(_) => StatusCode::BAD_GATEWAY,
+            Error::TooManySearches(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::QuotaExceeded(..) | Error::IndexFrozen(_) => StatusCode::FORBIDDEN,
+            Error::IOError(_)
+            | Error::SlogError(_)
+            | Error::SpawnError
+            | Error::UnknownError
+            | Error::PoisonedError
+            | Error::HyperError(_)
+            | Error::HttpError(_)
+            | Error::TantivyError(_)
+            | Error::TomlError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The error for a raw query Tantivy couldn't parse, which echoes the query back and, for errors
+    /// about a field, the clause that referenced it and the byte position that clause starts at
+    pub fn raw_query(raw: &str, err: QueryParserError) -> Self {
+        let field = match &err {
+            QueryParserError::FieldDoesNotExist(f)
+            | QueryParserError::FieldNotIndexed(f)
+            | QueryParserError::FieldDoesNotHavePositionsIndexed(f) => Some(f.as_str()),
+            _ => None,
+        };
+        let msg = match field.and_then(|f| field_clause(raw, f)) {
+            Some((position, clause)) => format!("{}, at clause `{}` (position {}) of raw query `{}`", err, clause, position, raw),
+            None => format!("{}, in raw query `{}`", err, raw),
+        };
+        Error::QueryError(msg)
+    }
+}
+
+/// The first clause of a raw query that searches `field`, along with the byte position it starts at
+fn field_clause<'a>(raw: &'a str, field: &str) -> Option<(usize, &'a str)> {
+    let prefix = format!("{}:", field);
+    let start = raw
+        .match_indices(&prefix)
+        .map(|(i, _)| i)
+        .find(|&i| !raw[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_'))?;
+    let value = &raw[start + prefix.len()..];
+    let len = match value.strip_prefix('"') {
+        Some(phrase) => phrase.find('"').map_or(value.len(), |end| end + 2),
+        None => value.find(|c: char| c.is_whitespace() || c == ')').unwrap_or(value.len()),
+    };
+    Some((start, &raw[start..start + prefix.len() + len]))
+}
+
 impl From<OpenDirectoryError> for Error {
     fn from(err: OpenDirectoryError) -> Self {
         Error::TantivyError(err.into())
@@ -85,13 +155,13 @@ impl From<OpenDirectoryError> for Error {
 
 impl From<QueryParserError> for Error {
     fn from(err: QueryParserError) -> Self {
-        Error::TantivyError(err.into())
+        Error::QueryError(err.to_string())
     }
 }
 
 impl From<DocParsingError> for Error {
     fn from(err: DocParsingError) -> Self {
-        Error::TantivyError(err.into())
+        Error::DocParsing(err.to_string())
     }
 }
 
@@ -100,3 +170,45 @@ impl From<TantivyError> for Error {
         Error::TantivyError(err.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        let io = std::io::Error::other("disk");
+        let json = serde_json::from_str::<u64>("asdf").unwrap_err();
+        let cases = vec![
+            (Error::UnknownIndex("idx".into()), StatusCode::NOT_FOUND),
+            (Error::UnknownIndexField("field".into()), StatusCode::BAD_REQUEST),
+            (Error::QueryError("bad".into()), StatusCode::BAD_REQUEST),
+            (Error::TantivyError(anyhow::Error::msg("bad")), StatusCode::INTERNAL_SERVER_ERROR),
+            (QueryParserError::SyntaxError("bad".into()).into(), StatusCode::BAD_REQUEST),
+            (Error::JsonParsing(json), StatusCode::BAD_REQUEST),
+            (DocParsingError::InvalidJson("bad".into()).into(), StatusCode::BAD_REQUEST),
+            (Error::AlreadyExists("idx".into()), StatusCode::CONFLICT),
+            (Error::RPCError("node".into()), StatusCode::BAD_GATEWAY),
+            (Error::TooManySearches("idx".into()), StatusCode::SERVICE_UNAVAILABLE),
+            (Error::QuotaExceeded("idx".into(), 10), StatusCode::FORBIDDEN),
+            (Error::IndexFrozen("idx".into()), StatusCode::FORBIDDEN),
+            (Error::WritesInProgress("idx".into()), StatusCode::CONFLICT),
+            (Error::IOError(io), StatusCode::INTERNAL_SERVER_ERROR),
+            (Error::SpawnError, StatusCode::INTERNAL_SERVER_ERROR),
+            (Error::UnknownError, StatusCode::INTERNAL_SERVER_ERROR),
+            (Error::PoisonedError, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (err, status) in cases {
+            assert_eq!(err.status_code(), status, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_field_clause() {
+        let raw = r#"title:"the life" AND (body:aquatic OR xbody:sea)"#;
+        assert_eq!(field_clause(raw, "title"), Some((0, r#"title:"the life""#)));
+        assert_eq!(field_clause(raw, "body"), Some((22, "body:aquatic")));
+        assert_eq!(field_clause(raw, "xbody"), Some((38, "xbody:sea")));
+        assert_eq!(field_clause(raw, "asdf"), None);
+    }
+}
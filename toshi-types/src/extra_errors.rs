@@ -2,8 +2,15 @@ use crate::error::{Error, ErrorResponse};
 
 impl From<Error> for http::Response<hyper::Body> {
     fn from(err: Error) -> Self {
+        let status = err.status_code();
         let body = ErrorResponse::new(err);
         let bytes = serde_json::to_vec(&body).unwrap();
-        http::Response::new(hyper::Body::from(bytes))
+        let mut resp = http::Response::new(hyper::Body::from(bytes));
+        *resp.status_mut() = status;
+        if status == http::StatusCode::SERVICE_UNAVAILABLE {
+            resp.headers_mut()
+                .insert(http::header::RETRY_AFTER, http::HeaderValue::from_static("1"));
+        }
+        resp
     }
 }
@@ -15,11 +15,17 @@ use tantivy::space_usage::SearcherSpaceUsage;
 use tantivy::{Index, IndexWriter};
 use tokio::sync::Mutex;
 
-pub use client::{ScoredDoc, SearchResults, SummaryResponse};
+pub use client::{
+    FacetCount, FacetResult, Highlight, MatchOffset, Pagination, ScoredDoc, SearchResults, SearchTiming, SummaryResponse, TailedDoc,
+    TermStats,
+};
 pub use error::{Error, ErrorResponse};
 pub use query::{
-    boolean::BoolQuery, facet::FacetQuery, fuzzy::FuzzyQuery, fuzzy::FuzzyTerm, phrase::PhraseQuery, phrase::TermPair, range::RangeQuery,
-    range::Ranges, regex::RegexQuery, term::ExactTerm, CreateQuery, FlatNamedDocument, KeyValue, Query, QueryOptions, Search,
+    agg::Aggregation, agg::AggregationCollector, agg::AggregationResult, agg::TermCount, bm25::Bm25, boolean::BoolClause,
+    boolean::BoolQuery, boolean::ClauseOccur, facet::FacetQuery, function_score::FunctionScoreQuery, fuzzy::FuzzyQuery, fuzzy::FuzzyTerm,
+    phrase::PhraseQuery, phrase::TermPair, range::RangeQuery, range::Ranges, regex::RegexQuery, score_expression::ScoreExpression,
+    term::ExactTerm, CreateQuery, FlatNamedDocument, HighlightOptions, KeyValue, Query, QueryOptions, Search, SortField, SortMissing,
+    SortOrder,
 };
 pub use server::*;
 
@@ -50,14 +56,16 @@ pub trait IndexHandle: Clone {
     fn get_name(&self) -> String;
     /// Return the underlying index
     fn get_index(&self) -> Index;
-    /// Return index writer
-    fn get_writer(&self) -> Arc<Mutex<IndexWriter>>;
+    /// Return index writer, frozen indexes don't have one
+    fn get_writer(&self) -> Result<Arc<Mutex<IndexWriter>>>;
     /// Get size of an index
     fn get_space(&self) -> SearcherSpaceUsage;
     /// The agreed upon raft commit ID this index is currently at.
     fn get_opstamp(&self) -> usize;
     /// Set that opstamp
     fn set_opstamp(&self, opstamp: usize);
+    /// The number of documents that can currently be searched, without running a search
+    fn num_docs(&self) -> u64;
     /// Commit the current index writes
     async fn commit(&self) -> Result<u64>;
     /// Search for documents in this index
@@ -66,6 +74,35 @@ pub trait IndexHandle: Clone {
     async fn add_document(&self, doc: AddDocument<SerdeValue>) -> Result<()>;
     /// Delete terms/documents from this index
     async fn delete_term(&self, term: DeleteDoc) -> Result<DocsAffected>;
+    /// Delete every document matching a query from this index, the number of documents affected is
+    /// only exact when the delete is committed
+    async fn delete_by_query(&self, delete: DeleteQuery) -> Result<DocsAffected>;
+    /// The settings currently stored alongside this index
+    fn get_settings(&self) -> IndexSettings;
+    /// Replace and persist the settings stored alongside this index
+    async fn set_settings(&self, settings: IndexSettings) -> Result<()>;
+    /// Load this index's term dictionaries and fast fields so the first searches against it aren't
+    /// slowed down by cold caches, returning the number of segments warmed
+    fn warmup(&self) -> Result<usize>;
+    /// Throw away the current index writer along with any uncommitted changes and build a new one,
+    /// used to recover an index whose writer has been left unusable
+    async fn recreate_writer(&self) -> Result<()>;
+    /// Commit any pending changes and drop this index's writer to free its memory, the index stays
+    /// searchable but rejects writes until it's thawed, even once it's reopened. Fails while writes
+    /// holding the writer are still running.
+    async fn freeze(&self) -> Result<()>;
+    /// Give a frozen index a writer again so it accepts writes
+    async fn thaw(&self) -> Result<()>;
+    /// Whether this index is currently frozen
+    fn is_frozen(&self) -> bool;
+    /// The limit on term length documents added to this index are held to, if there is one
+    fn term_limit(&self) -> Option<TermLimit>;
+    /// How many more documents can be added before this index reaches its `max_docs` setting,
+    /// counting uncommitted operations as documents
+    fn remaining_docs(&self) -> Option<u64> {
+        let used = self.num_docs() + self.get_opstamp() as u64;
+        self.get_settings().max_docs.map(|max| max.saturating_sub(used))
+    }
 }
 
 /// Defines the interface for obtaining a handle from a catalog to an index
@@ -76,6 +113,10 @@ pub trait Catalog: Send + Sync + 'static {
 
     /// The base path for local indexes, useless for remote
     fn base_path(&self) -> String;
+    /// The directory a local index named `name` is stored in
+    fn index_path(&self, name: &str) -> std::path::PathBuf {
+        std::path::Path::new(&self.base_path()).join(name)
+    }
     /// Return the entire collection of handles
     fn get_collection(&self) -> &dashmap::DashMap<String, Self::Handle>;
     /// Add a local index to the catalog
@@ -83,9 +124,11 @@ pub trait Catalog: Send + Sync + 'static {
     /// Return a list of index names
     async fn list_indexes(&self) -> Vec<String>;
     /// Return a handle to a single index
-    fn get_index(&self, name: &str) -> Result<Self::Handle>;
+    async fn get_index(&self, name: &str) -> Result<Self::Handle>;
     /// Determine if an index exists locally
     fn exists(&self, index: &str) -> bool;
+    /// Register any indexes that have appeared in the base path, returning the newly registered names
+    async fn refresh_catalog(&self) -> Result<Vec<String>>;
 }
 
 #[allow(missing_docs)]
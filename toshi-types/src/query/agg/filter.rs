@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::query::{AllQuery, EnableScoring, Query as TantivyQuery, Scorer, Weight};
+use tantivy::schema::Schema;
+use tantivy::{DocId, DocSet};
+
+use crate::error::Error;
+use crate::query::agg::{AggregationCollector, AggregationResult, AggregationSegmentCollector, SegmentFruit};
+use crate::query::{CreateQuery, Query};
+use crate::Result;
+
+/// Build the weight of a filter aggregation's query, only the schema is available here so raw
+/// queries, which need the index to be parsed, can't be used as filters
+pub(crate) fn weight(schema: &Schema, query: &Query) -> Result<Arc<dyn Weight>> {
+    query.validate_fields(schema)?;
+    let query = create_query(schema, query.clone())?;
+    Ok(Arc::from(query.weight(EnableScoring::Disabled(schema))?))
+}
+
+fn create_query(schema: &Schema, query: Query) -> Result<Box<dyn TantivyQuery>> {
+    match query {
+        Query::Regex(regex) => regex.create_query(schema),
+        Query::Phrase(phrase) => phrase.create_query(schema),
+        Query::Fuzzy(fuzzy) => fuzzy.create_query(schema),
+        Query::Exact(term) => term.create_query(schema),
+        Query::Range(range) => range.create_query(schema),
+        Query::Boolean { bool } => bool.create_query(schema),
+        // Only which documents match matters, so the decay is ignored
+        Query::FunctionScore { function_score } => create_query(schema, function_score.into_query()),
+        Query::All => Ok(Box::new(AllQuery)),
+        Query::Raw { .. } => Err(Error::QueryError("Raw queries can't be used as an aggregation filter".into())),
+    }
+}
+
+/// Passes the collected documents that also match the filter on to the sub aggregations
+pub(crate) struct FilterSource {
+    pub(crate) scorer: Box<dyn Scorer>,
+    pub(crate) count: u64,
+    pub(crate) children: Vec<AggregationSegmentCollector>,
+}
+
+impl FilterSource {
+    #[inline]
+    pub(crate) fn collect(&mut self, doc: DocId) {
+        if self.scorer.doc() < doc {
+            self.scorer.seek(doc);
+        }
+        if self.scorer.doc() == doc {
+            self.count += 1;
+            self.children.iter_mut().for_each(|child| child.collect(doc, 0.0));
+        }
+    }
+
+    pub(crate) fn harvest(self) -> SegmentFruit {
+        let fruits = self.children.into_iter().map(|child| child.harvest()).collect();
+        SegmentFruit::Filter(self.count, fruits)
+    }
+}
+
+/// Sum the matching documents of every segment and merge each sub aggregation's fruits
+pub(crate) fn merge(
+    segments: Vec<(u64, Vec<SegmentFruit>)>,
+    sub_aggs: &[(String, AggregationCollector)],
+) -> tantivy::Result<AggregationResult> {
+    let mut doc_count = 0;
+    let mut fruits: Vec<Vec<SegmentFruit>> = sub_aggs.iter().map(|_| Vec::new()).collect();
+    for (count, segment_fruits) in segments {
+        doc_count += count;
+        fruits.iter_mut().zip(segment_fruits).for_each(|(fruits, fruit)| fruits.push(fruit));
+    }
+    let aggregations = sub_aggs
+        .iter()
+        .zip(fruits)
+        .map(|((name, collector), fruits)| Ok((name.clone(), collector.merge_fruits(fruits)?)))
+        .collect::<tantivy::Result<_>>()?;
+    Ok(AggregationResult::Filter { doc_count, aggregations })
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::*;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_weight() {
+        let mut builder = SchemaBuilder::new();
+        builder.add_u64_field("test_u64", FAST | INDEXED);
+        let schema = builder.build();
+
+        assert!(weight(&schema, &Query::All).is_ok());
+        let range: Query = serde_json::from_str(r#"{ "range": { "test_u64": { "gte": 12 } } }"#).unwrap();
+        assert!(weight(&schema, &range).is_ok());
+        let raw: Query = serde_json::from_str(r#"{ "raw": "test_u64:12" }"#).unwrap();
+        assert!(weight(&schema, &raw).is_err());
+        let unknown: Query = serde_json::from_str(r#"{ "range": { "asdf": { "gte": 12 } } }"#).unwrap();
+        assert_eq!(
+            weight(&schema, &unknown).err().unwrap().to_string(),
+            "Unknown Field: 'asdf' queried"
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::de::Error as DeError;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::fastfield::Column;
+use tantivy::query::Weight;
+use tantivy::schema::{Field, FieldType, Schema};
+use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader, TantivyError};
+
+use crate::error::Error;
+use crate::query::agg::filter::FilterSource;
+use crate::query::agg::stats::Stats;
+pub use crate::query::agg::terms::TermCount;
+use crate::query::agg::terms::{BucketFruit, SubAggregations, TermsKeys, TermsSource};
+use crate::query::Query;
+use crate::Result;
+
+pub(crate) mod filter;
+pub(crate) mod percentiles;
+pub(crate) mod stats;
+pub(crate) mod terms;
+
+/// The aggregations that can be requested alongside a [`crate::Search`], each aggregation is
+/// computed over the documents matched by the search's query
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", remote = "Self")]
+pub enum Aggregation {
+    /// Percentiles of a numeric fast field's values
+    Percentiles {
+        /// The fast field to compute percentiles over
+        field: String,
+        /// The percentiles to return, between 0 and 100
+        #[serde(default = "percentiles::default_percents")]
+        percents: Vec<f64>,
+    },
+    /// Count, min, max, sum and average of a numeric fast field's values
+    Stats {
+        /// The fast field to compute statistics over
+        field: String,
+    },
+    /// Number of matched documents for each distinct value of a field
+    Terms {
+        /// The numeric fast field or facet field to count values of
+        field: String,
+        /// Only return this many of the most frequent values, every value is returned if not given
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<usize>,
+        /// Named aggregations computed separately over the documents of each value
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        aggs: BTreeMap<String, Aggregation>,
+    },
+    /// Number of matched documents that also match another query, written as
+    /// `{"filter": {...query}, "aggs": {...}}`, `{"filter": {"query": {...}, "aggs": {...}}}` is
+    /// accepted as well. Raw queries can't be used as the filter
+    Filter {
+        /// The query documents must also match to be counted
+        query: Box<Query>,
+        /// Named aggregations computed over only the documents matching the filter
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        aggs: BTreeMap<String, Aggregation>,
+    },
+}
+
+impl Serialize for Aggregation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Aggregation::Filter { query, aggs } => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("filter", query)?;
+                if !aggs.is_empty() {
+                    map.serialize_entry("aggs", aggs)?;
+                }
+                map.end()
+            }
+            agg => Aggregation::serialize(agg, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Aggregation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut value = serde_json::Value::deserialize(deserializer)?;
+        // A filter given as `{"filter": {...query}, "aggs": {...}}` is rewritten into the nested form
+        if let Some(map) = value.as_object_mut() {
+            let nested = map
+                .get("filter")
+                .and_then(|f| f.as_object())
+                .is_some_and(|f| f.contains_key("query"));
+            if map.contains_key("filter") && !nested {
+                let query = map.remove("filter").unwrap_or_default();
+                let aggs = map.remove("aggs").unwrap_or_else(|| serde_json::json!({}));
+                if let Some(key) = map.keys().next() {
+                    return Err(D::Error::custom(format!("unknown field `{}` next to a filter aggregation", key)));
+                }
+                map.insert("filter".into(), serde_json::json!({ "query": query, "aggs": aggs }));
+            }
+        }
+        Aggregation::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+impl Aggregation {
+    /// Shortcut for a percentiles aggregation returning the default percentiles
+    pub fn percentiles<F: ToString>(field: F) -> Self {
+        Aggregation::Percentiles {
+            field: field.to_string(),
+            percents: percentiles::default_percents(),
+        }
+    }
+
+    /// Shortcut for a stats aggregation
+    pub fn stats<F: ToString>(field: F) -> Self {
+        Aggregation::Stats { field: field.to_string() }
+    }
+
+    /// Shortcut for a terms aggregation
+    pub fn terms<F: ToString>(field: F) -> Self {
+        Aggregation::Terms {
+            field: field.to_string(),
+            size: None,
+            aggs: BTreeMap::new(),
+        }
+    }
+
+    /// Shortcut for a filter aggregation
+    pub fn filter(query: Query) -> Self {
+        Aggregation::Filter {
+            query: Box::new(query),
+            aggs: BTreeMap::new(),
+        }
+    }
+
+    /// Add a sub aggregation to a terms or filter aggregation, other aggregations are returned unchanged
+    pub fn with_sub_aggregation<V: ToString>(mut self, name: V, agg: Aggregation) -> Self {
+        if let Aggregation::Terms { aggs, .. } | Aggregation::Filter { aggs, .. } = &mut self {
+            aggs.insert(name.to_string(), agg);
+        }
+        self
+    }
+
+    /// Keep only the `size` most frequent values of a terms aggregation, other aggregations are
+    /// returned unchanged
+    pub fn with_size(mut self, size: usize) -> Self {
+        if let Aggregation::Terms { size: s, .. } = &mut self {
+            *s = Some(size);
+        }
+        self
+    }
+
+    /// Check the queries of this aggregation's filters, and those of its sub aggregations, against
+    /// the same limits as a search's query, see [`Query::validate_complexity`]
+    pub fn validate_complexity(&self, max_clauses: usize, max_depth: usize) -> Result<()> {
+        match self {
+            Aggregation::Filter { query, aggs } => {
+                query.validate_complexity(max_clauses, max_depth)?;
+                aggs.values().try_for_each(|agg| agg.validate_complexity(max_clauses, max_depth))
+            }
+            Aggregation::Terms { aggs, .. } => aggs.values().try_for_each(|agg| agg.validate_complexity(max_clauses, max_depth)),
+            Aggregation::Percentiles { .. } | Aggregation::Stats { .. } => Ok(()),
+        }
+    }
+
+    /// The field this aggregation reads its values from, filters don't read from a field
+    pub fn get_field(&self) -> Option<&str> {
+        match self {
+            Aggregation::Percentiles { field, .. } | Aggregation::Stats { field } | Aggregation::Terms { field, .. } => Some(field),
+            Aggregation::Filter { .. } => None,
+        }
+    }
+
+    /// Create the Tantivy collector that computes this aggregation for the given schema
+    pub fn collector(&self, schema: &Schema) -> Result<AggregationCollector> {
+        let source = match self {
+            Aggregation::Terms { field, .. } => terms_field(schema, field).map(|(f, t)| CollectorSource::Field(f, t))?,
+            Aggregation::Percentiles { field, .. } | Aggregation::Stats { field } => {
+                numeric_fast_field(schema, field).map(|(f, t)| CollectorSource::Field(f, t))?
+            }
+            Aggregation::Filter { query, .. } => CollectorSource::Filter(filter::weight(schema, query)?),
+        };
+        let sub_aggs = match self {
+            Aggregation::Terms { aggs, .. } | Aggregation::Filter { aggs, .. } => aggs
+                .iter()
+                .map(|(name, agg)| Ok((name.clone(), agg.collector(schema)?)))
+                .collect::<Result<_>>()?,
+            _ => Vec::new(),
+        };
+        Ok(AggregationCollector {
+            source,
+            aggregation: self.clone(),
+            sub_aggs,
+        })
+    }
+}
+
+/// Look up a field that values can be read from with a [`NumericColumn`]
+pub(crate) fn numeric_fast_field(schema: &Schema, name: &str) -> Result<(Field, FieldType)> {
+    let field = schema.get_field(name).ok_or_else(|| Error::UnknownIndexField(name.into()))?;
+    let field_type = schema.get_field_entry(field).field_type().clone();
+    match field_type {
+        FieldType::U64(_) | FieldType::I64(_) | FieldType::F64(_) if field_type.is_fast() => Ok((field, field_type)),
+        _ => Err(Error::QueryError(format!("Field: {} must be a numeric fast field", name))),
+    }
+}
+
+/// Terms can also be counted over facet fields, which are always fast
+fn terms_field(schema: &Schema, name: &str) -> Result<(Field, FieldType)> {
+    let field = schema.get_field(name).ok_or_else(|| Error::UnknownIndexField(name.into()))?;
+    match schema.get_field_entry(field).field_type() {
+        field_type @ FieldType::Facet(_) => Ok((field, field_type.clone())),
+        _ => numeric_fast_field(schema, name)
+            .map_err(|_| Error::QueryError(format!("Field: {} must be a numeric fast field or a facet field", name))),
+    }
+}
+
+/// The computed result of an [`Aggregation`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AggregationResult {
+    /// The requested percentiles keyed by percentile, values are null when no documents matched
+    Percentiles {
+        /// Percentile to value
+        values: BTreeMap<String, Option<f64>>,
+    },
+    /// Statistics of the matched values, min, max and avg are null when no documents matched
+    Stats {
+        /// Number of values
+        count: u64,
+        /// Smallest value
+        min: Option<f64>,
+        /// Largest value
+        max: Option<f64>,
+        /// Sum of all values
+        sum: f64,
+        /// Average of all values
+        avg: Option<f64>,
+    },
+    /// Counts of each distinct value, the most frequent first
+    Terms {
+        /// Value to count
+        buckets: Vec<TermCount>,
+    },
+    /// Number of matched documents that also matched the filter
+    Filter {
+        /// Number of documents
+        doc_count: u64,
+        /// Sub aggregations computed over only these documents
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        aggregations: BTreeMap<String, AggregationResult>,
+    },
+}
+
+/// Where an [`AggregationCollector`] reads what it aggregates from
+#[derive(Clone)]
+enum CollectorSource {
+    /// A fast field or facet field
+    Field(Field, FieldType),
+    /// The documents matching a filter's query
+    Filter(Arc<dyn Weight>),
+}
+
+/// A collector computing a single [`Aggregation`] over a numeric fast field
+#[derive(Clone)]
+pub struct AggregationCollector {
+    source: CollectorSource,
+    aggregation: Aggregation,
+    /// Collectors for the sub aggregations of a terms or filter aggregation
+    sub_aggs: Vec<(String, AggregationCollector)>,
+}
+
+/// The values an [`AggregationCollector`] gathers from a single segment
+#[derive(Debug)]
+pub enum SegmentFruit {
+    /// Every value seen in the segment
+    Values(Vec<f64>),
+    /// Running statistics of the values seen in the segment
+    Stats(Stats),
+    /// Number of documents seen for each distinct value in the segment
+    Counts(HashMap<String, BucketFruit>),
+    /// Number of documents that matched a filter along with the fruits of its sub aggregations
+    Filter(u64, Vec<SegmentFruit>),
+}
+
+/// What an [`AggregationSegmentCollector`] reads values from and gathers them into
+pub(crate) enum SegmentState {
+    Values(NumericColumn, Vec<f64>),
+    Stats(NumericColumn, Stats),
+    Terms(Box<TermsSource>),
+    Filter(Box<FilterSource>),
+}
+
+pub struct AggregationSegmentCollector {
+    state: SegmentState,
+}
+
+impl Collector for AggregationCollector {
+    type Fruit = AggregationResult;
+    type Child = AggregationSegmentCollector;
+
+    fn for_segment(&self, ord: SegmentOrdinal, segment: &SegmentReader) -> tantivy::Result<Self::Child> {
+        let state = match (&self.aggregation, &self.source) {
+            (Aggregation::Terms { .. }, CollectorSource::Field(field, field_type)) => {
+                let keys = match field_type {
+                    FieldType::Facet(_) => TermsKeys::Facet(segment.facet_reader(*field)?, Vec::new()),
+                    _ => TermsKeys::Numeric(NumericColumn::open(segment, *field, field_type)?),
+                };
+                let sub_aggs = if self.sub_aggs.is_empty() {
+                    None
+                } else {
+                    // Sub aggregations are only opened for each value once the segment is harvested,
+                    // opening them here first means any error is still reported
+                    for (_, collector) in &self.sub_aggs {
+                        collector.for_segment(ord, segment)?;
+                    }
+                    Some(SubAggregations {
+                        collectors: self.sub_aggs.clone(),
+                        segment: segment.clone(),
+                        ord,
+                        docs: HashMap::new(),
+                    })
+                };
+                SegmentState::Terms(Box::new(TermsSource {
+                    keys,
+                    counts: HashMap::new(),
+                    sub_aggs,
+                }))
+            }
+            (Aggregation::Percentiles { .. }, CollectorSource::Field(field, field_type)) => {
+                SegmentState::Values(NumericColumn::open(segment, *field, field_type)?, Vec::new())
+            }
+            (Aggregation::Stats { .. }, CollectorSource::Field(field, field_type)) => {
+                SegmentState::Stats(NumericColumn::open(segment, *field, field_type)?, Stats::default())
+            }
+            (Aggregation::Filter { .. }, CollectorSource::Filter(weight)) => {
+                let children = self
+                    .sub_aggs
+                    .iter()
+                    .map(|(_, collector)| collector.for_segment(ord, segment))
+                    .collect::<tantivy::Result<_>>()?;
+                SegmentState::Filter(Box::new(FilterSource {
+                    scorer: weight.scorer(segment, 1.0)?,
+                    count: 0,
+                    children,
+                }))
+            }
+            _ => return Err(TantivyError::InvalidArgument("Aggregation collector has the wrong source".into())),
+        };
+        Ok(AggregationSegmentCollector { state })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<SegmentFruit>) -> tantivy::Result<Self::Fruit> {
+        match &self.aggregation {
+            Aggregation::Percentiles { percents, .. } => {
+                let mut values: Vec<f64> = segment_fruits
+                    .into_iter()
+                    .flat_map(|fruit| match fruit {
+                        SegmentFruit::Values(v) => v,
+                        _ => Vec::new(),
+                    })
+                    .collect();
+                Ok(percentiles::compute(&mut values, percents))
+            }
+            Aggregation::Stats { .. } => {
+                let stats = segment_fruits
+                    .into_iter()
+                    .filter_map(|fruit| match fruit {
+                        SegmentFruit::Stats(s) => Some(s),
+                        _ => None,
+                    })
+                    .fold(Stats::default(), Stats::merge);
+                Ok(stats.into_result())
+            }
+            Aggregation::Terms { size, .. } => {
+                let counts = segment_fruits
+                    .into_iter()
+                    .filter_map(|fruit| match fruit {
+                        SegmentFruit::Counts(c) => Some(c),
+                        _ => None,
+                    })
+                    .collect();
+                terms::merge(counts, &self.sub_aggs, *size)
+            }
+            Aggregation::Filter { .. } => {
+                let segments = segment_fruits
+                    .into_iter()
+                    .filter_map(|fruit| match fruit {
+                        SegmentFruit::Filter(count, fruits) => Some((count, fruits)),
+                        _ => None,
+                    })
+                    .collect();
+                filter::merge(segments, &self.sub_aggs)
+            }
+        }
+    }
+}
+
+impl SegmentCollector for AggregationSegmentCollector {
+    type Fruit = SegmentFruit;
+
+    fn collect(&mut self, doc: DocId, _: Score) {
+        match &mut self.state {
+            SegmentState::Values(column, values) => values.push(column.get(doc)),
+            SegmentState::Stats(column, stats) => stats.collect(column.get(doc)),
+            SegmentState::Terms(source) => source.collect(doc),
+            SegmentState::Filter(source) => source.collect(doc),
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        match self.state {
+            SegmentState::Values(_, values) => SegmentFruit::Values(values),
+            SegmentState::Stats(_, stats) => SegmentFruit::Stats(stats),
+            SegmentState::Terms(source) => SegmentFruit::Counts((*source).harvest()),
+            SegmentState::Filter(source) => (*source).harvest(),
+        }
+    }
+}
+
+/// A fast field reader for any of the numeric field types, values are read back as f64
+pub(crate) enum NumericColumn {
+    U64(Arc<dyn Column<u64>>),
+    I64(Arc<dyn Column<i64>>),
+    F64(Arc<dyn Column<f64>>),
+}
+
+impl NumericColumn {
+    pub(crate) fn open(segment: &SegmentReader, field: Field, field_type: &FieldType) -> tantivy::Result<Self> {
+        let fast_fields = segment.fast_fields();
+        match field_type {
+            FieldType::I64(_) => Ok(NumericColumn::I64(fast_fields.i64(field)?)),
+            FieldType::F64(_) => Ok(NumericColumn::F64(fast_fields.f64(field)?)),
+            _ => Ok(NumericColumn::U64(fast_fields.u64(field)?)),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, doc: DocId) -> f64 {
+        match self {
+            NumericColumn::U64(c) => c.get_val(doc) as f64,
+            NumericColumn::I64(c) => c.get_val(doc) as f64,
+            NumericColumn::F64(c) => c.get_val(doc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::*;
+
+    use super::*;
+    use crate::ExactTerm;
+
+    #[test]
+    fn test_deserialize_percentiles() {
+        let body = r#"{ "percentiles": { "field": "test_u64" } }"#;
+        let agg: Aggregation = serde_json::from_str(body).unwrap();
+        assert_eq!(agg, Aggregation::percentiles("test_u64"));
+
+        let body = r#"{ "percentiles": { "field": "test_u64", "percents": [10, 20] } }"#;
+        let agg: Aggregation = serde_json::from_str(body).unwrap();
+        let expected = Aggregation::Percentiles {
+            field: "test_u64".into(),
+            percents: vec![10.0, 20.0],
+        };
+        assert_eq!(agg, expected);
+
+        let body = r#"{ "stats": { "field": "test_i64" } }"#;
+        let agg: Aggregation = serde_json::from_str(body).unwrap();
+        assert_eq!(agg, Aggregation::stats("test_i64"));
+
+        let body = r#"{ "terms": { "field": "test_facet" } }"#;
+        let agg: Aggregation = serde_json::from_str(body).unwrap();
+        assert_eq!(agg, Aggregation::terms("test_facet"));
+
+        let body = r#"{ "filter": { "query": { "term": { "test_text": "document" } }, "aggs": { "stats": { "stats": { "field": "test_u64" } } } } }"#;
+        let agg: Aggregation = serde_json::from_str(body).unwrap();
+        let query = Query::Exact(ExactTerm::with_term("test_text", "document"));
+        let expected = Aggregation::filter(query).with_sub_aggregation("stats", Aggregation::stats("test_u64"));
+        assert_eq!(agg, expected);
+
+        let body = r#"{ "filter": { "term": { "test_text": "document" } }, "aggs": { "stats": { "stats": { "field": "test_u64" } } } }"#;
+        let agg: Aggregation = serde_json::from_str(body).unwrap();
+        assert_eq!(agg, expected);
+        assert_eq!(
+            serde_json::to_value(&agg).unwrap(),
+            serde_json::from_str::<serde_json::Value>(body).unwrap()
+        );
+        let body = r#"{ "filter": { "term": { "test_text": "document" } }, "size": 1 }"#;
+        assert!(serde_json::from_str::<Aggregation>(body).is_err());
+    }
+
+    #[test]
+    fn test_filter_complexity() {
+        let query: Query =
+            serde_json::from_str(r#"{ "bool": { "must": [ { "term": { "test_text": "a" } }, { "term": { "test_text": "b" } } ] } }"#)
+                .unwrap();
+        let agg = Aggregation::terms("test_u64").with_sub_aggregation("nested", Aggregation::filter(query));
+        assert!(agg.validate_complexity(2, 0).is_ok());
+        assert!(matches!(agg.validate_complexity(1, 0), Err(Error::QueryError(_))));
+    }
+
+    #[test]
+    fn test_collector_requires_fast_field() {
+        let mut builder = SchemaBuilder::new();
+        builder.add_u64_field("test_u64", STORED);
+        builder.add_u64_field("test_fast", FAST);
+        builder.add_facet_field("test_facet", FacetOptions::default());
+        let schema = builder.build();
+
+        assert!(Aggregation::terms("test_facet").collector(&schema).is_ok());
+        assert!(Aggregation::terms("test_fast").collector(&schema).is_ok());
+        assert!(Aggregation::terms("test_u64").collector(&schema).is_err());
+        assert!(Aggregation::stats("test_facet").collector(&schema).is_err());
+
+        assert!(Aggregation::percentiles("test_u64").collector(&schema).is_err());
+        assert!(Aggregation::percentiles("test_fast").collector(&schema).is_ok());
+        assert_eq!(
+            Aggregation::percentiles("asdf").collector(&schema).err().unwrap().to_string(),
+            "Unknown Field: 'asdf' queried"
+        );
+    }
+}
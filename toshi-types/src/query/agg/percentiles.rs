@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use crate::query::agg::AggregationResult;
+
+pub(crate) fn default_percents() -> Vec<f64> {
+    vec![50.0, 95.0, 99.0]
+}
+
+/// Computes exact percentiles by sorting every collected value and interpolating linearly
+/// between the two closest ranks.
+pub(crate) fn compute(values: &mut [f64], percents: &[f64]) -> AggregationResult {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let values = percents
+        .iter()
+        .map(|p| (format!("{:.1}", p), percentile(values, *p)))
+        .collect::<BTreeMap<String, Option<f64>>>();
+    AggregationResult::Percentiles { values }
+}
+
+fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let weight = rank - lower as f64;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut values = vec![4.0, 1.0, 3.0, 2.0, 5.0];
+        let result = compute(&mut values, &[0.0, 50.0, 75.0, 100.0]);
+        let AggregationResult::Percentiles { values } = result else {
+            panic!("Expected percentiles")
+        };
+        assert_eq!(values["0.0"], Some(1.0));
+        assert_eq!(values["50.0"], Some(3.0));
+        assert_eq!(values["75.0"], Some(4.0));
+        assert_eq!(values["100.0"], Some(5.0));
+    }
+
+    #[test]
+    fn test_empty_percentiles() {
+        let result = compute(&mut [], &[50.0]);
+        let AggregationResult::Percentiles { values } = result else {
+            panic!("Expected percentiles")
+        };
+        assert_eq!(values["50.0"], None);
+    }
+}
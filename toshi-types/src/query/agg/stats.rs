@@ -0,0 +1,91 @@
+use crate::query::agg::AggregationResult;
+
+/// Running count, min, max and sum of the values seen by a collector
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    count: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+        }
+    }
+}
+
+impl Stats {
+    #[inline]
+    pub(crate) fn collect(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+    }
+
+    pub(crate) fn merge(mut self, other: Stats) -> Self {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self
+    }
+
+    /// min, max and avg are left empty when no values were collected
+    pub(crate) fn into_result(self) -> AggregationResult {
+        let (min, max, avg) = if self.count == 0 {
+            (None, None, None)
+        } else {
+            (Some(self.min), Some(self.max), Some(self.sum / self.count as f64))
+        };
+        AggregationResult::Stats {
+            count: self.count,
+            min,
+            max,
+            sum: self.sum,
+            avg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_stats() {
+        let mut first = Stats::default();
+        first.collect(1.0);
+        first.collect(5.0);
+        let mut second = Stats::default();
+        second.collect(-3.0);
+
+        let result = first.merge(second).merge(Stats::default()).into_result();
+        let expected = AggregationResult::Stats {
+            count: 3,
+            min: Some(-3.0),
+            max: Some(5.0),
+            sum: 3.0,
+            avg: Some(1.0),
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_empty_stats() {
+        let expected = AggregationResult::Stats {
+            count: 0,
+            min: None,
+            max: None,
+            sum: 0.0,
+            avg: None,
+        };
+        assert_eq!(Stats::default().into_result(), expected);
+    }
+}
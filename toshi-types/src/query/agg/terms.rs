@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::fastfield::FacetReader;
+use tantivy::schema::Facet;
+use tantivy::{DocId, SegmentOrdinal, SegmentReader};
+
+use crate::query::agg::{AggregationCollector, AggregationResult, NumericColumn, SegmentFruit};
+
+/// The number of matched documents with a single distinct value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TermCount {
+    /// The value, facets are returned as their path and numbers in their shortest form
+    pub value: String,
+    /// Number of matched documents with this value
+    pub count: u64,
+    /// Sub aggregations computed over only the documents with this value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aggregations: BTreeMap<String, AggregationResult>,
+}
+
+impl TermCount {
+    /// Constructor for a value's count without sub aggregations
+    pub fn new<V: ToString>(value: V, count: u64) -> Self {
+        Self {
+            value: value.to_string(),
+            count,
+            aggregations: BTreeMap::new(),
+        }
+    }
+}
+
+/// Reads the distinct values of a segment's documents
+pub(crate) enum TermsKeys {
+    /// Keyed by the bits of each value
+    Numeric(NumericColumn),
+    /// Keyed by facet ordinal, a document can have several facets
+    Facet(FacetReader, Vec<u64>),
+}
+
+/// The sub aggregations of a terms aggregation, which are computed over each value's documents
+/// once the segment is harvested
+pub(crate) struct SubAggregations {
+    pub(crate) collectors: Vec<(String, AggregationCollector)>,
+    pub(crate) segment: SegmentReader,
+    pub(crate) ord: SegmentOrdinal,
+    pub(crate) docs: HashMap<u64, Vec<DocId>>,
+}
+
+/// Counts the distinct values of a single segment, keyed by something cheap to hash until the
+/// segment is harvested
+pub(crate) struct TermsSource {
+    pub(crate) keys: TermsKeys,
+    pub(crate) counts: HashMap<u64, u64>,
+    pub(crate) sub_aggs: Option<SubAggregations>,
+}
+
+/// A segment's count of a single value along with the fruits of its sub aggregations, in the
+/// order the sub aggregations were requested
+#[derive(Debug)]
+pub struct BucketFruit {
+    count: u64,
+    aggs: Vec<SegmentFruit>,
+}
+
+impl TermsSource {
+    #[inline]
+    pub(crate) fn collect(&mut self, doc: DocId) {
+        let TermsSource { keys, counts, sub_aggs } = self;
+        let mut add = |key: u64| {
+            *counts.entry(key).or_default() += 1;
+            if let Some(sub_aggs) = sub_aggs {
+                sub_aggs.docs.entry(key).or_default().push(doc);
+            }
+        };
+        match keys {
+            TermsKeys::Numeric(column) => add(column.get(doc).to_bits()),
+            TermsKeys::Facet(reader, ords) => {
+                reader.facet_ords(doc, ords);
+                ords.iter().for_each(|ord| add(*ord));
+            }
+        }
+    }
+
+    pub(crate) fn harvest(self) -> HashMap<String, BucketFruit> {
+        let TermsSource {
+            keys,
+            counts,
+            mut sub_aggs,
+        } = self;
+        let mut bucket = |key: u64, count: u64| {
+            let aggs = match &mut sub_aggs {
+                Some(sub_aggs) => {
+                    let docs = sub_aggs.docs.remove(&key).unwrap_or_default();
+                    sub_aggs
+                        .collectors
+                        .iter()
+                        .map(|(_, collector)| {
+                            // Opening these already succeeded for this segment in `for_segment`
+                            let mut child = collector
+                                .for_segment(sub_aggs.ord, &sub_aggs.segment)
+                                .expect("Sub aggregation could not be opened for segment");
+                            docs.iter().for_each(|doc| child.collect(*doc, 0.0));
+                            child.harvest()
+                        })
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+            BucketFruit { count, aggs }
+        };
+        match keys {
+            TermsKeys::Numeric(_) => counts
+                .into_iter()
+                .map(|(k, v)| (f64::from_bits(k).to_string(), bucket(k, v)))
+                .collect(),
+            TermsKeys::Facet(mut reader, _) => {
+                let mut facet = Facet::root();
+                counts
+                    .into_iter()
+                    .filter_map(|(ord, count)| {
+                        reader.facet_from_ord(ord, &mut facet).ok()?;
+                        Some((facet.to_path_string(), bucket(ord, count)))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Sum the counts of every segment and merge the sub aggregations of each value, the most frequent
+/// values come first and ties are broken by value, numerically for numeric fields. Only the first
+/// `size` values are kept if given.
+pub(crate) fn merge(
+    segment_counts: Vec<HashMap<String, BucketFruit>>,
+    sub_aggs: &[(String, AggregationCollector)],
+    size: Option<usize>,
+) -> tantivy::Result<AggregationResult> {
+    let mut totals: HashMap<String, (u64, Vec<Vec<SegmentFruit>>)> = HashMap::new();
+    for (value, bucket) in segment_counts.into_iter().flatten() {
+        let (count, fruits) = totals
+            .entry(value)
+            .or_insert_with(|| (0, sub_aggs.iter().map(|_| Vec::new()).collect()));
+        *count += bucket.count;
+        fruits.iter_mut().zip(bucket.aggs).for_each(|(fruits, fruit)| fruits.push(fruit));
+    }
+    // Numeric values are formatted in their shortest form, so they're parsed back to be ordered by
+    // value rather than as strings, facet paths never parse as numbers
+    let mut totals: Vec<_> = totals
+        .into_iter()
+        .map(|(value, total)| (value.parse::<f64>().ok(), value, total))
+        .collect();
+    totals.sort_by(|(a_num, a, (a_count, _)), (b_num, b, (b_count, _))| {
+        b_count.cmp(a_count).then_with(|| match (a_num, b_num) {
+            (Some(a_num), Some(b_num)) => a_num.total_cmp(b_num),
+            _ => a.cmp(b),
+        })
+    });
+    // Sub aggregations are only merged for the values that are returned
+    totals.truncate(size.unwrap_or(usize::MAX));
+    let buckets = totals
+        .into_iter()
+        .map(|(_, value, (count, fruits))| {
+            let aggregations = sub_aggs
+                .iter()
+                .zip(fruits)
+                .map(|((name, collector), fruits)| Ok((name.clone(), collector.merge_fruits(fruits)?)))
+                .collect::<tantivy::Result<_>>()?;
+            Ok(TermCount {
+                value,
+                count,
+                aggregations,
+            })
+        })
+        .collect::<tantivy::Result<Vec<TermCount>>>()?;
+    Ok(AggregationResult::Terms { buckets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_counts() {
+        let bucket = |count| BucketFruit { count, aggs: Vec::new() };
+        let counts = || {
+            let first: HashMap<String, BucketFruit> = vec![("a".into(), bucket(1)), ("b".into(), bucket(2))].into_iter().collect();
+            let second: HashMap<String, BucketFruit> = vec![("a".into(), bucket(2)), ("c".into(), bucket(2))].into_iter().collect();
+            vec![first, second]
+        };
+        let expected = AggregationResult::Terms {
+            buckets: vec![TermCount::new("a", 3), TermCount::new("b", 2), TermCount::new("c", 2)],
+        };
+        assert_eq!(merge(counts(), &[], None).unwrap(), expected);
+
+        let expected = AggregationResult::Terms {
+            buckets: vec![TermCount::new("a", 3), TermCount::new("b", 2)],
+        };
+        assert_eq!(merge(counts(), &[], Some(2)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_merge_numeric_ties() {
+        let bucket = |count| BucketFruit { count, aggs: Vec::new() };
+        let counts: HashMap<String, BucketFruit> = vec![("10".into(), bucket(1)), ("9".into(), bucket(1)), ("-2.5".into(), bucket(1))]
+            .into_iter()
+            .collect();
+        let expected = AggregationResult::Terms {
+            buckets: vec![TermCount::new("-2.5", 1), TermCount::new("9", 1), TermCount::new("10", 1)],
+        };
+        assert_eq!(merge(vec![counts], &[], None).unwrap(), expected);
+    }
+}
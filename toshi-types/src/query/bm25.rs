@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Collector, TopDocs};
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::postings::SegmentPostings;
+use tantivy::query::Query as TantivyQuery;
+use tantivy::schema::IndexRecordOption;
+use tantivy::{DocAddress, DocId, DocSet, Postings, Score, Searcher, SegmentReader, Term};
+
+use crate::Result;
+
+/// BM25 parameters to rescore a search with in place of Tantivy's fixed k1 of 1.2 and b of 0.75.
+///
+/// Documents are rescored from the terms of the query, so clauses without terms, like ranges,
+/// no longer contribute to the score. Queries without any terms keep their original scores.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Bm25 {
+    /// Term frequency saturation, higher values let repeated terms count for more
+    #[serde(default = "Bm25::default_k1")]
+    pub k1: f32,
+    /// Length normalization, 0 ignores field length and 1 fully normalizes by it
+    #[serde(default = "Bm25::default_b")]
+    pub b: f32,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Self {
+            k1: Self::default_k1(),
+            b: Self::default_b(),
+        }
+    }
+}
+
+/// Searcher wide statistics for a single term of the query
+struct TermStats {
+    term: Term,
+    idf: Score,
+    average_fieldnorm: Score,
+}
+
+/// A term's postings and field norms in a single segment
+struct SegmentTerm {
+    postings: SegmentPostings,
+    fieldnorms: FieldNormReader,
+    idf: Score,
+    average_fieldnorm: Score,
+}
+
+impl Bm25 {
+    /// Constructor for custom BM25 parameters
+    pub fn new(k1: f32, b: f32) -> Self {
+        Self { k1, b }
+    }
+
+    /// Tantivy's k1
+    pub const fn default_k1() -> f32 {
+        1.2
+    }
+
+    /// Tantivy's b
+    pub const fn default_b() -> f32 {
+        0.75
+    }
+
+    /// A top docs collector that replaces the score of each document matched by `query` with its
+    /// BM25 score under these parameters
+    pub fn top_docs(
+        &self,
+        searcher: &Searcher,
+        query: &dyn TantivyQuery,
+        limit: usize,
+    ) -> Result<impl Collector<Fruit = Vec<(Score, DocAddress)>>> {
+        let mut terms = BTreeSet::new();
+        query.query_terms(&mut |term, _| {
+            terms.insert(term.clone());
+        });
+        let total_num_docs: u64 = searcher.segment_readers().iter().map(|s| u64::from(s.max_doc())).sum();
+        let mut stats = Vec::with_capacity(terms.len());
+        for term in terms {
+            let mut total_num_tokens = 0;
+            for segment in searcher.segment_readers() {
+                total_num_tokens += segment.inverted_index(term.field())?.total_num_tokens();
+            }
+            let doc_freq = searcher.doc_freq(&term)?;
+            stats.push(TermStats {
+                idf: idf(doc_freq, total_num_docs),
+                average_fieldnorm: total_num_tokens as Score / total_num_docs.max(1) as Score,
+                term,
+            });
+        }
+
+        let Bm25 { k1, b } = *self;
+        Ok(TopDocs::with_limit(limit).tweak_score(move |segment: &SegmentReader| {
+            let mut segment_terms: Vec<SegmentTerm> = stats.iter().filter_map(|s| open_term(segment, s)).collect();
+            let has_terms = !stats.is_empty();
+            move |doc: DocId, score: Score| {
+                if !has_terms {
+                    return score;
+                }
+                let mut total = 0.0;
+                for t in segment_terms.iter_mut() {
+                    if t.postings.doc() < doc {
+                        t.postings.seek(doc);
+                    }
+                    if t.postings.doc() == doc {
+                        let tf = t.postings.term_freq() as Score;
+                        let fieldnorm = t.fieldnorms.fieldnorm(doc) as Score;
+                        let norm = k1 * (1.0 - b + b * fieldnorm / t.average_fieldnorm);
+                        total += t.idf * (1.0 + k1) * tf / (tf + norm);
+                    }
+                }
+                total
+            }
+        }))
+    }
+}
+
+fn open_term(segment: &SegmentReader, stats: &TermStats) -> Option<SegmentTerm> {
+    let field = stats.term.field();
+    let postings = segment
+        .inverted_index(field)
+        .ok()?
+        .read_postings(&stats.term, IndexRecordOption::WithFreqs)
+        .ok()??;
+    Some(SegmentTerm {
+        postings,
+        fieldnorms: segment.get_fieldnorms_reader(field).ok()?,
+        idf: stats.idf,
+        average_fieldnorm: stats.average_fieldnorm,
+    })
+}
+
+/// The same inverse document frequency Tantivy uses
+fn idf(doc_freq: u64, doc_count: u64) -> Score {
+    let x = (doc_count.saturating_sub(doc_freq) as Score + 0.5) / (doc_freq as Score + 0.5);
+    (1.0 + x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_defaults() {
+        let bm25: Bm25 = serde_json::from_str(r#"{ "k1": 2.0 }"#).unwrap();
+        assert_eq!(bm25, Bm25::new(2.0, 0.75));
+    }
+}
@@ -6,15 +6,68 @@ use crate::error::Error;
 use crate::query::{CreateQuery, Query};
 use crate::Result;
 
+/// How a single clause of a [`BoolQuery`] has to match, the same as Tantivy's [`Occur`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClauseOccur {
+    /// The clause must match
+    Must,
+    /// The clause must not match
+    MustNot,
+    /// The clause may match, adding to the score when it does
+    Should,
+}
+
+impl From<ClauseOccur> for Occur {
+    fn from(occur: ClauseOccur) -> Self {
+        match occur {
+            ClauseOccur::Must => Occur::Must,
+            ClauseOccur::MustNot => Occur::MustNot,
+            ClauseOccur::Should => Occur::Should,
+        }
+    }
+}
+
+/// A clause in one of a [`BoolQuery`]'s lists, which either takes the occur of the list it's in or
+/// overrides it with its own, e.g. `{"occur": "should", "query": {"term": {"user": "kimchy"}}}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BoolClause {
+    /// A query with its own occur
+    WithOccur {
+        /// The occur to use in place of the list's
+        occur: ClauseOccur,
+        /// The query of this clause
+        query: Query,
+    },
+    /// A query that takes the occur of the list it's in
+    Query(Query),
+}
+
+impl BoolClause {
+    /// The query of this clause
+    pub fn query(&self) -> &Query {
+        match self {
+            BoolClause::WithOccur { query, .. } | BoolClause::Query(query) => query,
+        }
+    }
+}
+
+impl From<Query> for BoolClause {
+    fn from(query: Query) -> Self {
+        BoolClause::Query(query)
+    }
+}
+
 /// A boolean query parallel to Tantivy's [`tantivy::query::BooleanQuery`]: BooleanQuery
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BoolQuery {
     #[serde(default = "Vec::new")]
-    must: Vec<Query>,
+    pub(crate) must: Vec<BoolClause>,
     #[serde(default = "Vec::new")]
-    must_not: Vec<Query>,
+    pub(crate) must_not: Vec<BoolClause>,
     #[serde(default = "Vec::new")]
-    should: Vec<Query>,
+    pub(crate) should: Vec<BoolClause>,
     #[serde(default)]
     minimum_should_match: Option<u64>,
     #[serde(default)]
@@ -23,9 +76,9 @@ pub struct BoolQuery {
 
 impl BoolQuery {
     pub(crate) fn new(
-        must: Vec<Query>,
-        must_not: Vec<Query>,
-        should: Vec<Query>,
+        must: Vec<BoolClause>,
+        must_not: Vec<BoolClause>,
+        should: Vec<BoolClause>,
         minimum_should_match: Option<u64>,
         boost: Option<f64>,
     ) -> Self {
@@ -42,6 +95,11 @@ impl BoolQuery {
     pub fn builder() -> BoolQueryBuilder {
         BoolQueryBuilder::default()
     }
+
+    /// Every clause of this query regardless of which list it's in
+    pub(crate) fn clauses(&self) -> impl Iterator<Item = &BoolClause> {
+        self.must.iter().chain(&self.must_not).chain(&self.should)
+    }
 }
 
 impl CreateQuery for BoolQuery {
@@ -60,25 +118,31 @@ impl CreateQuery for BoolQuery {
     }
 }
 
-fn parse_queries(schema: &Schema, occur: Occur, queries: Vec<Query>) -> Result<Vec<(Occur, Box<dyn TQuery>)>> {
-    queries
+fn parse_queries(schema: &Schema, occur: Occur, clauses: Vec<BoolClause>) -> Result<Vec<(Occur, Box<dyn TQuery>)>> {
+    clauses
         .into_iter()
-        .map(|q| match q {
-            Query::Fuzzy(f) => Ok((occur, f.create_query(schema)?)),
-            Query::Exact(q) => Ok((occur, q.create_query(schema)?)),
-            Query::Range(r) => Ok((occur, r.create_query(schema)?)),
-            Query::Phrase(p) => Ok((occur, p.create_query(schema)?)),
-            Query::Regex(r) => Ok((occur, r.create_query(schema)?)),
-            _ => Err(Error::QueryError("Invalid type for boolean query".into())),
+        .map(|clause| {
+            let (occur, q) = match clause {
+                BoolClause::WithOccur { occur, query } => (occur.into(), query),
+                BoolClause::Query(query) => (occur, query),
+            };
+            match q {
+                Query::Fuzzy(f) => Ok((occur, f.create_query(schema)?)),
+                Query::Exact(q) => Ok((occur, q.create_query(schema)?)),
+                Query::Range(r) => Ok((occur, r.create_query(schema)?)),
+                Query::Phrase(p) => Ok((occur, p.create_query(schema)?)),
+                Query::Regex(r) => Ok((occur, r.create_query(schema)?)),
+                _ => Err(Error::QueryError("Invalid type for boolean query".into())),
+            }
         })
         .collect::<Result<Vec<(Occur, Box<dyn TQuery>)>>>()
 }
 
 #[derive(Debug, Default)]
 pub struct BoolQueryBuilder {
-    must: Vec<Query>,
-    must_not: Vec<Query>,
-    should: Vec<Query>,
+    must: Vec<BoolClause>,
+    must_not: Vec<BoolClause>,
+    should: Vec<BoolClause>,
     minimum_should_match: u64,
     boost: f64,
 }
@@ -92,7 +156,7 @@ impl BoolQueryBuilder {
     where
         T: Into<Query>,
     {
-        self.must.push(query.into());
+        self.must.push(BoolClause::Query(query.into()));
         self
     }
 
@@ -100,7 +164,7 @@ impl BoolQueryBuilder {
     where
         T: Into<Query>,
     {
-        self.must_not.push(query.into());
+        self.must_not.push(BoolClause::Query(query.into()));
         self
     }
 
@@ -108,7 +172,7 @@ impl BoolQueryBuilder {
     where
         T: Into<Query>,
     {
-        self.should.push(query.into());
+        self.should.push(BoolClause::Query(query.into()));
         self
     }
 
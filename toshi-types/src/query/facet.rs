@@ -6,26 +6,100 @@ use crate::query::KeyValue;
 /// It's also of note that this is the only query that does not implement [`crate::CreateQuery`] this
 /// is because facets are collected via a different interface in Tantivy, not via the query API
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct FacetQuery(KeyValue<String, Vec<String>>);
+pub struct FacetQuery {
+    #[serde(flatten)]
+    facets: KeyValue<String, Vec<String>>,
+    /// The delimiter the facet paths are written with, when set it's replaced with Tantivy's `/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delimiter: Option<String>,
+    /// Only return this many of the most frequent facets, highest count first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_k: Option<usize>,
+}
 
 impl FacetQuery {
     /// Constructor to create a new facet query from a known key value
     pub fn new(facets: KeyValue<String, Vec<String>>) -> Self {
-        Self(facets)
+        Self {
+            facets,
+            delimiter: None,
+            top_k: None,
+        }
     }
 
     /// Constructor to create the key value for the user
     pub fn with_terms(field: String, terms: Vec<String>) -> Self {
-        Self(KeyValue::new(field, terms))
+        Self::new(KeyValue::new(field, terms))
+    }
+
+    /// Use a delimiter other than `/` for the facet paths of this query
+    pub fn with_delimiter<D: ToString>(mut self, delimiter: D) -> Self {
+        self.delimiter = Some(delimiter.to_string());
+        self
+    }
+
+    /// Only return the `k` most frequent facets under the query's path, the path can be left out
+    /// to rank the top level facets of the field
+    pub fn with_top_k(mut self, k: usize) -> Self {
+        self.top_k = Some(k);
+        self
+    }
+
+    /// How many of the most frequent facets to return, if the query is limited to them
+    pub fn get_top_k(&self) -> Option<usize> {
+        self.top_k
     }
 
-    /// Return a query's values
-    pub fn get_facets_values(&self) -> &[String] {
-        &self.0.value
+    /// Return a query's values, normalized to `/` delimited paths if a custom delimiter was given,
+    /// a query without values is for the root facet `/`
+    pub fn get_facets_values(&self) -> Vec<String> {
+        if self.facets.value.is_empty() {
+            return vec!["/".into()];
+        }
+        match self.delimiter.as_deref() {
+            Some(d) if !d.is_empty() && d != "/" => self
+                .facets
+                .value
+                .iter()
+                .map(|v| {
+                    let path = v.replace(d, "/");
+                    if path.starts_with('/') {
+                        path
+                    } else {
+                        format!("/{}", path)
+                    }
+                })
+                .collect(),
+            _ => self.facets.value.clone(),
+        }
     }
 
     /// Return the query's fields
     pub fn get_facets_fields(&self) -> &str {
-        &self.0.field
+        &self.facets.field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_delimiter() {
+        let body = r#"{ "test_facet": ["cat.cat2", ".dog"], "delimiter": "." }"#;
+        let query: FacetQuery = serde_json::from_str(body).unwrap();
+        assert_eq!(query.get_facets_fields(), "test_facet");
+        assert_eq!(query.get_facets_values(), vec!["/cat/cat2", "/dog"]);
+
+        let body = r#"{ "test_facet": ["/cat"] }"#;
+        let query: FacetQuery = serde_json::from_str(body).unwrap();
+        assert_eq!(query.get_facets_values(), vec!["/cat"]);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let query: FacetQuery = serde_json::from_str(r#"{ "test_facet": [], "top_k": 3 }"#).unwrap();
+        assert_eq!(query.get_top_k(), Some(3));
+        assert_eq!(query.get_facets_values(), vec!["/"]);
     }
 }
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Collector, ScoreSegmentTweaker, ScoreTweaker, TopDocs};
+use tantivy::schema::{Field, FieldType, Schema};
+use tantivy::{DocAddress, DocId, Score, SegmentReader};
+
+use crate::query::agg::{numeric_fast_field, NumericColumn};
+use crate::query::Query;
+use crate::Result;
+
+/// Wraps a query and multiplies the relevance of each match by an exponential decay,
+/// `exp(-lambda * |origin - value|)`, where value is read from a numeric fast field. This makes
+/// documents further from `origin`, such as older documents when `origin` is now, rank lower.
+///
+/// The decay is applied while collecting the top documents, so it's only accepted as the
+/// outermost query of a search and can't be combined with `score_expression` or `bm25`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionScoreQuery {
+    /// The query whose matches are scored
+    pub(crate) query: Box<Query>,
+    /// The numeric fast field the decay is computed from
+    pub(crate) field: String,
+    /// The value with no decay at all
+    pub(crate) origin: f64,
+    /// How quickly scores decay as values move away from `origin`
+    pub(crate) lambda: f64,
+}
+
+impl FunctionScoreQuery {
+    /// Constructor for a decayed query
+    pub fn new<F: ToString>(query: Query, field: F, origin: f64, lambda: f64) -> Self {
+        Self {
+            query: Box::new(query),
+            field: field.to_string(),
+            origin,
+            lambda,
+        }
+    }
+
+    /// The wrapped query
+    pub fn get_query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Consume this query, returning the query it wraps
+    pub fn into_query(self) -> Query {
+        *self.query
+    }
+
+    /// A top docs collector that applies this query's decay to every matched document's score
+    pub fn top_docs(&self, schema: &Schema, limit: usize) -> Result<impl Collector<Fruit = Vec<(Score, DocAddress)>>> {
+        let (field, field_type) = numeric_fast_field(schema, &self.field)?;
+        let tweaker = DecayTweaker {
+            field,
+            field_type,
+            origin: self.origin,
+            lambda: self.lambda,
+        };
+        Ok(TopDocs::with_limit(limit).tweak_score(tweaker))
+    }
+}
+
+/// Opens the decayed field's column for each segment, failing the search if it can't be opened
+/// rather than leaving the segment's scores undecayed
+struct DecayTweaker {
+    field: Field,
+    field_type: FieldType,
+    origin: f64,
+    lambda: f64,
+}
+
+struct DecaySegmentTweaker {
+    column: NumericColumn,
+    origin: f64,
+    lambda: f64,
+}
+
+impl ScoreTweaker<Score> for DecayTweaker {
+    type Child = DecaySegmentTweaker;
+
+    fn segment_tweaker(&self, segment: &SegmentReader) -> tantivy::Result<Self::Child> {
+        Ok(DecaySegmentTweaker {
+            column: NumericColumn::open(segment, self.field, &self.field_type)?,
+            origin: self.origin,
+            lambda: self.lambda,
+        })
+    }
+}
+
+impl ScoreSegmentTweaker<Score> for DecaySegmentTweaker {
+    fn score(&mut self, doc: DocId, score: Score) -> Score {
+        score * (-self.lambda * (self.origin - self.column.get(doc)).abs()).exp() as Score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::*;
+
+    use super::*;
+
+    #[test]
+    fn test_deserialize_function_score() {
+        let body = r#"{ "function_score": { "query": { "term": { "test_text": "document" } }, "field": "test_u64", "origin": 14, "lambda": 0.5 } }"#;
+        let query: Query = serde_json::from_str(body).unwrap();
+        match query {
+            Query::FunctionScore { function_score } => {
+                assert!(matches!(function_score.get_query(), Query::Exact(_)));
+                assert_eq!(function_score.field, "test_u64");
+            }
+            q => panic!("Expected a function score query, got: {:?}", q),
+        }
+    }
+
+    #[test]
+    fn test_requires_fast_field() {
+        let mut builder = SchemaBuilder::new();
+        builder.add_u64_field("test_u64", STORED);
+        let schema = builder.build();
+        let query = FunctionScoreQuery::new(Query::All, "test_u64", 0.0, 1.0);
+        assert!(query.top_docs(&schema, 10).is_err());
+    }
+}
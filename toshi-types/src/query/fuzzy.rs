@@ -8,9 +8,9 @@ use crate::query::{make_field_value, CreateQuery, KeyValue, Query};
 use crate::Result;
 
 /// A query where terms can have distance between them, but still be a match
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FuzzyQuery {
-    fuzzy: KeyValue<String, FuzzyTerm>,
+    pub(crate) fuzzy: KeyValue<String, FuzzyTerm>,
 }
 
 impl FuzzyQuery {
@@ -28,16 +28,55 @@ impl CreateQuery for FuzzyQuery {
     fn create_query(self, schema: &Schema) -> Result<Box<dyn TantivyQuery>> {
         let KeyValue { field, value } = self.fuzzy;
         let term = make_field_value(schema, &field, &value.value)?;
-        Ok(Box::new(FuzzyTermQuery::new(term, value.distance, value.transposition)))
+        let distance = value.distance.for_term(&value.value);
+        Ok(Box::new(FuzzyTermQuery::new(term, distance, value.transposition)))
+    }
+}
+
+/// The marker for picking an edit distance from the term, serialized as `"auto"`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Auto {
+    /// Scale the edit distance by the term's length
+    Auto,
+}
+
+/// How many edits a term can be away from a match, either a fixed number or `"auto"`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum FuzzyDistance {
+    /// The same distance for every term
+    Fixed(u8),
+    /// 0 for terms of up to 2 characters, 1 for 3 to 5 and 2 for anything longer
+    Auto(Auto),
+}
+
+impl Default for FuzzyDistance {
+    fn default() -> Self {
+        FuzzyDistance::Fixed(0)
+    }
+}
+
+impl FuzzyDistance {
+    /// The edit distance used for `term`
+    pub fn for_term(self, term: &str) -> u8 {
+        match self {
+            FuzzyDistance::Fixed(distance) => distance,
+            FuzzyDistance::Auto(_) => match term.chars().count() {
+                0..=2 => 0,
+                3..=5 => 1,
+                _ => 2,
+            },
+        }
     }
 }
 
 /// A term to be considered in the query
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FuzzyTerm {
     value: String,
     #[serde(default)]
-    distance: u8,
+    distance: FuzzyDistance,
     #[serde(default)]
     transposition: bool,
 }
@@ -47,7 +86,16 @@ impl FuzzyTerm {
     pub fn new(value: String, distance: u8, transposition: bool) -> Self {
         Self {
             value,
-            distance,
+            distance: FuzzyDistance::Fixed(distance),
+            transposition,
+        }
+    }
+
+    /// Constructor to create a fuzzy query whose distance scales with the length of the term
+    pub fn auto(value: String, transposition: bool) -> Self {
+        Self {
+            value,
+            distance: FuzzyDistance::Auto(Auto::Auto),
             transposition,
         }
     }
@@ -57,7 +105,7 @@ impl FuzzyTerm {
 pub struct FuzzyQueryBuilder {
     field: String,
     value: String,
-    distance: u8,
+    distance: FuzzyDistance,
     transposition: bool,
 }
 
@@ -83,7 +131,12 @@ impl FuzzyQueryBuilder {
     }
 
     pub fn with_distance(mut self, distance: u8) -> Self {
-        self.distance = distance;
+        self.distance = FuzzyDistance::Fixed(distance);
+        self
+    }
+
+    pub fn with_auto_distance(mut self) -> Self {
+        self.distance = FuzzyDistance::Auto(Auto::Auto);
         self
     }
 
@@ -93,8 +146,31 @@ impl FuzzyQueryBuilder {
     }
 
     pub fn build(self) -> Query {
-        let term = FuzzyTerm::new(self.value, self.distance, self.transposition);
+        let term = FuzzyTerm {
+            value: self.value,
+            distance: self.distance,
+            transposition: self.transposition,
+        };
         let query = FuzzyQuery::new(KeyValue::new(self.field, term));
         Query::Fuzzy(query)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_distance() {
+        let term: FuzzyTerm = serde_json::from_str(r#"{ "value": "ab", "distance": "auto" }"#).unwrap();
+        assert_eq!(term.distance, FuzzyDistance::Auto(Auto::Auto));
+        assert_eq!(term.distance.for_term(&term.value), 0);
+        assert_eq!(term.distance.for_term("docs"), 1);
+        assert_eq!(term.distance.for_term("document"), 2);
+
+        let term: FuzzyTerm = serde_json::from_str(r#"{ "value": "document", "distance": 1 }"#).unwrap();
+        assert_eq!(term.distance.for_term(&term.value), 1);
+        let term: FuzzyTerm = serde_json::from_str(r#"{ "value": "document" }"#).unwrap();
+        assert_eq!(term.distance.for_term(&term.value), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::marker::PhantomData;
 
@@ -13,15 +14,20 @@ use tantivy::Term;
 
 use crate::error::Error;
 use crate::query::{
-    boolean::BoolQuery, facet::FacetQuery, fuzzy::FuzzyQuery, phrase::PhraseQuery, range::RangeQuery, regex::RegexQuery, term::ExactTerm,
+    agg::Aggregation, bm25::Bm25, boolean::BoolQuery, facet::FacetQuery, function_score::FunctionScoreQuery, fuzzy::FuzzyQuery,
+    phrase::PhraseQuery, range::RangeQuery, regex::RegexQuery, score_expression::ScoreExpression, term::ExactTerm,
 };
 
+pub(crate) mod agg;
+pub(crate) mod bm25;
 pub(crate) mod boolean;
 pub(crate) mod facet;
+pub(crate) mod function_score;
 pub(crate) mod fuzzy;
 pub(crate) mod phrase;
 pub(crate) mod range;
 pub(crate) mod regex;
+pub(crate) mod score_expression;
 pub(crate) mod term;
 
 /// Additional Options for results returned from queries
@@ -29,6 +35,13 @@ pub(crate) mod term;
 pub struct QueryOptions {
     pretty: Option<bool>,
     include_sizes: Option<bool>,
+    limit: Option<usize>,
+    commit_every: Option<usize>,
+    template: Option<String>,
+    upsert_field: Option<String>,
+    rejected_line_length: Option<usize>,
+    count_only: Option<bool>,
+    since: Option<u64>,
 }
 
 impl QueryOptions {
@@ -42,7 +55,17 @@ impl QueryOptions {
     /// returns: QueryOptions
     ///
     pub fn new(pretty: Option<bool>, include_sizes: Option<bool>) -> Self {
-        QueryOptions { pretty, include_sizes }
+        QueryOptions {
+            pretty,
+            include_sizes,
+            limit: None,
+            commit_every: None,
+            template: None,
+            upsert_field: None,
+            rejected_line_length: None,
+            count_only: None,
+            since: None,
+        }
     }
 
     /// Include Index sizes or not
@@ -56,6 +79,48 @@ impl QueryOptions {
     pub fn pretty(&self) -> bool {
         self.pretty.unwrap_or(false)
     }
+
+    /// Max number of documents the client asked for, if any
+    #[inline]
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Commit a bulk insert after every N documents
+    #[inline]
+    pub fn commit_every(&self) -> Option<usize> {
+        self.commit_every
+    }
+
+    /// Name of the schema template to create an index from
+    #[inline]
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
+
+    /// Field a bulk insert replaces existing documents by
+    #[inline]
+    pub fn upsert_field(&self) -> Option<&str> {
+        self.upsert_field.as_deref()
+    }
+
+    /// Echo a line a bulk insert rejects back in its error, cut down to this many characters
+    #[inline]
+    pub fn rejected_line_length(&self) -> Option<usize> {
+        self.rejected_line_length
+    }
+
+    /// Only count an index's documents instead of returning them
+    #[inline]
+    pub fn count_only(&self) -> bool {
+        self.count_only.unwrap_or(false)
+    }
+
+    /// Opstamp of the last document a tail's client got before reconnecting
+    #[inline]
+    pub fn since(&self) -> Option<u64> {
+        self.since
+    }
 }
 
 /// Trait that generically represents Tantivy queries
@@ -65,7 +130,7 @@ pub trait CreateQuery {
 }
 
 /// The possible Tantivy Queries to issue
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Query {
     /// [`tantivy::query::FuzzyQuery`]: FuzzyQuery
@@ -83,6 +148,11 @@ pub enum Query {
         /// Collection of boolean clauses
         bool: BoolQuery,
     },
+    /// A query whose scores decay with distance from an origin, see [`FunctionScoreQuery`]
+    FunctionScore {
+        /// The query and decay to apply to it
+        function_score: FunctionScoreQuery,
+    },
     /// Raw is a query that passes by the query parser and is just executed directly against the index
     Raw {
         /// The actual query to be ran
@@ -92,18 +162,225 @@ pub enum Query {
     All,
 }
 
-/// Boolean gets it's own special From impl due to not being a tuple query.
+impl Query {
+    /// Every schema field this query references, raw queries are left to Tantivy's query parser
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Query::Fuzzy(q) => vec![q.fuzzy.field.as_str()],
+            Query::Exact(q) => vec![q.term.field.as_str()],
+            Query::Phrase(q) => vec![q.phrase.field.as_str()],
+            Query::Regex(q) => vec![q.regex.field.as_str()],
+            Query::Range(q) => vec![q.range.field.as_str()],
+            Query::Boolean { bool } => bool.clauses().flat_map(|clause| clause.query().fields()).collect(),
+            Query::FunctionScore { function_score } => {
+                let mut fields = function_score.query.fields();
+                fields.push(function_score.field.as_str());
+                fields
+            }
+            Query::Raw { .. } | Query::All => Vec::new(),
+        }
+    }
+
+    /// Check that every field this query references exists in the schema, all of the missing
+    /// fields are reported in a single [`Error::UnknownIndexField`]
+    pub fn validate_fields(&self, schema: &Schema) -> crate::Result<()> {
+        let mut missing: Vec<&str> = self.fields().into_iter().filter(|f| schema.get_field(f).is_none()).collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        missing.sort_unstable();
+        missing.dedup();
+        Err(Error::UnknownIndexField(missing.join(", ")))
+    }
+
+    /// The number of boolean clauses in this query, including those of nested boolean queries
+    pub fn clause_count(&self) -> usize {
+        match self {
+            Query::Boolean { bool } => bool.clauses().map(|clause| 1 + clause.query().clause_count()).sum(),
+            Query::FunctionScore { function_score } => function_score.query.clause_count(),
+            _ => 0,
+        }
+    }
+
+    /// How many levels deep this query nests other queries, a query without any nested queries has
+    /// a depth of 1
+    pub fn depth(&self) -> usize {
+        match self {
+            Query::Boolean { bool } => 1 + bool.clauses().map(|clause| clause.query().depth()).max().unwrap_or_default(),
+            Query::FunctionScore { function_score } => 1 + function_score.query.depth(),
+            _ => 1,
+        }
+    }
+
+    /// Whether a function score query appears anywhere in this query, including this query itself
+    pub fn contains_function_score(&self) -> bool {
+        match self {
+            Query::Boolean { bool } => bool.clauses().any(|clause| clause.query().contains_function_score()),
+            Query::FunctionScore { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Reject function score queries anywhere but the outermost query, since the decay is applied
+    /// while collecting top documents a nested one would silently have no effect
+    pub fn validate_function_score(&self) -> crate::Result<()> {
+        let nested = match self {
+            Query::FunctionScore { function_score } => function_score.query.contains_function_score(),
+            query => query.contains_function_score(),
+        };
+        if nested {
+            let msg = "function_score is only supported as the outermost query";
+            return Err(Error::QueryError(msg.into()));
+        }
+        Ok(())
+    }
+
+    /// Reject queries with more clauses or deeper nesting than allowed before they're built, a limit
+    /// of 0 leaves that dimension unchecked
+    pub fn validate_complexity(&self, max_clauses: usize, max_depth: usize) -> crate::Result<()> {
+        let clauses = self.clause_count();
+        if max_clauses > 0 && clauses > max_clauses {
+            let msg = format!("Query has {} clauses which is over the limit of {}", clauses, max_clauses);
+            return Err(Error::QueryError(msg));
+        }
+        let depth = self.depth();
+        if max_depth > 0 && depth > max_depth {
+            let msg = format!("Query is nested {} levels deep which is over the limit of {}", depth, max_depth);
+            return Err(Error::QueryError(msg));
+        }
+        Ok(())
+    }
+}
+
+/// Boolean and function score get their own special From impls due to not being tuple queries.
 impl From<BoolQuery> for Query {
     fn from(bool: BoolQuery) -> Self {
         Query::Boolean { bool }
     }
 }
 
+impl From<FunctionScoreQuery> for Query {
+    fn from(function_score: FunctionScoreQuery) -> Self {
+        Query::FunctionScore { function_score }
+    }
+}
+
 macro_rules! to_query { ($($t:tt $e:ident),+) => { $(impl From<$t> for Query { fn from(q: $t) -> Self { Query::$e(q) } })* }; }
 to_query! { PhraseQuery Phrase, FuzzyQuery Fuzzy, ExactTerm Exact, RegexQuery Regex, RangeQuery Range }
 
-/// The request body of a search POST in Toshi
+/// The direction results are sorted in by a field
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Lowest values first
+    Asc,
+    /// Highest values first
+    #[default]
+    Desc,
+}
+
+/// A field to sort results by and the direction to sort it in, a bare field name sorts descending
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "SortFieldRepr")]
+pub struct SortField {
+    /// The field to sort by
+    pub field: String,
+    /// The direction to sort in
+    pub order: SortOrder,
+}
+
+impl SortField {
+    /// Sort by `field` in the given direction
+    pub fn new<F: ToString>(field: F, order: SortOrder) -> Self {
+        Self {
+            field: field.to_string(),
+            order,
+        }
+    }
+}
+
+impl From<&str> for SortField {
+    fn from(field: &str) -> Self {
+        SortField::new(field, SortOrder::default())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SortFieldRepr {
+    Name(String),
+    Field {
+        field: String,
+        #[serde(default)]
+        order: SortOrder,
+    },
+}
+
+impl From<SortFieldRepr> for SortField {
+    fn from(repr: SortFieldRepr) -> Self {
+        match repr {
+            SortFieldRepr::Name(field) => SortField::new(field, SortOrder::default()),
+            SortFieldRepr::Field { field, order } => SortField::new(field, order),
+        }
+    }
+}
+
+/// `sort_by` can be a single sort field or a list of them, each given by name or with an order
+fn sort_fields<'de, D>(deserializer: D) -> Result<Vec<SortField>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(SortField),
+        Many(Vec<SortField>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(field)) => vec![field],
+        Some(OneOrMany::Many(fields)) => fields,
+        None => Vec::new(),
+    })
+}
+
+/// Placement of documents that have no value for a field being sorted on, whichever direction the
+/// field is sorted in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMissing {
+    /// Documents without a value come after every document with one
+    #[default]
+    Last,
+    /// Documents without a value come before every document with one
+    First,
+}
+
+/// Which stored text fields to return fragments of around where the query matched, fields that
+/// aren't stored text fields are skipped
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HighlightOptions {
+    /// The fields to highlight
+    pub fields: Vec<String>,
+    /// The most characters a fragment may have, Tantivy's default of 150 when missing
+    #[serde(default)]
+    pub max_fragment_length: Option<usize>,
+}
+
+impl HighlightOptions {
+    /// Highlight the given fields with fragments of the default length
+    pub fn new<F: ToString>(fields: &[F]) -> Self {
+        Self {
+            fields: fields.iter().map(ToString::to_string).collect(),
+            max_fragment_length: None,
+        }
+    }
+}
+
+/// The request body of a search POST in Toshi, unknown keys are rejected so a misspelled option
+/// isn't silently replaced by its default
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Search {
     /// Optional query
     pub query: Option<Query>,
@@ -112,19 +389,90 @@ pub struct Search {
     /// Max number of documents to return
     #[serde(default = "Search::default_limit")]
     pub limit: usize,
-    /// Field to sort results by
+    /// Number of top documents to skip before the ones returned
+    #[serde(default)]
+    pub offset: usize,
+    /// Fields to sort results by, later fields only ordering documents that tie on the earlier
+    /// ones. [`Search::DOC_ORDER`] on its own returns documents in the order they were added in,
+    /// and [`Search::SCORE`] sorts by the query's score.
+    #[serde(default, deserialize_with = "sort_fields")]
+    pub sort_by: Vec<SortField>,
+    /// Where documents without a value for the sort field are placed
     #[serde(default)]
-    pub sort_by: Option<String>,
+    pub sort_missing: SortMissing,
+    /// Named aggregations to compute over the matched documents
+    #[serde(default)]
+    pub aggs: BTreeMap<String, Aggregation>,
+    /// Require every term of a raw query to match instead of any of them
+    #[serde(default)]
+    pub conjunction_by_default: bool,
+    /// Only return the highest ranked document for each distinct value of this field, pages are
+    /// cut from those documents and the pagination total counts them
+    #[serde(default)]
+    pub collapse: Option<String>,
+    /// Rescore matches with these BM25 parameters instead of the index's or Tantivy's defaults
+    #[serde(default)]
+    pub bm25: Option<Bm25>,
+    /// Return the byte offsets of every query match in the text fields of each document
+    #[serde(default)]
+    pub return_match_offsets: bool,
+    /// Return the document frequency and total term frequency of each term of the query
+    #[serde(default)]
+    pub term_stats: bool,
+    /// Return every field of a document as an array, even when it only has a single value
+    #[serde(default)]
+    pub always_arrays: bool,
+    /// Stop searching once this many documents have matched, returning them in index order
+    /// without scores. The matched total is then a lower bound rather than an exact count.
+    #[serde(default)]
+    pub early_terminate: Option<usize>,
+    /// Rescore matches with an arithmetic expression of their score and fast field values, see
+    /// [`ScoreExpression`]
+    #[serde(default)]
+    pub score_expression: Option<ScoreExpression>,
+    /// Return fragments of stored text fields with the query's matches highlighted
+    #[serde(default)]
+    pub highlight: Option<HighlightOptions>,
+    /// Only count the matching documents, none of them are loaded or returned
+    #[serde(default)]
+    pub count_only: bool,
 }
 
 impl Search {
+    /// The `sort_by` value that returns documents in index order without scoring them. Documents
+    /// come back in the order they were added until segments are merged and reordered.
+    pub const DOC_ORDER: &'static str = "_doc";
+
+    /// The `sort_by` value that sorts by the score of the query, on its own in descending order it's
+    /// the same as not sorting. After other fields it breaks the ties between their values.
+    pub const SCORE: &'static str = "_score";
+
+    /// The most documents a search returns when no other limit is configured
+    pub const DEFAULT_MAX_LIMIT: usize = 10_000;
+
+    /// The most documents a search may skip when no other limit is configured
+    pub const DEFAULT_MAX_OFFSET: usize = 10_000;
+
     /// Construct a new Search query
     pub fn new(query: Option<Query>, facets: Option<FacetQuery>, limit: usize, sort_by: Option<String>) -> Self {
         Search {
             query,
             facets,
             limit,
-            sort_by,
+            offset: 0,
+            sort_by: sort_by.into_iter().map(|f| SortField::new(f, SortOrder::default())).collect(),
+            sort_missing: SortMissing::default(),
+            aggs: BTreeMap::new(),
+            conjunction_by_default: false,
+            collapse: None,
+            bm25: None,
+            return_match_offsets: false,
+            term_stats: false,
+            always_arrays: false,
+            early_terminate: None,
+            score_expression: None,
+            highlight: None,
+            count_only: false,
         }
     }
 
@@ -143,18 +491,36 @@ impl Search {
         100
     }
 
+    /// Fill in a missing query with [`Query::All`] and clamp the limit to `max_limit`. Searches that
+    /// skip more than `max_offset` documents are rejected instead of clamped, since every skipped
+    /// document still has to be collected and clamping would return a different page.
+    pub fn normalize(mut self, max_limit: usize, max_offset: usize) -> crate::Result<Self> {
+        if self.offset > max_offset {
+            let msg = format!(
+                "Offset: {} is over the max offset of {}, narrow the query to reach documents further down",
+                self.offset, max_offset
+            );
+            return Err(Error::QueryError(msg));
+        }
+        self.query.get_or_insert(Query::All);
+        self.limit = self.limit.min(max_limit);
+        Ok(self)
+    }
+
+    /// Leave documents out of the results while still counting every hit and computing facets and
+    /// aggregations, for replicas answering a coordinator that only needs those
+    pub fn aggregations_only(mut self) -> Self {
+        self.count_only = true;
+        self
+    }
+
     pub(crate) fn all_query() -> Query {
         Query::All
     }
 
     /// A shortcut for querying for all documents in an Index
     pub fn all_docs() -> Self {
-        Self {
-            query: Some(Self::all_query()),
-            facets: None,
-            limit: Self::default_limit(),
-            sort_by: None,
-        }
+        Self::new(Some(Self::all_query()), None, Self::default_limit(), None)
     }
 
     /// Another shortcut, but with a known limit
@@ -170,7 +536,20 @@ pub struct SearchBuilder {
     query: Query,
     facets: Option<FacetQuery>,
     limit: usize,
-    sort_by: Option<String>,
+    offset: usize,
+    sort_by: Vec<SortField>,
+    sort_missing: SortMissing,
+    aggs: BTreeMap<String, Aggregation>,
+    conjunction_by_default: bool,
+    collapse: Option<String>,
+    bm25: Option<Bm25>,
+    return_match_offsets: bool,
+    term_stats: bool,
+    always_arrays: bool,
+    early_terminate: Option<usize>,
+    score_expression: Option<ScoreExpression>,
+    highlight: Option<HighlightOptions>,
+    count_only: bool,
 }
 
 impl Default for SearchBuilder {
@@ -185,7 +564,20 @@ impl SearchBuilder {
             query: Query::All,
             facets: None,
             limit: Search::default_limit(),
-            sort_by: None,
+            offset: 0,
+            sort_by: Vec::new(),
+            sort_missing: SortMissing::default(),
+            aggs: BTreeMap::new(),
+            conjunction_by_default: false,
+            collapse: None,
+            bm25: None,
+            return_match_offsets: false,
+            term_stats: false,
+            always_arrays: false,
+            early_terminate: None,
+            score_expression: None,
+            highlight: None,
+            count_only: false,
         }
     }
 
@@ -201,15 +593,95 @@ impl SearchBuilder {
         self.limit = limit;
         self
     }
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
     pub fn sort_by<V>(mut self, field: V) -> Self
     where
         V: ToString,
     {
-        self.sort_by = Some(field.to_string());
+        self.sort_by.push(SortField::new(field, SortOrder::default()));
+        self
+    }
+    pub fn sort_by_order<V>(mut self, field: V, order: SortOrder) -> Self
+    where
+        V: ToString,
+    {
+        self.sort_by.push(SortField::new(field, order));
+        self
+    }
+    pub fn sort_missing(mut self, missing: SortMissing) -> Self {
+        self.sort_missing = missing;
+        self
+    }
+    pub fn with_aggregation<V>(mut self, name: V, agg: Aggregation) -> Self
+    where
+        V: ToString,
+    {
+        self.aggs.insert(name.to_string(), agg);
+        self
+    }
+    pub fn conjunction_by_default(mut self) -> Self {
+        self.conjunction_by_default = true;
+        self
+    }
+    pub fn collapse_on<V>(mut self, field: V) -> Self
+    where
+        V: ToString,
+    {
+        self.collapse = Some(field.to_string());
+        self
+    }
+    pub fn with_bm25(mut self, bm25: Bm25) -> Self {
+        self.bm25 = Some(bm25);
+        self
+    }
+    pub fn with_match_offsets(mut self) -> Self {
+        self.return_match_offsets = true;
+        self
+    }
+    pub fn with_term_stats(mut self) -> Self {
+        self.term_stats = true;
+        self
+    }
+    pub fn with_always_arrays(mut self) -> Self {
+        self.always_arrays = true;
+        self
+    }
+    pub fn early_terminate(mut self, matches: usize) -> Self {
+        self.early_terminate = Some(matches);
+        self
+    }
+    pub fn with_score_expression(mut self, expr: ScoreExpression) -> Self {
+        self.score_expression = Some(expr);
+        self
+    }
+    pub fn with_highlight(mut self, highlight: HighlightOptions) -> Self {
+        self.highlight = Some(highlight);
+        self
+    }
+    pub fn count_only(mut self) -> Self {
+        self.count_only = true;
         self
     }
     pub fn build(self) -> Search {
-        Search::new(Some(self.query), self.facets, self.limit, self.sort_by)
+        let mut search = Search::new(Some(self.query), self.facets, self.limit, None);
+        search.sort_by = self.sort_by;
+        search.offset = self.offset;
+        search.sort_missing = self.sort_missing;
+        search.aggs = self.aggs;
+        search.conjunction_by_default = self.conjunction_by_default;
+        search.collapse = self.collapse;
+        search.bm25 = self.bm25;
+        search.return_match_offsets = self.return_match_offsets;
+        search.term_stats = self.term_stats;
+        search.always_arrays = self.always_arrays;
+        search.early_terminate = self.early_terminate;
+        search.score_expression = self.score_expression;
+        search.highlight = self.highlight;
+        search.count_only = self.count_only;
+        search
     }
 }
 
@@ -223,7 +695,7 @@ fn make_field_value(schema: &Schema, k: &str, v: &str) -> crate::Result<Term> {
 
 /// A single key/value pair, this struct is used when we want to accept only single key/value pairs
 /// for a query and a Map would not allow that.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct KeyValue<K, V>
 where
     K: DeserializeOwned,
@@ -304,7 +776,7 @@ where
     }
 }
 
-impl<'de, K, V> Serialize for KeyValue<K, V>
+impl<K, V> Serialize for KeyValue<K, V>
 where
     K: Serialize + DeserializeOwned,
     V: Serialize + DeserializeOwned,
@@ -325,9 +797,16 @@ pub struct FlatNamedDocument(pub DashMap<String, Value>);
 
 impl From<NamedFieldDocument> for FlatNamedDocument {
     fn from(nfd: NamedFieldDocument) -> Self {
+        Self::from_named(nfd, false)
+    }
+}
+
+impl FlatNamedDocument {
+    /// Flatten a document's fields, single valued fields become scalars unless `always_arrays` is set
+    pub fn from_named(nfd: NamedFieldDocument, always_arrays: bool) -> Self {
         let map = DashMap::with_capacity(nfd.0.len());
         for (k, v) in nfd.0 {
-            if v.len() == 1 {
+            if v.len() == 1 && !always_arrays {
                 map.insert(k, serde_json::to_value(&v[0]).unwrap());
                 continue;
             }
@@ -335,6 +814,40 @@ impl From<NamedFieldDocument> for FlatNamedDocument {
         }
         FlatNamedDocument(map)
     }
+
+    /// Every value of a field, single valued fields are returned as a one element Vec
+    pub fn get_all(&self, field: &str) -> Vec<Value> {
+        match self.0.get(field).map(|v| v.value().clone()) {
+            Some(Value::Array(values)) => values,
+            Some(value) => vec![value],
+            None => Vec::new(),
+        }
+    }
+
+    /// The first value of a field
+    pub fn get_first(&self, field: &str) -> Option<Value> {
+        self.get_all(field).into_iter().next()
+    }
+
+    /// The first value of a text field
+    pub fn get_str(&self, field: &str) -> Option<String> {
+        self.get_first(field).and_then(|v| v.as_str().map(String::from))
+    }
+
+    /// The first value of an i64 field
+    pub fn get_i64(&self, field: &str) -> Option<i64> {
+        self.get_first(field).and_then(|v| v.as_i64())
+    }
+
+    /// The first value of a u64 field
+    pub fn get_u64(&self, field: &str) -> Option<u64> {
+        self.get_first(field).and_then(|v| v.as_u64())
+    }
+
+    /// The first value of an f64 field
+    pub fn get_f64(&self, field: &str) -> Option<f64> {
+        self.get_first(field).and_then(|v| v.as_f64())
+    }
 }
 
 #[cfg(test)]
@@ -360,6 +873,95 @@ mod tests {
         println!("{}", serde_json::to_string_pretty(&named).unwrap());
     }
 
+    #[test]
+    fn test_doc_accessors() {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        let likes = schema_builder.add_i64_field("likes", STORED);
+        let schema: Schema = schema_builder.build();
+        let doc = tantivy::doc!(
+            title => "Life Aquatic",
+            title => "The Life Aquatic with Steve Zissou",
+            likes => -4i64
+        );
+        let named: FlatNamedDocument = schema.to_named_doc(&doc).into();
+
+        assert_eq!(named.get_i64("likes"), Some(-4));
+        assert_eq!(named.get_u64("likes"), None);
+        assert_eq!(named.get_all("likes").len(), 1);
+        assert_eq!(named.get_str("title"), Some("Life Aquatic".into()));
+        assert_eq!(named.get_all("title").len(), 2);
+        assert_eq!(named.get_all("title")[1], "The Life Aquatic with Steve Zissou");
+        assert_eq!(named.get_str("asdf"), None);
+        assert!(named.get_all("asdf").is_empty());
+    }
+
+    #[test]
+    fn test_doc_always_arrays() {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        let likes = schema_builder.add_i64_field("likes", STORED);
+        let schema: Schema = schema_builder.build();
+        let doc = tantivy::doc!(title => "Life Aquatic", likes => -4i64);
+
+        let named: FlatNamedDocument = schema.to_named_doc(&doc).into();
+        assert_eq!(serde_json::to_value(&named).unwrap()["likes"], serde_json::json!(-4));
+        let named = FlatNamedDocument::from_named(schema.to_named_doc(&doc), true);
+        let value = serde_json::to_value(&named).unwrap();
+        assert_eq!(value["likes"], serde_json::json!([-4]));
+        assert_eq!(value["title"], serde_json::json!(["Life Aquatic"]));
+        assert_eq!(named.get_i64("likes"), Some(-4));
+    }
+
+    #[test]
+    fn test_validate_fields() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("title", TEXT);
+        let schema: Schema = schema_builder.build();
+
+        let query = BoolQuery::builder()
+            .must_match(ExactTerm::with_term("title", "aquatic"))
+            .must_match(ExactTerm::with_term("asdf", "aquatic"))
+            .should_match(FuzzyQuery::builder().for_field("qwer").with_value("life").build())
+            .build();
+        assert_eq!(query.fields(), vec!["title", "asdf", "qwer"]);
+        let err = query.validate_fields(&schema).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown Field: 'asdf, qwer' queried");
+        assert!(Query::Exact(ExactTerm::with_term("title", "life")).validate_fields(&schema).is_ok());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let search: Search = serde_json::from_str(r#"{ "limit": 500, "offset": 10 }"#).unwrap();
+        let normalized = search.clone().normalize(100, 10).unwrap();
+        assert_eq!(normalized.limit, 100);
+        assert_eq!(normalized.offset, 10);
+        assert!(matches!(normalized.query, Some(Query::All)));
+        assert_eq!(search.clone().normalize(1000, 10).unwrap().limit, 500);
+        assert!(matches!(search.normalize(100, 9), Err(Error::QueryError(_))));
+    }
+
+    #[test]
+    fn test_validate_complexity() {
+        let leaf = || ExactTerm::with_term("title", "aquatic");
+        let inner = BoolQuery::builder().must_match(leaf()).should_match(leaf()).build();
+        let query = BoolQuery::builder().must_match(inner).must_not_match(leaf()).build();
+        assert_eq!(query.clause_count(), 4);
+        assert_eq!(query.depth(), 3);
+        assert!(query.validate_complexity(4, 3).is_ok());
+        assert!(query.validate_complexity(0, 0).is_ok());
+        let err = query.validate_complexity(3, 0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error in query execution: 'Query has 4 clauses which is over the limit of 3'"
+        );
+        let err = query.validate_complexity(0, 2).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error in query execution: 'Query is nested 3 levels deep which is over the limit of 2'"
+        );
+    }
+
     #[test]
     fn test_kv_serialize() {
         let kv = KeyValue::new("test_field".to_string(), 1);
@@ -375,7 +977,26 @@ mod tests {
 
         assert!(query.query.is_some());
         assert_eq!(query.limit, 50);
-        assert!(query.sort_by.is_some());
-        assert_eq!(query.sort_by.unwrap(), "text");
+        assert_eq!(query.sort_by, vec![SortField::from("text")]);
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let search: Search = serde_json::from_str(r#"{ "sort_by": "test_u64" }"#).unwrap();
+        assert_eq!(search.sort_by, vec![SortField::new("test_u64", SortOrder::Desc)]);
+
+        let body = r#"{ "sort_by": ["test_u64", { "field": "test_i64", "order": "asc" }, { "field": "rank" }] }"#;
+        let search: Search = serde_json::from_str(body).unwrap();
+        let expected = vec![
+            SortField::new("test_u64", SortOrder::Desc),
+            SortField::new("test_i64", SortOrder::Asc),
+            SortField::new("rank", SortOrder::Desc),
+        ];
+        assert_eq!(search.sort_by, expected);
+
+        let search: Search = serde_json::from_str(r#"{ "sort_by": null }"#).unwrap();
+        assert!(search.sort_by.is_empty());
+        let round_trip: Search = serde_json::from_str(&serde_json::to_string(&Search::builder().sort_by("a").build()).unwrap()).unwrap();
+        assert_eq!(round_trip.sort_by, vec![SortField::from("a")]);
     }
 }
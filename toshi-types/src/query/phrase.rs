@@ -8,9 +8,9 @@ use crate::{error::Error, Result};
 
 /// A query for a phrase of terms, see [`tantivy::query::PhraseQuery`] for more info on what
 /// can be included here
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PhraseQuery {
-    phrase: KeyValue<String, TermPair>,
+    pub(crate) phrase: KeyValue<String, TermPair>,
 }
 
 impl PhraseQuery {
@@ -27,7 +27,7 @@ impl PhraseQuery {
 }
 
 /// The tokens used in the phrase query
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TermPair {
     terms: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
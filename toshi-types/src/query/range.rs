@@ -3,7 +3,7 @@ use std::ops::Bound;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{to_value, Value};
-use tantivy::query::{Query as TantivyQuery, RangeQuery as TantivyRangeQuery};
+use tantivy::query::{BoostQuery, Query as TantivyQuery, RangeQuery as TantivyRangeQuery};
 use tantivy::schema::{FieldType, Schema};
 
 use crate::query::{CreateQuery, KeyValue, Query};
@@ -14,7 +14,7 @@ use crate::{error::Error, Result};
 /// lte = less than or equal
 /// lt = less than
 /// gt = greater than
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Ranges {
     /// Possible range values, all are optional, but on parsing if enough do not exist the query
@@ -28,13 +28,13 @@ pub enum Ranges {
         lt: Option<Value>,
         /// Greater than
         gt: Option<Value>,
-        /// Currently does nothing and can be safely omitted
+        /// Multiplies the score of matching documents, only finite positive boosts are applied
         boost: Option<f32>,
     },
 }
 
 /// A query for a range of values, for example 1 through 10
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RangeQuery {
     pub(crate) range: KeyValue<String, Ranges>,
 }
@@ -154,21 +154,25 @@ where
 
 fn create_range_query(schema: &Schema, field: &str, r: Ranges) -> Result<Box<dyn TantivyQuery>> {
     match r {
-        Ranges::ValueRange { gte, lte, lt, gt, .. } => {
+        Ranges::ValueRange { gte, lte, lt, gt, boost } => {
             let field = schema
                 .get_field(field)
                 .ok_or_else(|| Error::QueryError(format!("Field {} does not exist", field)))?;
             let field_type = schema.get_field_entry(field).field_type();
-            match field_type {
+            let query: Box<dyn TantivyQuery> = match field_type {
                 &FieldType::I64(_) => {
                     let (upper, lower) = create_ranges::<i64>(gte, lte, lt, gt)?;
-                    Ok(Box::new(TantivyRangeQuery::new_i64_bounds(field, lower, upper)))
+                    Box::new(TantivyRangeQuery::new_i64_bounds(field, lower, upper))
                 }
                 &FieldType::U64(_) => {
                     let (upper, lower) = create_ranges::<u64>(gte, lte, lt, gt)?;
-                    Ok(Box::new(TantivyRangeQuery::new_u64_bounds(field, lower, upper)))
+                    Box::new(TantivyRangeQuery::new_u64_bounds(field, lower, upper))
                 }
-                ref ft => Err(Error::QueryError(format!("Invalid field type: {:?} for range query", ft))),
+                ref ft => return Err(Error::QueryError(format!("Invalid field type: {:?} for range query", ft))),
+            };
+            match boost {
+                Some(b) if b.is_finite() && b > 0.0 => Ok(Box::new(BoostQuery::new(query, b))),
+                _ => Ok(query),
             }
         }
     }
@@ -184,7 +188,7 @@ mod tests {
     fn test_deserialize_missing_ranges() {
         let body = r#"{ "range" : { "test_i64" : { "gte" : 2012 } } }"#;
         let req = serde_json::from_str::<RangeQuery>(body);
-        assert!(!req.is_err());
+        assert!(req.is_ok());
     }
 
     #[test]
@@ -225,7 +229,7 @@ mod tests {
         let built = schema.build();
         let req = serde_json::from_str::<RangeQuery>(body).unwrap().create_query(&built);
 
-        assert!(!req.is_err());
+        assert!(req.is_ok());
     }
 
     #[test]
@@ -6,9 +6,9 @@ use crate::query::{CreateQuery, KeyValue};
 use crate::{error::Error, Result};
 
 /// A search query based around a regular expression
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RegexQuery {
-    regex: KeyValue<String, String>,
+    pub(crate) regex: KeyValue<String, String>,
 }
 
 impl RegexQuery {
@@ -31,7 +31,8 @@ impl CreateQuery for RegexQuery {
         let field = schema
             .get_field(&field)
             .ok_or_else(|| Error::QueryError(format!("Field: {} does not exist", field)))?;
-        Ok(Box::new(TantivyRegexQuery::from_pattern(&value, field)?))
+        let query = TantivyRegexQuery::from_pattern(&value, field).map_err(|e| Error::QueryError(format!("Invalid regex: {}", e)))?;
+        Ok(Box::new(query))
     }
 }
 
@@ -58,7 +59,7 @@ mod tests {
         schema.add_u64_field("test_text", FAST);
         let phrase: RegexQuery = serde_json::from_str(body).unwrap();
         let query = phrase.create_query(&schema.build());
-        assert!(query.is_err());
+        assert!(matches!(query, Err(Error::QueryError(_))));
     }
 
     #[test]
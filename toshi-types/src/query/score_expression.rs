@@ -0,0 +1,446 @@
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Collector, ScoreSegmentTweaker, ScoreTweaker, TopDocs};
+use tantivy::schema::{Field, FieldType, Schema};
+use tantivy::{DocAddress, DocId, Score, SegmentReader};
+
+use crate::error::Error;
+use crate::query::agg::{numeric_fast_field, NumericColumn};
+use crate::Result;
+
+/// An arithmetic expression every matched document is rescored with, such as
+/// `_score * 0.7 + log(test_u64) * 0.3`.
+///
+/// Expressions are made of numbers, `_score` for the document's relevance and the names of numeric
+/// fast fields, combined with `+`, `-`, `*`, `/`, parentheses and the functions `log`, `log10`,
+/// `sqrt`, `abs`, `exp`, `min` and `max`. `_score` is Tantivy's relevance, BM25 parameters and
+/// function score decay aren't applied to it. Documents whose expression isn't a number rank last.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScoreExpression {
+    source: String,
+    expr: Expr,
+    /// The fields the expression reads, which [`Expr::Field`] refers to by position
+    fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Score,
+    Field(usize),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Log,
+    Log10,
+    Sqrt,
+    Abs,
+    Exp,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "log" => Some(Function::Log),
+            "log10" => Some(Function::Log10),
+            "sqrt" => Some(Function::Sqrt),
+            "abs" => Some(Function::Abs),
+            "exp" => Some(Function::Exp),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Function::Min | Function::Max => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, score: f64, values: &[f64]) -> f64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Score => score,
+            Expr::Field(i) => values[*i],
+            Expr::Neg(e) => -e.eval(score, values),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(score, values), rhs.eval(score, values));
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    _ => lhs / rhs,
+                }
+            }
+            Expr::Call(function, args) => {
+                let arg = args[0].eval(score, values);
+                match function {
+                    Function::Log => arg.ln(),
+                    Function::Log10 => arg.log10(),
+                    Function::Sqrt => arg.sqrt(),
+                    Function::Abs => arg.abs(),
+                    Function::Exp => arg.exp(),
+                    Function::Min => arg.min(args[1].eval(score, values)),
+                    Function::Max => arg.max(args[1].eval(score, values)),
+                }
+            }
+        }
+    }
+}
+
+impl ScoreExpression {
+    /// The expression as it was written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Check that every field the expression reads is a numeric fast field of the schema
+    pub fn validate_fields(&self, schema: &Schema) -> Result<()> {
+        self.resolve_fields(schema).map(|_| ())
+    }
+
+    fn resolve_fields(&self, schema: &Schema) -> Result<Vec<(Field, FieldType)>> {
+        self.fields.iter().map(|f| numeric_fast_field(schema, f)).collect()
+    }
+
+    /// A top docs collector that replaces the score of each matched document with the value of this
+    /// expression
+    pub fn top_docs(&self, schema: &Schema, limit: usize) -> Result<impl Collector<Fruit = Vec<(Score, DocAddress)>>> {
+        let tweaker = ExpressionTweaker {
+            fields: self.resolve_fields(schema)?,
+            expr: self.expr.clone(),
+        };
+        Ok(TopDocs::with_limit(limit).tweak_score(tweaker))
+    }
+}
+
+/// Opens the columns an expression reads for each segment, failing the search if one can't be
+/// opened rather than scoring with made up values
+struct ExpressionTweaker {
+    fields: Vec<(Field, FieldType)>,
+    expr: Expr,
+}
+
+struct ExpressionSegmentTweaker {
+    columns: Vec<NumericColumn>,
+    expr: Expr,
+    values: Vec<f64>,
+}
+
+impl ScoreTweaker<Score> for ExpressionTweaker {
+    type Child = ExpressionSegmentTweaker;
+
+    fn segment_tweaker(&self, segment: &SegmentReader) -> tantivy::Result<Self::Child> {
+        let columns = self
+            .fields
+            .iter()
+            .map(|(f, t)| NumericColumn::open(segment, *f, t))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        Ok(ExpressionSegmentTweaker {
+            values: vec![0.0; columns.len()],
+            columns,
+            expr: self.expr.clone(),
+        })
+    }
+}
+
+impl ScoreSegmentTweaker<Score> for ExpressionSegmentTweaker {
+    fn score(&mut self, doc: DocId, score: Score) -> Score {
+        for (value, column) in self.values.iter_mut().zip(&self.columns) {
+            *value = column.get(doc);
+        }
+        let score = self.expr.eval(f64::from(score), &self.values) as Score;
+        if score.is_nan() {
+            Score::NEG_INFINITY
+        } else {
+            score
+        }
+    }
+}
+
+impl FromStr for ScoreExpression {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        if source.len() > MAX_LENGTH {
+            return Err(invalid(
+                &source[..floor_char_boundary(source, 32)],
+                format!("expressions can't be longer than {} bytes", MAX_LENGTH),
+            ));
+        }
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?.into_iter().peekable(),
+            fields: Vec::new(),
+            depth: 0,
+        };
+        let expr = parser.expr()?;
+        if let Some(token) = parser.tokens.next() {
+            return Err(parser.error(format!("unexpected {:?}", token)));
+        }
+        Ok(Self {
+            source: source.into(),
+            expr,
+            fields: parser.fields,
+        })
+    }
+}
+
+impl TryFrom<String> for ScoreExpression {
+    type Error = Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        source.parse()
+    }
+}
+
+impl From<ScoreExpression> for String {
+    fn from(expr: ScoreExpression) -> Self {
+        expr.source
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+fn invalid(source: &str, reason: String) -> Error {
+    Error::QueryError(format!("Invalid score expression: '{}', {}", source, reason))
+}
+
+fn take_while(source: &str, chars: &mut Peekable<CharIndices>, start: usize, pred: impl Fn(char) -> bool) -> String {
+    let mut end = start;
+    while let Some((i, c)) = chars.peek().copied() {
+        if !pred(c) {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+    source[start..end].to_string()
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.peek().copied() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let number = take_while(source, &mut chars, start, |c| c.is_ascii_digit() || c == '.');
+            let n = number.parse().map_err(|_| invalid(source, format!("bad number {}", number)))?;
+            tokens.push(Token::Number(n));
+        } else if c.is_alphabetic() || c == '_' {
+            tokens.push(Token::Ident(take_while(source, &mut chars, start, |c| {
+                c.is_alphanumeric() || c == '_'
+            })));
+        } else {
+            chars.next();
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' => Token::Op(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' => Token::Comma,
+                _ => return Err(invalid(source, format!("unexpected '{}'", c))),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// The longest expression accepted, which also bounds how deep a chain of operators evaluates
+const MAX_LENGTH: usize = 1024;
+
+/// The most parentheses, function calls and negations an expression can nest, so parsing one
+/// can't overflow the stack
+const MAX_DEPTH: usize = 32;
+
+fn floor_char_boundary(source: &str, mut index: usize) -> usize {
+    index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// A recursive descent parser, where `*` and `/` bind tighter than `+` and `-`
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+    fields: Vec<String>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: String) -> Error {
+        invalid(self.source, reason)
+    }
+
+    /// Parse something nested one level deeper, failing once [`MAX_DEPTH`] is passed
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(format!("expressions can't be nested more than {} levels deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn next_op(&mut self, ops: &[char]) -> Option<char> {
+        match self.tokens.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.tokens.next();
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.next_op(&['+', '-']) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.next_op(&['*', '/']) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next_op(&['-']) {
+            Some(_) => Ok(Expr::Neg(Box::new(self.nested(Self::unary)?))),
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.tokens.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) if self.tokens.peek() == Some(&Token::Open) => {
+                self.tokens.next();
+                let function = Function::from_name(&name).ok_or_else(|| self.error(format!("unknown function {}", name)))?;
+                let mut args = vec![self.nested(Self::expr)?];
+                while self.tokens.next_if_eq(&Token::Comma).is_some() {
+                    args.push(self.nested(Self::expr)?);
+                }
+                self.close()?;
+                if args.len() != function.arity() {
+                    return Err(self.error(format!("{} takes {} argument(s)", name, function.arity())));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Some(Token::Ident(name)) if name == "_score" => Ok(Expr::Score),
+            Some(Token::Ident(name)) => {
+                let i = match self.fields.iter().position(|f| *f == name) {
+                    Some(i) => i,
+                    None => {
+                        self.fields.push(name);
+                        self.fields.len() - 1
+                    }
+                };
+                Ok(Expr::Field(i))
+            }
+            Some(Token::Open) => {
+                let expr = self.nested(Self::expr)?;
+                self.close()?;
+                Ok(expr)
+            }
+            Some(token) => Err(self.error(format!("unexpected {:?}", token))),
+            None => Err(self.error("unexpected end".into())),
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        match self.tokens.next() {
+            Some(Token::Close) => Ok(()),
+            _ => Err(self.error("missing ')'".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::*;
+
+    use super::*;
+
+    fn eval(source: &str, score: f64, values: &[f64]) -> f64 {
+        source.parse::<ScoreExpression>().unwrap().expr.eval(score, values)
+    }
+
+    #[test]
+    fn test_parse_and_eval() {
+        assert_eq!(eval("1 + 2 * 3", 0.0, &[]), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, &[]), 9.0);
+        assert_eq!(eval("-_score - 1", 2.0, &[]), -3.0);
+        assert_eq!(eval("max(test_u64, 4) / 2 + min(_score, test_u64)", 5.0, &[3.0]), 5.0);
+        assert_eq!(eval("_score * 0.7 + log(test_u64) * 0.3", 1.0, &[1.0]), 0.7);
+
+        let expr: ScoreExpression = serde_json::from_str(r#""test_u64 * test_i64 + test_u64""#).unwrap();
+        assert_eq!(expr.fields, vec!["test_u64".to_string(), "test_i64".to_string()]);
+        assert_eq!(serde_json::to_string(&expr).unwrap(), r#""test_u64 * test_i64 + test_u64""#);
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for source in &["", "1 +", "(1", "1 2", "pow(1, 2)", "max(1)", "1 % 2", "1..2"] {
+            assert!(source.parse::<ScoreExpression>().is_err(), "{} parsed", source);
+        }
+        assert_eq!(
+            "log(".parse::<ScoreExpression>().err().unwrap().to_string(),
+            "Error in query execution: 'Invalid score expression: 'log(', unexpected end'"
+        );
+
+        let nested = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(nested.parse::<ScoreExpression>().is_err());
+        let negated = format!("{}1", "-".repeat(33));
+        assert!(negated
+            .parse::<ScoreExpression>()
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("nested more than 32"));
+        let deep = format!("{}1{}", "(".repeat(32), ")".repeat(32));
+        assert!(deep.parse::<ScoreExpression>().is_ok());
+    }
+
+    #[test]
+    fn test_requires_fast_field() {
+        let mut builder = SchemaBuilder::new();
+        builder.add_u64_field("test_u64", STORED);
+        builder.add_u64_field("test_fast", FAST);
+        let schema = builder.build();
+        let expr: ScoreExpression = "_score * test_fast".parse().unwrap();
+        assert!(expr.validate_fields(&schema).is_ok());
+        let expr: ScoreExpression = "_score * test_u64".parse().unwrap();
+        assert!(expr.validate_fields(&schema).is_err());
+        assert!(expr.top_docs(&schema, 10).is_err());
+    }
+}
@@ -8,9 +8,9 @@ use crate::query::*;
 use crate::Result;
 
 /// An exact term to search for
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ExactTerm {
-    term: KeyValue<String, String>,
+    pub(crate) term: KeyValue<String, String>,
 }
 
 impl ExactTerm {
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt::Formatter;
-use tantivy::schema::Schema;
+use tantivy::schema::{FieldEntry, Schema, SchemaBuilder};
+
+use crate::{Bm25, Query};
 
 /// In a delete query, this is returned indicating the number of documents that were removed
 /// by the delete.
@@ -26,7 +29,7 @@ pub struct IndexOptions {
 pub struct AddDocument<D> {
     /// Options surrounding the insert [`IndexOptions`]
     pub options: Option<IndexOptions>,
-    /// The actual document to insert
+    /// The actual document to insert, Toshi also accepts an array of documents that all share the options
     pub document: D,
 }
 
@@ -37,10 +40,95 @@ impl<D> AddDocument<D> {
     }
 }
 
+/// Settings stored alongside an index that change how Toshi treats it, every setting is optional
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct IndexSettings {
+    /// A stored field whose value is returned as the `_id` of each document in search results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_field: Option<String>,
+    /// How terms of a raw query are combined when they're searched for in a field, fields without
+    /// an operator use the search's `conjunction_by_default`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_operators: BTreeMap<String, DefaultOperator>,
+    /// BM25 parameters searches are scored with unless a search sets its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bm25: Option<Bm25>,
+    /// Seconds between automatic commits of this index in place of the server's `auto_commit_duration`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_commit_duration: Option<f32>,
+    /// The most documents this index may hold, uncommitted operations are counted as documents
+    /// so the limit is approximate until the next commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_docs: Option<u64>,
+    /// Reject documents with fields that aren't in the schema in place of the server's `strict_mapping`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_mapping: Option<bool>,
+    /// The most bytes a term of an added document may have in place of the server's `max_term_length`,
+    /// 0 leaves terms unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_term_length: Option<usize>,
+    /// Cut longer terms short rather than rejecting their documents in place of the server's
+    /// `truncate_long_terms`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncate_long_terms: Option<bool>,
+    /// The most bytes of a stored text value returned with search results, longer values are cut
+    /// short and end in [`IndexSettings::TRUNCATION_MARKER`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_stored_field_bytes: Option<usize>,
+    /// Whether the index is frozen, only changed by freezing and thawing it so it stays frozen
+    /// after the index is closed or the server restarts
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+}
+
+impl IndexSettings {
+    /// Appended to stored values cut short by `max_stored_field_bytes`
+    pub const TRUNCATION_MARKER: &'static str = "...[truncated]";
+}
+
+/// The longest terms of added documents may be once their text is tokenized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermLimit {
+    /// The most bytes a term may have
+    pub max_length: usize,
+    /// Whether longer terms are cut short to `max_length` rather than rejecting their document
+    pub truncate: bool,
+}
+
+/// Whether a document needs to match every term of a raw query in a field or just one of them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultOperator {
+    /// Every term has to match
+    And,
+    /// Any term can match
+    Or,
+}
+
 /// A wrapper around Tantivy's schema for when an index is created. [`tantivy::schema::Schema`]
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Clone)]
 pub struct SchemaBody(pub Schema);
 
+impl<'de> Deserialize<'de> for SchemaBody {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Tantivy panics on a field name it has already seen, so duplicates are caught before the
+        // schema is built
+        let fields = Vec::<FieldEntry>::deserialize(deserializer)?;
+        let mut names = HashSet::with_capacity(fields.len());
+        let mut builder = SchemaBuilder::new();
+        for field in fields {
+            if !names.insert(field.name().to_string()) {
+                return Err(D::Error::custom(format!("Duplicate field: '{}' in schema", field.name())));
+            }
+            builder.add_field(field);
+        }
+        Ok(SchemaBody(builder.build()))
+    }
+}
+
 impl std::fmt::Debug for SchemaBody {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         f.write_str("Schema {\n")?;
@@ -62,6 +150,17 @@ pub struct DeleteDoc {
     pub terms: HashMap<String, String>,
 }
 
+/// The request body for deleting every document that matches a query
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DeleteQuery {
+    /// Options surrounding the delete [`IndexOptions`]
+    #[serde(default)]
+    pub options: Option<IndexOptions>,
+    /// The documents to delete, any query a search accepts can be used
+    pub query: Query,
+}
+
 #[cfg(test)]
 mod tests {
 